@@ -0,0 +1,21 @@
+use std::env;
+
+use mechtron::replay::replay;
+
+// replay a run recorded with Sources::record.  exits with an error naming the first content
+// that diverges, or quietly if every recorded cycle was reproduced
+//   replay <recorded dir> <replay dir>
+fn main() -> Result<(),Box<dyn std::error::Error>>{
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3
+    {
+        return Err("usage: replay <recorded dir> <replay dir>".into());
+    }
+
+    match replay( args[1].clone(), args[2].clone() )?
+    {
+        None => Ok(()),
+        Some(divergence) => Err(divergence.to_string().into())
+    }
+}
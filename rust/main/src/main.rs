@@ -1,19 +1,23 @@
 use mechtron::app::SYS;
-use mechtron_common::message::Message;
+use mechtron::scheduler::Run;
+use mechtron_common::artifact::Artifact;
 
 fn main() -> Result<(),Box<dyn std::error::Error>>{
 
-    let sim_id= SYS.net.id_seq.next();
-    let nucleus_id= SYS.net.id_seq.next();
-    SYS.local.sources.add( &sim_id );
-    let mut source = SYS.local.sources.get( &sim_id )?;
-    source.add_nuclues(nucleus_id);
+    let sim_artifact = Artifact::from("uberscott.com:examples:1.0.0:hello/hello-sim.yaml")?;
+    SYS.local.configs.sim_config_keeper.cache(&sim_artifact)?;
+    let sim_config = SYS.local.configs.sim_config_keeper.get(&sim_artifact)?;
 
-    let create_neutron_message = Message::new( &SYS.net.id_seq,
-                                                         );
-    source.messaging.cyclic_intake().intake(message);
+    let sim_id = SYS.local.sources.launch( sim_config )?;
+    let scheduler = SYS.local.sources.schedule( &sim_id, Run::UntilQuiescent )?;
+    scheduler.start()?;
+
+    // a run that had incidents fails with them rather than passing them by
+    let incidents: Vec<String> = SYS.local.sources.get( &sim_id )?.take_incidents()?.iter().map(|incident| incident.to_string() ).collect();
+    if !incidents.is_empty()
+    {
+        return Err(format!("the simulation had {} incidents:\n{}", incidents.len(), incidents.join("\n")).into());
+    }
 
     Ok(())
 }
-
-
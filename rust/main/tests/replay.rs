@@ -0,0 +1,36 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process;
+
+use mechtron::app::SYS;
+use mechtron::replay::replay;
+use mechtron::scheduler::Run;
+use mechtron_common::artifact::Artifact;
+
+// every sim issues ids from its own sequence, so the replay can run in the process that recorded
+#[test]
+fn a_recorded_run_replays_without_diverging() -> Result<(),Box<dyn Error>>
+{
+    let dir = env::temp_dir().join(format!("mechtron-replay-{}", process::id()));
+    let recorded = dir.join("recorded");
+    let replayed = dir.join("replayed");
+
+    let sim_artifact = Artifact::from("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml")?;
+    SYS.local.configs.sim_config_keeper.cache(&sim_artifact)?;
+    let sim_config = SYS.local.configs.sim_config_keeper.get(&sim_artifact)?;
+
+    let sim_id = SYS.local.sources.record( sim_config, recorded.to_str().unwrap().to_string() )?;
+    let scheduler = SYS.local.sources.schedule( &sim_id, Run::Cycles(4) )?;
+    scheduler.start()?;
+
+    let divergence = replay( recorded.to_str().unwrap().to_string(), replayed.to_str().unwrap().to_string() );
+    fs::remove_dir_all(&dir)?;
+
+    if let Option::Some(divergence) = divergence?
+    {
+        panic!("{}", divergence);
+    }
+
+    Ok(())
+}
@@ -15,18 +15,21 @@ use no_proto::buffer::NP_Buffer;
 use no_proto::error::NP_Error;
 use no_proto::NP_Factory;
 use no_proto::pointer::{NP_Scalar, NP_Value};
-use wasmer::{CompileError, Cranelift, JIT, Module, Store};
+use wasmer::Module;
 
-use mechtron_common::artifact::{Artifact, ArtifactCacher};
+use mechtron_common::artifact::{Artifact, ArtifactCache, ArtifactCacher};
 use mechtron_common::buffers::BufferFactories;
-use mechtron_common::configs::{Configs, Keeper, MechtronConfig, MechtronConfigYaml, Parser, SimConfig};
+use mechtron_common::configs::{Configs, MechtronConfig, MechtronConfigYaml, SimConfig};
 use mechtron_common::message::Message;
 
 use crate::content::{InterCyclicContentStructure, TronKey};
+use crate::limits::compile;
 use crate::message::{MessageIntake, MessageRouter};
 use crate::nucleus::NucleiStore;
 use crate::repository::FileSystemArtifactRepository;
+use crate::scheduler::{Run, Scheduler, SchedulerState};
 use crate::source::Source;
+use crate::store::ContentStore;
 use mechtron_common::id::{IdSeq, Id};
 
 lazy_static! {
@@ -60,9 +63,8 @@ impl System
 
 pub struct Local
 {
-    pub wasm_store: Arc<Store>,
     pub configs: Configs,
-    pub wasm_module_keeper: Keeper<Module>,
+    pub wasm_modules: WasmModules,
     pub sources: Sources
 }
 
@@ -70,12 +72,10 @@ impl Local {
     fn new() -> Self
     {
         let repo = Arc::new(FileSystemArtifactRepository::new("../../repo/".to_string()));
-        let wasm_store =Arc::new(Store::new(&JIT::new(Cranelift::default()).engine()));
 
         Local {
-            wasm_store: wasm_store.clone(),
             configs: Configs::new(repo.clone()),
-            wasm_module_keeper: Keeper::new(repo.clone(), Box::new(WasmModuleParser { wasm_store: wasm_store.clone() })),
+            wasm_modules: WasmModules::new(repo.clone()),
             sources: Sources::new()
         }
     }
@@ -97,43 +97,82 @@ impl Network
 }
 
 
-struct WasmModuleParser
+// the wasm of a mechtron is compiled for the limits of its mechtron config, so mechtrons that
+// share a wasm artifact but not a memory limit each get a module of their own
+pub struct WasmModules
 {
-    wasm_store: Arc<Store>
+    repo: Arc<dyn ArtifactCache+Send+Sync>,
+    modules: RwLock<HashMap<(Artifact,u32),Arc<Module>>>
 }
 
-impl Parser<Module> for WasmModuleParser
+impl WasmModules
 {
-    fn parse(&self, artifact: &Artifact, str: &str) -> Result<Module, Box<dyn Error>> {
-        let result = Module::new(&self.wasm_store, str);
-        match result {
-            Ok(module) => Ok(module),
-            Err(e) => { Err(e.into()) }
+    fn new( repo: Arc<dyn ArtifactCache+Send+Sync> ) -> Self
+    {
+        WasmModules {
+            repo: repo,
+            modules: RwLock::new(HashMap::new())
+        }
+    }
+
+    pub fn get( &self, mechtron_config: &MechtronConfig ) -> Result<Arc<Module>,Box<dyn Error + '_>>
+    {
+        let key = (mechtron_config.wasm.clone(), mechtron_config.limits.memory_pages);
+        if let Option::Some(module) = self.modules.read()?.get(&key)
+        {
+            return Ok(module.clone());
         }
+
+        let module = Arc::new(compile(self.repo.load(&mechtron_config.wasm)?.as_slice(), &mechtron_config.limits)?);
+        self.modules.write()?.insert(key, module.clone());
+        Ok(module)
     }
 }
 
 
-struct LocalMessageRouter
-{}
+// routes messages to the messaging structure of the nucleus they are addressed to.  every
+// nucleus in this process must be registered before messages can be sent to it
+pub struct LocalMessageRouter
+{
+    nuclei: RwLock<HashMap<Id,Arc<dyn MessageIntake>>>
+}
 
 impl LocalMessageRouter
 {
     pub fn new() -> Self {
-        LocalMessageRouter {}
+        LocalMessageRouter {
+            nuclei: RwLock::new(HashMap::new())
+        }
+    }
+
+    pub fn register(&self, nucleus_id: Id, intake: Arc<dyn MessageIntake>) -> Result<(), Box<dyn Error + '_>>
+    {
+        let mut nuclei = self.nuclei.write()?;
+        nuclei.insert(nucleus_id, intake);
+        Ok(())
     }
 }
 
 impl MessageRouter for LocalMessageRouter
 {
-    fn send(&self, messages: Vec<Message>) {
-        unimplemented!()
+    fn send(&self, messages: Vec<Message>) -> Result<(), Box<dyn Error + '_>> {
+        let nuclei = self.nuclei.read()?;
+        for message in messages
+        {
+            match nuclei.get(&message.to.tron.nucleus_id)
+            {
+                None => return Err(format!("nucleus {:?} is not known to this router, cannot deliver message {:?}", message.to.tron.nucleus_id, message.id).into()),
+                Some(intake) => intake.intake(message)?
+            }
+        }
+        Ok(())
     }
 }
 
 struct Sources
 {
-    sources: RwLock<HashMap<Id,Arc<Source>>>
+    sources: RwLock<HashMap<Id,Arc<Source>>>,
+    schedulers: RwLock<HashMap<Id,Arc<Scheduler>>>
 }
 
 
@@ -142,15 +181,91 @@ impl Sources
     pub fn new()->Self
     {
         Sources{
-            sources: RwLock::new(HashMap::new() )
+            sources: RwLock::new(HashMap::new() ),
+            schedulers: RwLock::new(HashMap::new() )
         }
     }
 
-    pub fn launch( &mut self, sim_config: Arc<SimConfig> )->Result<(),Box<dyn Error + '_>>
+    pub fn launch( &self, sim_config: Arc<SimConfig> )->Result<Id,Box<dyn Error + '_>>
     {
-        let mut source = Source::launch(sime_config)?;
-        sources.insert( source.id().clone(), Arc::new(source ));
-        Ok(())
+        let source = Source::launch(sim_config, Option::None)?;
+        self.add(source)
+    }
+
+    // launch a sim whose content history is persisted to the given store
+    pub fn launch_with_store( &self, sim_config: Arc<SimConfig>, store: Arc<dyn ContentStore> )->Result<Id,Box<dyn Error + '_>>
+    {
+        let source = Source::launch(sim_config, Option::Some(store))?;
+        self.add(source)
+    }
+
+    // resume a sim from the last revision committed to the given store
+    pub fn restore( &self, sim_config: Arc<SimConfig>, store: Arc<dyn ContentStore> )->Result<Id,Box<dyn Error + '_>>
+    {
+        let source = Source::restore(sim_config, store)?;
+        self.add(source)
+    }
+
+    // launch a sim that writes a checkpoint to 'dir' at the end of every cycle
+    pub fn launch_with_checkpoints( &self, sim_config: Arc<SimConfig>, dir: String )->Result<Id,Box<dyn Error + '_>>
+    {
+        let source = Source::launch_with_checkpoints(sim_config, dir)?;
+        self.add(source)
+    }
+
+    // launch a deterministic sim that records its content and messages to 'dir' for replay
+    pub fn record( &self, sim_config: Arc<SimConfig>, dir: String )->Result<Id,Box<dyn Error + '_>>
+    {
+        let source = Source::launch_recorded(sim_config, dir)?;
+        self.add(source)
+    }
+
+    // continue a sim from the last checkpoint written to 'dir'
+    pub fn resume( &self, dir: String )->Result<Id,Box<dyn Error + '_>>
+    {
+        let source = Source::resume(dir)?;
+        self.add(source)
+    }
+
+    fn add( &self, source: Source )->Result<Id,Box<dyn Error + '_>>
+    {
+        let sim_id = source.id().clone();
+        let mut sources = self.sources.write()?;
+        if sources.contains_key(&sim_id)
+        {
+            return Err(format!("sim id {:?} is already present in the sources",sim_id).into());
+        }
+        sources.insert( sim_id.clone(), Arc::new(source ));
+        Ok(sim_id)
+    }
+
+    // create the scheduler that will drive the given sim, start() must still be called on it.
+    // a scheduler that is not in the middle of a run gives up its place to the new one
+    pub fn schedule( &self, sim_id: &Id, run: Run ) -> Result<Arc<Scheduler>,Box<dyn Error+'_>>
+    {
+        self.get(sim_id)?;
+        let mut schedulers = self.schedulers.write()?;
+        if let Option::Some(scheduler) = schedulers.get(sim_id)
+        {
+            let state = scheduler.state()?;
+            if state != SchedulerState::Idle
+            {
+                return Err(format!("sim id {:?} already has a scheduler that is {:?}",sim_id,state).into());
+            }
+        }
+        let scheduler = Arc::new(Scheduler::new(sim_id.clone(), run)?);
+        schedulers.insert( sim_id.clone(), scheduler.clone() );
+        Ok(scheduler)
+    }
+
+    pub fn scheduler( &self, sim_id: &Id ) -> Result<Arc<Scheduler>,Box<dyn Error+'_>>
+    {
+        let schedulers = self.schedulers.read()?;
+        match schedulers.get(sim_id)
+        {
+            None => Err(format!("sim id {:?} does not have a scheduler",sim_id).into()),
+            Some(scheduler) => Ok(scheduler.clone())
+        }
     }
 
     pub fn get( &self, sim_id: &Id ) -> Result<Arc<Source>,Box<dyn Error+'_>>
@@ -165,3 +280,81 @@ impl Sources
         return Ok(source.clone());
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+
+    use mechtron_common::id::{Id, IdSeq, TronKey};
+    use mechtron_common::message::{Cycle, From, InterDeliveryType, Message, MessageKind, To};
+
+    use crate::app::LocalMessageRouter;
+    use crate::message::{MessageIntake, MessageRouter};
+
+    struct Intake
+    {
+        messages: Mutex<Vec<Message>>
+    }
+
+    impl Intake
+    {
+        fn new()->Arc<Self>
+        {
+            Arc::new(Intake{ messages: Mutex::new(vec!()) })
+        }
+
+        fn len(&self)->usize
+        {
+            self.messages.lock().unwrap().len()
+        }
+    }
+
+    impl MessageIntake for Intake
+    {
+        fn intake(&self, message: Message) -> Result<(),Box<dyn Error>>
+        {
+            self.messages.lock().unwrap().push(message);
+            Ok(())
+        }
+    }
+
+    fn message( nucleus_id: &Id )->Message
+    {
+        let from = From{ tron: TronKey::new(Id::new(0,1), Id::new(0,2)), cycle: 0, timestamp: 0 };
+        let to = To{
+            tron: TronKey::new(nucleus_id.clone(), Id::new(0,3)),
+            port: "port".to_string(),
+            cycle: Cycle::Next,
+            phase: 0,
+            inter_delivery_type: InterDeliveryType::Cyclic
+        };
+        Message::longform(&IdSeq::new(0), MessageKind::Update, from, to, vec!(), Option::None, Option::None)
+    }
+
+    #[test]
+    fn messages_are_routed_to_the_nucleus_they_are_addressed_to()
+    {
+        let left_id = Id::new(0,10);
+        let right_id = Id::new(0,20);
+        let left = Intake::new();
+        let right = Intake::new();
+
+        let router = LocalMessageRouter::new();
+        router.register(left_id.clone(), left.clone()).unwrap();
+        router.register(right_id.clone(), right.clone()).unwrap();
+        router.send(vec!(message(&left_id), message(&right_id), message(&left_id))).unwrap();
+
+        assert_eq!(left.len(), 2);
+        assert_eq!(right.len(), 1);
+    }
+
+    #[test]
+    fn a_nucleus_that_is_not_registered_is_an_error()
+    {
+        let router = LocalMessageRouter::new();
+        router.register(Id::new(0,10), Intake::new()).unwrap();
+        assert!(router.send(vec!(message(&Id::new(0,20)))).is_err());
+    }
+}
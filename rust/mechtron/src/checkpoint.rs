@@ -0,0 +1,199 @@
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use bytes::Bytes;
+
+use mechtron_common::artifact::Artifact;
+use mechtron_common::configs::Configs;
+use mechtron_common::id::{Id, IdSeq, Revision};
+use mechtron_common::message::Message;
+
+use crate::store::{read_section, write_section};
+
+static CHECKPOINT_FILE: &'static str = "checkpoint";
+
+// everything needed to continue a Source from a cycle boundary other than its content, which
+// is kept in the content store alongside the checkpoint.  pending messages include both the
+// messages still in the pipeline and those waiting in a chamber for a future cycle
+pub struct Checkpoint
+{
+    pub sim_config: Artifact,
+    pub sim_id: Id,
+    pub head: Revision,
+    pub id_seq: i64,
+    pub next_id: i64,
+    pub pending: Vec<Message>,
+    pub transactions: Vec<Message>
+}
+
+impl Checkpoint
+{
+    pub fn path( dir: &str )->PathBuf
+    {
+        PathBuf::from(dir).join(CHECKPOINT_FILE)
+    }
+
+    pub fn exists( dir: &str )->bool
+    {
+        Checkpoint::path(dir).exists()
+    }
+
+    pub fn write( &self, dir: &str )->Result<(),Box<dyn Error>>
+    {
+        let header = format!("{}\n{} {}\n{}\n{} {}\n{} {}",
+                             self.sim_config.to(),
+                             self.sim_id.seq_id, self.sim_id.id,
+                             self.head.cycle,
+                             self.id_seq, self.next_id,
+                             self.pending.len(), self.transactions.len() );
+
+        let mut bytes = vec!();
+        write_section(&mut bytes, header.as_bytes());
+        write_section(&mut bytes, &messages_to_bytes(&self.pending)?);
+        write_section(&mut bytes, &messages_to_bytes(&self.transactions)?);
+
+        // the previous checkpoint is only replaced once the new one is completely written
+        let path = Checkpoint::path(dir);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn read( dir: &str, seq: &IdSeq, configs: &Configs )->Result<Self,Box<dyn Error>>
+    {
+        let mut file = File::open(Checkpoint::path(dir))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut offset = 0;
+        let header = String::from_utf8(read_section(&bytes, &mut offset)?)?;
+        let pending = read_section(&bytes, &mut offset)?;
+        let transactions = read_section(&bytes, &mut offset)?;
+
+        let lines: Vec<&str> = header.lines().collect();
+        if lines.len() != 5
+        {
+            return Err(format!("checkpoint in {} has a malformed header",dir).into());
+        }
+        let sim_id = pair(lines[1])?;
+        let id_seq = pair(lines[3])?;
+        let counts = pair(lines[4])?;
+
+        Ok(Checkpoint{
+            sim_config: Artifact::from(lines[0])?,
+            sim_id: Id::new(sim_id.0, sim_id.1),
+            head: Revision{ cycle: lines[2].parse()? },
+            id_seq: id_seq.0,
+            next_id: id_seq.1,
+            pending: messages_from_bytes(seq, configs, pending, counts.0)?,
+            transactions: messages_from_bytes(seq, configs, transactions, counts.1)?
+        })
+    }
+}
+
+fn pair( line: &str )->Result<(i64,i64),Box<dyn Error>>
+{
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 2
+    {
+        return Err(format!("checkpoint header line '{}' should contain two numbers",line).into());
+    }
+    Ok((parts[0].parse()?,parts[1].parse()?))
+}
+
+pub fn messages_to_bytes( messages: &Vec<Message> )->Result<Vec<u8>,Box<dyn Error>>
+{
+    if messages.is_empty()
+    {
+        return Ok(vec!());
+    }
+    let messages: Vec<&Message> = messages.iter().collect();
+    Ok(Message::messages_to_buffer(&messages)?.finish().bytes())
+}
+
+pub fn messages_from_bytes( seq: &IdSeq, configs: &Configs, bytes: Vec<u8>, count: i64 )->Result<Vec<Message>,Box<dyn Error>>
+{
+    if count == 0
+    {
+        return Ok(vec!());
+    }
+    let messages = Message::messages_from_bytes(seq, configs, &Bytes::from(bytes))?;
+    if messages.len() as i64 != count
+    {
+        return Err(format!("expected {} messages but found {}",count,messages.len()).into());
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+    use std::fs;
+
+    use mechtron_common::artifact::Artifact;
+    use mechtron_common::id::{Id, IdSeq, Revision, TronKey};
+    use mechtron_common::message::{From, Message, MessageKind, To};
+
+    use crate::app::SYS;
+    use crate::checkpoint::Checkpoint;
+
+    fn message( seq: &IdSeq, kind: MessageKind )->Message
+    {
+        let from = From{ tron: TronKey::new(Id::new(0,1), Id::new(0,2)), cycle: 3, timestamp: 3 };
+        let to = To::basic(TronKey::new(Id::new(0,1), Id::new(0,4)), "port".to_string());
+        Message::longform(seq, kind, from, to, vec!(), Option::None, Option::None)
+    }
+
+    #[test]
+    fn round_trip() -> Result<(),Box<dyn Error>>
+    {
+        let dir = std::env::temp_dir().join(format!("mechtron-checkpoint-{}", std::process::id()));
+        let dir = dir.to_str().unwrap().to_string();
+        fs::create_dir_all(&dir)?;
+
+        let seq = IdSeq::new(0);
+        let pending = message(&seq, MessageKind::Update);
+        let mut request = message(&seq, MessageKind::Request);
+        request.transaction = Option::Some(request.id.clone());
+        request.response_port = Option::Some("responses".to_string());
+
+        let checkpoint = Checkpoint{
+            sim_config: Artifact::from("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml")?,
+            sim_id: Id::new(0,7),
+            head: Revision{ cycle: 3 },
+            id_seq: 0,
+            next_id: seq.peek(),
+            pending: vec!(pending.clone()),
+            transactions: vec!(request.clone())
+        };
+        checkpoint.write(&dir)?;
+        assert!(Checkpoint::exists(&dir));
+
+        let read = Checkpoint::read(&dir, &seq, &SYS.local.configs)?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(read.sim_config, checkpoint.sim_config);
+        assert_eq!(read.sim_id, checkpoint.sim_id);
+        assert_eq!(read.head, checkpoint.head);
+        assert_eq!(read.id_seq, checkpoint.id_seq);
+        assert_eq!(read.next_id, checkpoint.next_id);
+
+        assert_eq!(read.pending.len(), 1);
+        assert_eq!(read.pending[0].id, pending.id);
+        assert_eq!(read.pending[0].to.tron, pending.to.tron);
+
+        assert_eq!(read.transactions.len(), 1);
+        assert_eq!(read.transactions[0].id, request.id);
+        assert_eq!(read.transactions[0].transaction, request.transaction);
+        assert_eq!(read.transactions[0].response_port, request.response_port);
+
+        Ok(())
+    }
+}
@@ -5,11 +5,15 @@ use std::error::Error;
 use no_proto::memory::NP_Memory_Owned;
 use mechtron_common::id::{RevisionKey, TronKey, Revision, ContentKey, Id};
 use mechtron_common::content::{Content, ReadOnlyContent};
-use mechtron_common::configs::Configs;
+use mechtron_common::configs::{Configs, ContentRetention};
+use crate::app::SYS;
+use crate::diff::ContentDiff;
+use crate::store::ContentStore;
 
 
 pub struct InterCyclicContentStructure {
-    history: RwLock<HashMap<Id,HashMap<TronKey,RwLock<ContentHistory>>>>
+    history: RwLock<HashMap<Id,HashMap<TronKey,RwLock<ContentHistory>>>>,
+    store: Option<Arc<dyn ContentStore>>
 }
 
 
@@ -17,10 +21,63 @@ impl InterCyclicContentStructure
 {
    pub fn new() -> Self{
        InterCyclicContentStructure {
-           history: RwLock::new(HashMap::new())
+           history: RwLock::new(HashMap::new()),
+           store: Option::None
        }
    }
 
+   // content is written through to the store and only the most recent revision of each
+   // tron is kept in memory
+   pub fn with_store( store: Arc<dyn ContentStore> ) -> Self{
+       InterCyclicContentStructure {
+           history: RwLock::new(HashMap::new()),
+           store: Option::Some(store)
+       }
+   }
+
+   // load the content of the given revision from the store so the next cycle can be revised from it
+   pub fn restore(&self, revision: &Revision, configs: &Configs ) -> Result<(),Box<dyn Error>>
+   {
+       if self.store.is_none()
+       {
+           return Err("cannot restore content without a content store".into());
+       }
+       let store = self.store.as_ref().unwrap();
+
+       let mut history = self.history.write()?;
+       for key in store.keys(revision)?
+       {
+           let content = match store.get(&key, configs)?
+           {
+               None => return Err(format!("content store is missing content for key {:?}",key).into()),
+               Some(content) => content
+           };
+
+           if !history.contains_key(&key.tron_id.nucleus_id )
+           {
+               history.insert( key.tron_id.nucleus_id.clone(), HashMap::new() );
+           }
+           let history = history.get_mut(&key.tron_id.nucleus_id ).unwrap();
+           if !history.contains_key(&key.tron_id )
+           {
+               history.insert( key.tron_id.clone(), RwLock::new(ContentHistory::new(key.tron_id.clone() ) ) );
+           }
+           let mut history = history.get(&key.tron_id ).unwrap().write()?;
+           ReadOnlyContentIntake::intake(&mut *history, content, key)?;
+       }
+       Ok(())
+   }
+
+   // mark the revision as complete in the store (if there is one)
+   pub fn commit(&self, sim_id: &Id, revision: &Revision ) -> Result<(),Box<dyn Error>>
+   {
+       if let Option::Some(store) = &self.store
+       {
+           store.commit(sim_id,revision)?;
+       }
+       Ok(())
+   }
+
    fn get(&self, key:&ContentKey) -> Result<&ReadOnlyContent,Box<dyn Error>>
    {
        let history = self.history.read()?;
@@ -41,6 +98,38 @@ impl InterCyclicContentStructure
        return history.get(&key.revision);
    }
 
+   // a copy of the content for the key from memory, or from the store if it is no longer held in memory
+   fn fetch(&self, key:&ContentKey, configs: &Configs ) -> Result<ReadOnlyContent,Box<dyn Error>>
+   {
+       if let Ok(content) = self.get(key)
+       {
+           return Ok(content.copy()?.read_only()?);
+       }
+       match &self.store
+       {
+           None => Err(format!("content for key {:?} is not available",key).into()),
+           Some(store) => match store.get(key, configs)?
+           {
+               None => Err(format!("content for key {:?} is not in memory or the content store",key).into()),
+               Some(content) => Ok(content)
+           }
+       }
+   }
+
+   // a field by field comparison of a tron's content between two revisions
+   pub fn diff(&self, tron_key: &TronKey, from: &Revision, to: &Revision, configs: &Configs ) -> Result<ContentDiff,Box<dyn Error>>
+   {
+       let from_content = self.fetch(&ContentKey{ tron_id: tron_key.clone(), revision: from.clone() }, configs)?;
+       let to_content = self.fetch(&ContentKey{ tron_id: tron_key.clone(), revision: to.clone() }, configs)?;
+       ContentDiff::new(tron_key.clone(), (from, &from_content), (to, &to_content), configs)
+   }
+
+   // the content of a tron at a revision rendered as json
+   pub fn export(&self, key: &ContentKey, configs: &Configs ) -> Result<String,Box<dyn Error>>
+   {
+       Ok(self.fetch(key, configs)?.to_json()?.stringify())
+   }
+
    fn on_each_history( &self, func: fn( history: &ContentHistory ))->Result<(),Box<dyn Error>>
    {
        let history = self.history.read()?;
@@ -57,6 +146,34 @@ impl InterCyclicContentStructure
        Ok(())
    }
 
+   // apply the retention policy to the history of every tron.  when backed by a store the
+   // policy is applied to the store and memory is reduced to the most recent revision
+   pub fn prune(&self, retention: &ContentRetention ) ->Result<(),Box<dyn Error>>
+   {
+       let history = self.history.read()?;
+       for nucleus_id in history.keys() {
+           let history = history.get(&nucleus_id ).unwrap();
+
+           for key in history.keys()
+           {
+               let mut history = history.get(key).unwrap().write()?;
+               match &self.store
+               {
+                   None => history.prune(retention),
+                   Some(store) => {
+                       if let Option::Some(last) = history.last
+                       {
+                           store.prune(key, retention, &Revision{ cycle: last })?;
+                       }
+                       history.prune(&ContentRetention::Last(1));
+                   }
+               }
+           }
+       }
+
+       Ok(())
+   }
+
    // query for all nucleus in given revision
    pub fn query_nuclei(&self, revision: &Revision ) ->Result<HashSet<Id>,Box<dyn Error>>
    {
@@ -85,19 +202,15 @@ impl InterCyclicContentStructure
 
         let history = self.history.read()?;
 
-        if history.contains_key(nucleus_id)
+        if let Option::Some(history) = history.get(nucleus_id)
         {
-            for nucleus_id in history.keys()
+            for tron_key in  history.keys()
             {
-                let history = history.get(nucleus_id).unwrap();
-                for tron_key in  history.keys()
+                let history = history.get(tron_key).unwrap().read()?;
+                if( history.contains(revision) )
                 {
-                    let history = history.get(tron_key).unwrap().read()?;
-                    if( history.contains(revision) )
-                    {
-                        let content = history.get(revision)?;
-                        rtn.push((content.read_only(configs)?,ContentKey{tron_id:tron_key.clone(),revision:revision.clone()}) )
-                    }
+                    let content = history.get(revision)?;
+                    rtn.push((content.read_only(configs)?,ContentKey{tron_id:tron_key.clone(),revision:revision.clone()}) )
                 }
             }
         }
@@ -106,9 +219,11 @@ impl InterCyclicContentStructure
     }
 }
 
-impl ContentIntake for InterCyclicContentStructure
+impl InterCyclicContentStructure
 {
-    fn intake(&mut self,content: Content, key: ContentKey) -> Result<(), Box<dyn Error+'_>> {
+    // the histories are locked internally so content can be taken in while the source is
+    // shared with the trons reading from it
+    pub fn put(&self, content: Content, key: ContentKey) -> Result<(), Box<dyn Error+'_>> {
         let mut history = self.history.write()?;
         if !history.contains_key(&key.tron_id.nucleus_id )
         {
@@ -124,21 +239,47 @@ impl ContentIntake for InterCyclicContentStructure
         let mut history = history.get( &key.tron_id ).unwrap();
         let mut history = history.write()?;
 
+        if let Option::Some(store) = &self.store
+        {
+            store.put(&key, &content.read_only()?)?;
+        }
+
         history.intake(content,key)?;
 
         Ok(())
     }
 }
 
+impl ContentIntake for InterCyclicContentStructure
+{
+    fn intake(&mut self,content: Content, key: ContentKey) -> Result<(), Box<dyn Error+'_>> {
+        self.put(content, key)
+    }
+}
+
 impl ContentRetrieval for InterCyclicContentStructure {
 
-    fn read_only(&self, key: &ContentKey, configs: &Configs ) -> Result<&ReadOnlyContent, Box<dyn Error>> {
-        let rtn = self.get(key)?;
-        Ok(rtn)
+    // memory only holds the most recent revision when there is a store, earlier revisions
+    // the retention policy kept are read back from the store the same way copy() does
+    fn read_only(&self, key: &ContentKey, configs: &Configs ) -> Result<ReadOnlyContent, Box<dyn Error>> {
+        self.fetch(key, configs)
     }
 
     fn copy(&self, revision: &ContentKey) -> Result<Content, Box<dyn Error+'_>> {
 
+        // revisions no longer held in memory may still be in the store
+        if let Option::Some(store) = &self.store
+        {
+            if self.get(key).is_err()
+            {
+                return match store.get(key, &SYS.local.configs)?
+                {
+                    None => Err(format!("content for key {:?} is not in the content store",key).into()),
+                    Some(content) => Ok(content.copy()?)
+                };
+            }
+        }
+
         let rtn = self.get(key)?;
         let rtn = rtn.copy()?;
         Ok(rtn)
@@ -162,13 +303,13 @@ pub trait ReadOnlyContentAccess
 
 pub trait ContentAccess
 {
-    fn get( &mut self, key: &ContentKey )->Result<&Content,Box<dyn Error+'_>>;
+    fn get( &mut self, key: &ContentKey )->Result<&mut Content,Box<dyn Error+'_>>;
 }
 
 
 pub trait ContentRetrieval
 {
-    fn read_only( &self, key: &ContentKey, configs: &Configs )->Result<&ReadOnlyContent,Box<dyn Error+'_>>;
+    fn read_only( &self, key: &ContentKey, configs: &Configs )->Result<ReadOnlyContent,Box<dyn Error+'_>>;
     fn copy( &self, key: &ContentKey )->Result<Content,Box<dyn Error+'_>>;
 }
 
@@ -176,14 +317,18 @@ pub trait ContentRetrieval
 pub struct ContentHistory
 {
     key: TronKey,
-    content: HashMap<i64,ReadOnlyContent>
+    content: HashMap<i64,ReadOnlyContent>,
+    first: Option<i64>,
+    last: Option<i64>
 }
 
 impl ContentHistory {
     pub fn new(key: TronKey) ->Self{
         ContentHistory{
             key: key,
-            content: HashMap::new()
+            content: HashMap::new(),
+            first: Option::None,
+            last: Option::None
         }
     }
 
@@ -191,11 +336,39 @@ impl ContentHistory {
     {
         match self.content.get(&revision.cycle )
         {
-            None => Err(format!("could not find history for cycle {}", key.revision.cycle ).into()),
+            None => {
+                if self.is_pruned(revision)
+                {
+                    return Err(format!("revision pruned: content for tron {:?} at cycle {} has been removed by the retention policy", self.key, revision.cycle ).into());
+                }
+                Err(format!("could not find history for cycle {}", revision.cycle ).into())
+            },
             Some(content) => Ok(content)
         }
     }
 
+    // content is intaken for every cycle from a tron's creation onward, so a gap
+    // between the first and last cycles must have been pruned
+    pub fn is_pruned( &self, revision: &Revision )->bool
+    {
+        if self.first.is_none() || self.contains(revision)
+        {
+            return false;
+        }
+        revision.cycle >= self.first.unwrap() && revision.cycle <= self.last.unwrap()
+    }
+
+    // the most recent revision is always retained since the next cycle is revised from it
+    pub fn prune( &mut self, retention: &ContentRetention )
+    {
+        if self.last.is_none()
+        {
+            return;
+        }
+        let last = self.last.unwrap();
+        self.content.retain(|cycle,_| retention.retains(*cycle, last) );
+    }
+
     pub fn contains( &self, revision: &Revision  )->bool
     {
         return self.content.contains_key(&revision.cycle);
@@ -206,7 +379,22 @@ impl ContentHistory {
 impl ContentIntake for ContentHistory
 {
     fn intake(&mut self, content: Content,  key:ContentKey ) -> Result<(), Box<dyn Error>> {
-       self.content.insert(key.revision.cycle.clone(),content.read_only()? );
+       ReadOnlyContentIntake::intake(self, content.read_only()?, key )
+    }
+}
+
+impl ReadOnlyContentIntake for ContentHistory
+{
+    fn intake(&mut self, content: ReadOnlyContent,  key:ContentKey ) -> Result<(), Box<dyn Error>> {
+       self.content.insert(key.revision.cycle.clone(),content );
+       if self.first.is_none() || key.revision.cycle < self.first.unwrap()
+       {
+           self.first = Option::Some(key.revision.cycle);
+       }
+       if self.last.is_none() || key.revision.cycle > self.last.unwrap()
+       {
+           self.last = Option::Some(key.revision.cycle);
+       }
        Ok(())
     }
 }
@@ -220,11 +408,6 @@ pub struct IntraCyclicContentStructure
 
 impl IntraCyclicContentStructure
 {
-    pub fn intake( &mut self, content: ReadOnlyContent, key: ContentKey )
-    {
-        self.read_only_store.insert(key.tron_id.tron_id, content );
-    }
-
     pub fn new( revision: Revision )->Self
     {
         IntraCyclicContentStructure{
@@ -234,7 +417,63 @@ impl IntraCyclicContentStructure
         }
     }
 
+    // content for a tron that was created during this cycle
+    pub fn create( &mut self, key: ContentKey, content: Content )->Result<(),Box<dyn Error>>
+    {
+        if self.store.contains_key(&key.tron_id.tron_id) || self.read_only_store.contains_key(&key.tron_id.tron_id)
+        {
+            return Err(format!("content for tron {:?} already exists",key.tron_id).into());
+        }
+        self.store.insert(key.tron_id.tron_id.clone(), content );
+        Ok(())
+    }
+
+    // a destroyed tron is not drained so no content is kept for it from this revision on
+    pub fn destroy( &mut self, tron_id: &Id )
+    {
+        self.store.remove(tron_id);
+        self.read_only_store.remove(tron_id);
+    }
+
+    pub fn contains( &self, tron_id: &Id )->bool
+    {
+        self.store.contains_key(tron_id) || self.read_only_store.contains_key(tron_id)
+    }
+
+    pub fn tron_ids( &self )->Vec<Id>
+    {
+        let mut rtn: HashSet<Id> = HashSet::new();
+        for tron_id in self.read_only_store.keys()
+        {
+            rtn.insert(tron_id.clone());
+        }
+        for tron_id in self.store.keys()
+        {
+            rtn.insert(tron_id.clone());
+        }
+        let mut rtn: Vec<Id> = rtn.into_iter().collect();
+        rtn.sort();
+        rtn
+    }
 
+    // remove all content from this structure keyed to this structure's revision.  content
+    // that was never modified in this cycle is carried forward unchanged
+    pub fn drain( &mut self, nucleus_id: &Id )->Result<Vec<(Content,ContentKey)>,Box<dyn Error>>
+    {
+        let mut rtn = vec!();
+        for tron_id in self.tron_ids()
+        {
+            let key = ContentKey{ tron_id: TronKey::new(nucleus_id.clone(), tron_id.clone()), revision: self.revision.clone() };
+            let content = match self.store.remove(&tron_id)
+            {
+                Some(content) => content,
+                None => self.read_only_store.get(&tron_id).unwrap().copy()?
+            };
+            rtn.push((content,key));
+        }
+        self.read_only_store.clear();
+        Ok(rtn)
+    }
 }
 
 impl ReadOnlyContentIntake for IntraCyclicContentStructure
@@ -247,7 +486,7 @@ impl ReadOnlyContentIntake for IntraCyclicContentStructure
 
 impl ContentAccess for IntraCyclicContentStructure
 {
-    fn get( &mut self, key: ContentKey )->Result<&Content,Box<dyn Error>>
+    fn get( &mut self, key: &ContentKey )->Result<&mut Content,Box<dyn Error>>
     {
         if !self.store.contains_key(&key.tron_id.tron_id)
         {
@@ -260,6 +499,6 @@ impl ContentAccess for IntraCyclicContentStructure
             self.store.insert( key.tron_id.tron_id.clone(), content );
         }
 
-        Ok(self.store.get(*key.tron_id.tron_id ))
+        Ok(self.store.get_mut(&key.tron_id.tron_id ).unwrap())
     }
 }
@@ -0,0 +1,235 @@
+use std::error::Error;
+use std::fmt;
+
+use no_proto::json_flex::NP_JSON;
+
+use mechtron_common::artifact::Artifact;
+use mechtron_common::configs::Configs;
+use mechtron_common::content::ReadOnlyContent;
+use mechtron_common::id::{Revision, TronKey};
+
+pub enum Change
+{
+    Added(NP_JSON),
+    Removed(NP_JSON),
+    Modified{ from: NP_JSON, to: NP_JSON }
+}
+
+// a change to one field.  the path is the list of table columns, map keys and list indexes
+// leading from the root of the buffer to the field
+pub struct FieldDiff
+{
+    pub path: Vec<String>,
+    pub change: Change
+}
+
+// everything that changed in the content of a tron between two revisions
+pub struct ContentDiff
+{
+    pub tron_key: TronKey,
+    pub from: Revision,
+    pub to: Revision,
+    pub artifact: Option<(Artifact,Artifact)>,
+    pub meta: Vec<FieldDiff>,
+    pub data: Vec<FieldDiff>
+}
+
+impl ContentDiff
+{
+    // buffers are walked using the schemas of their artifacts.  when the artifact itself changed
+    // the data is walked with each revision's own schema
+    pub fn new( tron_key: TronKey, from: (&Revision, &ReadOnlyContent), to: (&Revision, &ReadOnlyContent), configs: &Configs )->Result<Self,Box<dyn Error>>
+    {
+        let (from_revision, from) = from;
+        let (to_revision, to) = to;
+
+        let meta_factory = configs.core_buffer_factory("schema/content/meta")?;
+        let from_meta = encode(meta_factory.open_buffer(from.meta.bytes()).json_encode(&[]))?;
+        let to_meta = encode(meta_factory.open_buffer(to.meta.bytes()).json_encode(&[]))?;
+
+        let from_data = encode(configs.buffer_factory_keeper.get(&from.artifact)?.open_buffer(from.data.bytes()).json_encode(&[]))?;
+        let to_data = encode(configs.buffer_factory_keeper.get(&to.artifact)?.open_buffer(to.data.bytes()).json_encode(&[]))?;
+
+        let mut meta = vec!();
+        diff(&mut vec!(), &from_meta, &to_meta, &mut meta);
+        let mut data = vec!();
+        diff(&mut vec!(), &from_data, &to_data, &mut data);
+
+        Ok(ContentDiff{
+            tron_key: tron_key,
+            from: from_revision.clone(),
+            to: to_revision.clone(),
+            artifact: match from.artifact == to.artifact {
+                true => Option::None,
+                false => Option::Some((from.artifact.clone(), to.artifact.clone()))
+            },
+            meta: meta,
+            data: data
+        })
+    }
+
+    pub fn is_empty(&self)->bool
+    {
+        self.artifact.is_none() && self.meta.is_empty() && self.data.is_empty()
+    }
+}
+
+fn encode( json: Result<NP_JSON,no_proto::error::NP_Error> )->Result<NP_JSON,Box<dyn Error>>
+{
+    match json
+    {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("could not walk content buffer with its schema: {:?}",e).into())
+    }
+}
+
+fn diff( path: &mut Vec<String>, from: &NP_JSON, to: &NP_JSON, rtn: &mut Vec<FieldDiff> )
+{
+    match (from, to)
+    {
+        (NP_JSON::Null, NP_JSON::Null) => {},
+        (NP_JSON::Null, to) => rtn.push(FieldDiff{ path: path.clone(), change: Change::Added(to.clone()) }),
+        (from, NP_JSON::Null) => rtn.push(FieldDiff{ path: path.clone(), change: Change::Removed(from.clone()) }),
+        (NP_JSON::Dictionary(from), NP_JSON::Dictionary(to)) => {
+            for (key, from_value) in &from.values
+            {
+                path.push(key.clone());
+                match to.get(key)
+                {
+                    None => diff(path, from_value, &NP_JSON::Null, rtn),
+                    Some(to_value) => diff(path, from_value, to_value, rtn)
+                }
+                path.pop();
+            }
+            for (key, to_value) in &to.values
+            {
+                if !from.has(key)
+                {
+                    path.push(key.clone());
+                    diff(path, &NP_JSON::Null, to_value, rtn);
+                    path.pop();
+                }
+            }
+        },
+        (NP_JSON::Array(from), NP_JSON::Array(to)) => {
+            for index in 0..std::cmp::max(from.len(), to.len())
+            {
+                path.push(index.to_string());
+                diff(path, from.get(index).unwrap_or(&NP_JSON::Null), to.get(index).unwrap_or(&NP_JSON::Null), rtn);
+                path.pop();
+            }
+        },
+        (from, to) => {
+            if from.stringify() != to.stringify()
+            {
+                rtn.push(FieldDiff{ path: path.clone(), change: Change::Modified{ from: from.clone(), to: to.clone() } });
+            }
+        }
+    }
+}
+
+impl fmt::Display for FieldDiff
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self.path.is_empty() {
+            true => "/".to_string(),
+            false => self.path.join("/")
+        };
+        match &self.change
+        {
+            Change::Added(value) => write!(f, "+ {} = {}", path, value.stringify() ),
+            Change::Removed(value) => write!(f, "- {} = {}", path, value.stringify() ),
+            Change::Modified{ from, to } => write!(f, "~ {} : {} -> {}", path, from.stringify(), to.stringify() )
+        }
+    }
+}
+
+impl fmt::Display for ContentDiff
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tron {:?} cycle {} -> {}", self.tron_key, self.from.cycle, self.to.cycle )?;
+        if self.is_empty()
+        {
+            return writeln!(f, "  no changes");
+        }
+        if let Option::Some((from,to)) = &self.artifact
+        {
+            writeln!(f, "  artifact: {} -> {}", from.to(), to.to() )?;
+        }
+        if !self.meta.is_empty()
+        {
+            writeln!(f, "  meta:")?;
+            for field in &self.meta
+            {
+                writeln!(f, "    {}", field )?;
+            }
+        }
+        if !self.data.is_empty()
+        {
+            writeln!(f, "  data:")?;
+            for field in &self.data
+            {
+                writeln!(f, "    {}", field )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+
+    use mechtron_common::json::parse;
+
+    use crate::diff::{diff, Change, FieldDiff};
+
+    fn diffs( from: &str, to: &str )->Result<Vec<FieldDiff>,Box<dyn Error>>
+    {
+        let mut rtn = vec!();
+        diff(&mut vec!(), &parse(from)?, &parse(to)?, &mut rtn);
+        Ok(rtn)
+    }
+
+    #[test]
+    fn identical_content_has_no_diffs() -> Result<(),Box<dyn Error>>
+    {
+        let json = r#"{"name":"left","count":3,"tags":["a","b"]}"#;
+        assert!(diffs(json, json)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn field_changes() -> Result<(),Box<dyn Error>>
+    {
+        let rtn = diffs(r#"{"name":"left","count":3,"gone":true}"#, r#"{"name":"left","count":4,"new":"x"}"#)?;
+        assert_eq!(rtn.len(), 3);
+
+        let count = rtn.iter().find(|d| d.path == vec!("count".to_string())).unwrap();
+        match &count.change
+        {
+            Change::Modified{ from, to } => {
+                assert_eq!(from.stringify(), "3");
+                assert_eq!(to.stringify(), "4");
+            },
+            _ => panic!("expected count to be modified")
+        }
+        assert!(rtn.iter().any(|d| d.path == vec!("gone".to_string()) && matches!(d.change, Change::Removed(_))));
+        assert!(rtn.iter().any(|d| d.path == vec!("new".to_string()) && matches!(d.change, Change::Added(_))));
+        assert_eq!(count.to_string(), "~ count : 3 -> 4");
+        Ok(())
+    }
+
+    #[test]
+    fn list_changes_are_indexed() -> Result<(),Box<dyn Error>>
+    {
+        let rtn = diffs(r#"{"tags":["a","b"]}"#, r#"{"tags":["a","c","d"]}"#)?;
+        assert_eq!(rtn.len(), 2);
+        assert_eq!(rtn[0].path, vec!("tags".to_string(), "1".to_string()));
+        assert!(matches!(rtn[0].change, Change::Modified{..}));
+        assert_eq!(rtn[1].path, vec!("tags".to_string(), "2".to_string()));
+        assert!(matches!(rtn[1].change, Change::Added(_)));
+        Ok(())
+    }
+}
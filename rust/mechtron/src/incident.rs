@@ -0,0 +1,59 @@
+use std::fmt;
+
+use mechtron_common::id::{Id, Revision, TronKey};
+
+use crate::tron::MechtronError;
+use crate::wasm::LimitExceeded;
+
+// something that went wrong in a revision without stopping it.  a tron that fails fails only
+// the call it was making, a message that cannot be routed is rejected back to its sender and
+// a message that can be neither delivered nor rejected is dropped.
+// the source keeps these for whoever is running the simulation to take
+#[derive(Clone,Debug)]
+pub enum Incident
+{
+    TronFailed{ revision: Revision, tron: TronKey, failure: TronFailure },
+    MessageRejected{ revision: Revision, message: Id, reason: String },
+    MessageDropped{ revision: Revision, message: Id, reason: String }
+}
+
+impl fmt::Display for Incident
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Incident::TronFailed{ revision, tron, failure } => write!(f, "cycle {}: tron {:?} failed: {}", revision.cycle, tron, failure),
+            Incident::MessageRejected{ revision, message, reason } => write!(f, "cycle {}: rejected message {:?}: {}", revision.cycle, message, reason),
+            Incident::MessageDropped{ revision, message, reason } => write!(f, "cycle {}: dropped message {:?}: {}", revision.cycle, message, reason)
+        }
+    }
+}
+
+// why a tron failed, kept as the error that was raised so it can be told apart by kind
+#[derive(Clone,Debug)]
+pub enum TronFailure
+{
+    // the tron could not be instantiated and fails every call for the rest of the cycle
+    Init(String),
+    LimitExceeded(LimitExceeded),
+    // the wasm guest of the tron returned an error or panicked
+    Guest(MechtronError),
+    // the call could not be completed by the host, most often because a message the tron
+    // built could not be sent (an unknown lookup name or phase, or an undeclared outbound port)
+    Call(String)
+}
+
+impl fmt::Display for TronFailure
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            TronFailure::Init(reason) => write!(f, "{}", reason),
+            TronFailure::LimitExceeded(error) => write!(f, "{}", error),
+            TronFailure::Guest(error) => write!(f, "{}", error),
+            TronFailure::Call(reason) => write!(f, "{}", reason)
+        }
+    }
+}
@@ -9,7 +9,14 @@ pub mod message;
 pub mod repository;
 pub mod content;
 pub mod source;
+pub mod store;
+pub mod checkpoint;
+pub mod replay;
+pub mod diff;
+pub mod lookup;
 pub mod create;
+pub mod incident;
+pub mod limits;
 
 
 
@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+
+use wasmer::{BaseTunables, CompilerConfig, Cranelift, ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, JIT, LocalFunctionIndex, MemoryType, MiddlewareError, MiddlewareReaderState, Module, ModuleMiddleware, Mutability, Pages, Store, TableType, Target, Tunables, Type, Val};
+use wasmer::vm::{Memory, MemoryError, MemoryStyle, Table, TableStyle, VMMemoryDefinition, VMTableDefinition};
+use wasmer::wasmparser::{Operator, Type as WpType, TypeOrFuncType};
+use wasmer_types::GlobalIndex;
+use wasmer_vm::ModuleInfo;
+
+use mechtron_common::configs::MechtronLimits;
+
+// the globals the metering adds to every mechtron module
+static REMAINING_POINTS: &'static str = "mechtron_remaining_points";
+static POINTS_EXHAUSTED: &'static str = "mechtron_points_exhausted";
+
+// every mechtron module is compiled with a store of its own so that only mechtron modules
+// are metered and each is capped at the memory limit of its mechtron config
+pub fn compile( wasm: &[u8], limits: &MechtronLimits ) -> Result<Module,Box<dyn Error>>
+{
+    let mut compiler = Cranelift::default();
+    compiler.push_middleware(Arc::new(Metering::new()));
+    let tunables = MemoryLimit::new(BaseTunables::for_target(&Target::default()), Pages(limits.memory_pages));
+    let store = Store::new_with_tunables(&JIT::new(compiler).engine(), tunables);
+    Ok(Module::new(&store, wasm)?)
+}
+
+// give the instance a fresh budget of instructions for its next call
+pub fn set_remaining_points( instance: &Instance, points: u64 ) -> Result<(),Box<dyn Error>>
+{
+    instance.exports.get_global(REMAINING_POINTS)?.set(Val::I64(points as i64))?;
+    instance.exports.get_global(POINTS_EXHAUSTED)?.set(Val::I32(0))?;
+    Ok(())
+}
+
+// true once the guest has been stopped for running out of instructions.  the guest can
+// not reach the flag itself so a trap for any other reason never sets it
+pub fn points_exhausted( instance: &Instance ) -> Result<bool,Box<dyn Error>>
+{
+    match instance.exports.get_global(POINTS_EXHAUSTED)?.get()
+    {
+        Val::I32(exhausted) => Ok(exhausted != 0),
+        _ => Err(format!("{} is not an i32 global", POINTS_EXHAUSTED).into())
+    }
+}
+
+// counts one point for every operator a guest runs.  the points are taken from a global the
+// middleware adds to the module at every branch, call and return, and the guest traps as
+// soon as it would use more than it has left.  a Metering is only ever used for one module.
+// the Metering of wasmer_middlewares is not used because it leaves the points as they were
+// when it stops a guest, so running out of them can not be told apart from any other trap
+#[derive(Debug)]
+struct Metering
+{
+    globals: Mutex<Option<(GlobalIndex,GlobalIndex)>>
+}
+
+impl Metering
+{
+    fn new() -> Self
+    {
+        Metering {
+            globals: Mutex::new(Option::None)
+        }
+    }
+}
+
+impl ModuleMiddleware for Metering
+{
+    fn generate_function_middleware( &self, _: LocalFunctionIndex ) -> Box<dyn FunctionMiddleware>
+    {
+        // the module info is always transformed before any of its functions are compiled,
+        // if it was not the function fails to compile when it is fed
+        let globals = match self.globals.lock()
+        {
+            Ok(globals) => *globals,
+            Err(_) => Option::None
+        };
+        Box::new(FunctionMetering {
+            globals: globals,
+            cost: 0
+        })
+    }
+
+    fn transform_module_info( &self, module_info: &mut ModuleInfo )
+    {
+        let remaining = module_info.globals.push(GlobalType::new(Type::I64, Mutability::Var));
+        module_info.global_initializers.push(GlobalInit::I64Const(0));
+        module_info.exports.insert(REMAINING_POINTS.to_string(), ExportIndex::Global(remaining));
+
+        let exhausted = module_info.globals.push(GlobalType::new(Type::I32, Mutability::Var));
+        module_info.global_initializers.push(GlobalInit::I32Const(0));
+        module_info.exports.insert(POINTS_EXHAUSTED.to_string(), ExportIndex::Global(exhausted));
+
+        if let Ok(mut globals) = self.globals.lock()
+        {
+            *globals = Option::Some((remaining, exhausted));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FunctionMetering
+{
+    // the remaining points and exhausted flag globals of the module
+    globals: Option<(GlobalIndex,GlobalIndex)>,
+    // points used since the last check
+    cost: u64
+}
+
+impl FunctionMiddleware for FunctionMetering
+{
+    fn feed<'a>( &mut self, operator: Operator<'a>, state: &mut MiddlewareReaderState<'a> ) -> Result<(), MiddlewareError>
+    {
+        let (remaining, exhausted) = match self.globals
+        {
+            Some(globals) => globals,
+            None => return Err(MiddlewareError::new("mechtron metering", "the module was not given its metering globals"))
+        };
+        self.cost = self.cost + 1;
+
+        match operator
+        {
+            Operator::Loop { .. } | Operator::End | Operator::Else | Operator::Br { .. } | Operator::BrIf { .. } | Operator::BrTable { .. } | Operator::Call { .. } | Operator::CallIndirect { .. } | Operator::Return => {
+                state.extend(&[
+                    // if remaining < cost { exhausted = 1; unreachable }
+                    Operator::GlobalGet { global_index: remaining.as_u32() },
+                    Operator::I64Const { value: self.cost as i64 },
+                    Operator::I64LtU,
+                    Operator::If { ty: TypeOrFuncType::Type(WpType::EmptyBlockType) },
+                    Operator::I32Const { value: 1 },
+                    Operator::GlobalSet { global_index: exhausted.as_u32() },
+                    Operator::Unreachable,
+                    Operator::End,
+                    // remaining = remaining - cost
+                    Operator::GlobalGet { global_index: remaining.as_u32() },
+                    Operator::I64Const { value: self.cost as i64 },
+                    Operator::I64Sub,
+                    Operator::GlobalSet { global_index: remaining.as_u32() }
+                ]);
+                self.cost = 0;
+            }
+            _ => {}
+        }
+
+        state.push_operator(operator);
+        Ok(())
+    }
+}
+
+// the maximum a module declares for its memory is lowered to the limit so memory.grow past the
+// limit fails in the guest, and a module that needs more than the limit to start with can not
+// be instantiated at all
+struct MemoryLimit
+{
+    base: BaseTunables,
+    pages: Pages
+}
+
+impl MemoryLimit
+{
+    fn new( base: BaseTunables, pages: Pages ) -> Self
+    {
+        MemoryLimit {
+            base: base,
+            pages: pages
+        }
+    }
+
+    fn adjust( &self, requested: &MemoryType ) -> MemoryType
+    {
+        let mut adjusted = requested.clone();
+        adjusted.maximum = match requested.maximum {
+            Some(maximum) if maximum < self.pages => Option::Some(maximum),
+            _ => Option::Some(self.pages)
+        };
+        adjusted
+    }
+
+    fn validate( &self, memory: &MemoryType ) -> Result<(),MemoryError>
+    {
+        if memory.minimum > self.pages
+        {
+            return Err(MemoryError::MinimumMemoryTooLarge{ min_requested: memory.minimum, max_allowed: self.pages });
+        }
+        Ok(())
+    }
+}
+
+impl Tunables for MemoryLimit
+{
+    fn memory_style( &self, memory: &MemoryType ) -> MemoryStyle
+    {
+        self.base.memory_style(&self.adjust(memory))
+    }
+
+    fn table_style( &self, table: &TableType ) -> TableStyle
+    {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory( &self, ty: &MemoryType, style: &MemoryStyle ) -> Result<Arc<dyn Memory>, MemoryError>
+    {
+        let adjusted = self.adjust(ty);
+        self.validate(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+
+    unsafe fn create_vm_memory( &self, ty: &MemoryType, style: &MemoryStyle, vm_definition_location: NonNull<VMMemoryDefinition> ) -> Result<Arc<dyn Memory>, MemoryError>
+    {
+        let adjusted = self.adjust(ty);
+        self.validate(&adjusted)?;
+        self.base.create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+
+    fn create_host_table( &self, ty: &TableType, style: &TableStyle ) -> Result<Arc<dyn Table>, String>
+    {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table( &self, ty: &TableType, style: &TableStyle, vm_definition_location: NonNull<VMTableDefinition> ) -> Result<Arc<dyn Table>, String>
+    {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
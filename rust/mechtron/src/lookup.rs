@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::RwLock;
+
+use no_proto::json_flex::NP_JSON;
+use no_proto::memory::NP_Memory_Owned;
+
+use mechtron_common::buffers::get;
+use mechtron_common::content::ReadOnlyContent;
+use mechtron_common::id::{ContentKey, Id, Revision, TronKey};
+
+use crate::tron::{Context, Neutron, SIMTRON};
+
+// names are resolved from the content of the previous revision, which never changes once
+// it is committed.  a cache is created for each revision of a nucleus and shared by every
+// tron in it so each neutron and the SimTron are read at most once per cycle.  the next
+// revision gets a new cache, which is how names registered in this cycle become visible
+pub struct LookupCache
+{
+    revision: Revision,
+    simulation_nucleus_ids: RwLock<HashMap<Id,Id>>,
+    nucleus_names: RwLock<Option<HashMap<String,Id>>>,
+    tron_names: RwLock<HashMap<Id,HashMap<String,Id>>>
+}
+
+impl LookupCache
+{
+    pub fn new( revision: Revision )->Self
+    {
+        LookupCache{
+            revision: revision,
+            simulation_nucleus_ids: RwLock::new(HashMap::new()),
+            nucleus_names: RwLock::new(Option::None),
+            tron_names: RwLock::new(HashMap::new())
+        }
+    }
+
+    fn previous( &self, tron_key: TronKey )->ContentKey
+    {
+        ContentKey{ tron_id: tron_key, revision: Revision{ cycle: self.revision.cycle - 1 } }
+    }
+
+    // the nucleus the SimTron lives in, as recorded by the neutron of the given nucleus
+    pub fn simulation_nucleus_id( &self, context: &Context, nucleus_id: &Id )->Result<Id,Box<dyn Error>>
+    {
+        if let Option::Some(id) = self.simulation_nucleus_ids.read()?.get(nucleus_id)
+        {
+            return Ok(id.clone());
+        }
+
+        let neutron_content = context.get_content(&self.previous(Neutron::key(nucleus_id)))?;
+        let id = Id::new(get::<i64, NP_Memory_Owned>(&neutron_content.data, &[&"simulation_nucleus_id", &"seq_id"])?,
+                         get::<i64, NP_Memory_Owned>(&neutron_content.data, &[&"simulation_nucleus_id", &"id"])?);
+
+        self.simulation_nucleus_ids.write()?.insert(nucleus_id.clone(), id.clone());
+        Ok(id)
+    }
+
+    pub fn lookup_nucleus( &self, context: &Context, name: &str )->Result<Id,Box<dyn Error>>
+    {
+        if self.nucleus_names.read()?.is_none()
+        {
+            let simulation_nucleus_id = self.simulation_nucleus_id(context, &context.id.nucleus_id)?;
+            let simtron_key = self.lookup_tron(context, &simulation_nucleus_id, SIMTRON)?;
+            let simtron_content = context.get_content(&self.previous(simtron_key))?;
+            let names = names(&simtron_content, "nucleus_names")?;
+            *self.nucleus_names.write()? = Option::Some(names);
+        }
+
+        match self.nucleus_names.read()?.as_ref().unwrap().get(name)
+        {
+            None => Err(format!("no nucleus is registered with the lookup name '{}'", name).into()),
+            Some(nucleus_id) => Ok(nucleus_id.clone())
+        }
+    }
+
+    pub fn lookup_tron( &self, context: &Context, nucleus_id: &Id, name: &str )->Result<TronKey,Box<dyn Error>>
+    {
+        if !self.tron_names.read()?.contains_key(nucleus_id)
+        {
+            let neutron_content = context.get_content(&self.previous(Neutron::key(nucleus_id)))?;
+            let names = names(&neutron_content, "tron_names")?;
+            self.tron_names.write()?.insert(nucleus_id.clone(), names);
+        }
+
+        match self.tron_names.read()?.get(nucleus_id).unwrap().get(name)
+        {
+            None => Err(format!("nucleus {:?} does not have a tron with the lookup name '{}'", nucleus_id, name).into()),
+            Some(tron_id) => Ok(TronKey::new(nucleus_id.clone(), tron_id.clone()))
+        }
+    }
+}
+
+// every entry of a map of names to seq_id, id tables
+fn names( content: &ReadOnlyContent, column: &str )->Result<HashMap<String,Id>,Box<dyn Error>>
+{
+    let json = match content.data.json_encode(&[column])
+    {
+        Ok(json) => json,
+        Err(_) => return Err(format!("could not read {} from content of {}", column, content.artifact.to()).into())
+    };
+
+    let mut rtn = HashMap::new();
+    if let NP_JSON::Dictionary(map) = json
+    {
+        for (name, value) in &map.values
+        {
+            if let (Some(seq_id), Some(id)) = (value["seq_id"].into_i64(), value["id"].into_i64())
+            {
+                rtn.insert(name.clone(), Id::new(seq_id.clone(), id.clone()));
+            }
+        }
+    }
+    Ok(rtn)
+}
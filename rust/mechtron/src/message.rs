@@ -1,21 +1,70 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::time::Instant;
 
-use mechtron_common::message::{Cycle, To};
+use mechtron_common::message::{Cycle, From, MessageKind, To};
 use mechtron_common::message::Message;
 
 use mechtron_common::revision::Revision;
 use std::sync::{RwLock, Arc, Mutex};
 use crate::content::TronKey;
 use std::error::Error;
-use mechtron_common::id::{ContentKey, TronKey, Revision, DeliveryMomentKey};
+use mechtron_common::id::{ContentKey, TronKey, Revision, DeliveryMomentKey, Id, IdSeq};
 
 struct MessageChamber {
     key: TronKey,
     messages: HashMap<DeliveryMomentKey,RwLock<Vec<MessageDelivery>>>
 }
 
+impl MessageChamber
+{
+    fn new( key: TronKey )->Self
+    {
+        MessageChamber{
+            key: key,
+            messages: HashMap::new()
+        }
+    }
+
+    fn intake( &mut self, delivery: MessageDelivery )->Result<(),Box<dyn Error+'_>>
+    {
+        let moment = DeliveryMomentKey{
+            cycle: delivery_cycle(&delivery.message),
+            phase: delivery.message.to.phase
+        };
+        if !self.messages.contains_key(&moment)
+        {
+            self.messages.insert(moment.clone(), RwLock::new(vec!()));
+        }
+        let mut deliveries = self.messages.get(&moment).unwrap().write()?;
+        deliveries.push(delivery);
+        Ok(())
+    }
+
+    // remove every delivery due in the given cycle regardless of phase
+    fn take( &mut self, cycle: i64 )->Result<Vec<MessageDelivery>,Box<dyn Error+'_>>
+    {
+        let moments: Vec<DeliveryMomentKey> = self.messages.keys().filter(|moment| moment.cycle == cycle ).cloned().collect();
+        let mut rtn = vec!();
+        for moment in moments
+        {
+            let deliveries = self.messages.remove(&moment).unwrap();
+            rtn.append(&mut deliveries.into_inner()?);
+        }
+        Ok(rtn)
+    }
+
+    fn is_empty( &self )->bool
+    {
+        self.messages.is_empty()
+    }
+
+    fn is_due( &self, cycle: i64 )->bool
+    {
+        self.messages.keys().any(|moment| moment.cycle == cycle )
+    }
+}
+
 struct MessageDelivery
 {
     received: Instant,
@@ -25,16 +74,19 @@ struct MessageDelivery
 pub struct MessagingStructure
 {
     chambers: HashMap<TronKey,RwLock<MessageChamber>>,
-    pipeline: Arc<MessagePipeline>
+    pipeline: Arc<MessagePipeline>,
+    // how many cycles into the future a message may be addressed
+    horizon: i64
 }
 
 impl MessagingStructure
 {
-    pub fn new()->Self
+    pub fn new( horizon: i64 )->Self
     {
         MessagingStructure {
             chambers: HashMap::new(),
-            pipeline: Arc::new(MessagePipeline::new() )
+            pipeline: Arc::new(MessagePipeline::new() ),
+            horizon: horizon
         }
     }
 
@@ -45,7 +97,7 @@ impl MessagingStructure
             return Err(format!("MessageStore already contains tron_id {:?} ",tron_id).into());
         }
 
-        self.chambers.insert(tron_id, RwLock::new(MessageChamber::new()));
+        self.chambers.insert(tron_id.clone(), RwLock::new(MessageChamber::new(tron_id)));
 
         return Ok(());
     }
@@ -55,10 +107,139 @@ impl MessagingStructure
         return self.pipeline.clone();
     }
 
-    pub fn flood(&mut self)
+    // move every message waiting in the pipeline into the chamber of the tron it is addressed to,
+    // keyed by the cycle and phase it should be delivered in.  head is the last completed revision
+    pub fn flood(&mut self, head: &Revision )->Result<(),Box<dyn Error+'_>>
+    {
+        for delivery in self.pipeline.flood()?
+        {
+            validate_delivery_cycle(&delivery.message, head, self.horizon)?;
+
+            if !self.chambers.contains_key(&delivery.message.to.tron)
+            {
+                self.create(delivery.message.to.tron.clone())?;
+            }
+
+            let mut chamber = self.chambers.get(&delivery.message.to.tron).unwrap().write()?;
+            chamber.intake(delivery)?;
+        }
+        Ok(())
+    }
+
+    pub fn has_pending(&self)->Result<bool,Box<dyn Error+'_>>
+    {
+        if self.pipeline.has_pending()?
+        {
+            return Ok(true);
+        }
+
+        for chamber in self.chambers.values()
+        {
+            if !chamber.read()?.is_empty()
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // every message that has not yet been delivered, whether still in the pipeline or waiting in a chamber
+    pub fn pending(&self)->Result<Vec<Message>,Box<dyn Error+'_>>
     {
-        let messages = self.pipeline.flood();
+        let mut rtn = self.pipeline.messages()?;
+        for chamber in self.chambers.values()
+        {
+            let chamber = chamber.read()?;
+            for deliveries in chamber.messages.values()
+            {
+                for delivery in deliveries.read()?.iter()
+                {
+                    rtn.push(delivery.message.clone());
+                }
+            }
+        }
+        Ok(rtn)
     }
+
+    // put messages captured by pending() back in the pipeline, the next flood returns
+    // them to the chambers for the moments they are to be delivered
+    pub fn restore(&self, messages: Vec<Message> )->Result<(),Box<dyn Error+'_>>
+    {
+        let pipeline = self.cyclic_intake();
+        for message in messages
+        {
+            pipeline.intake(message)?;
+        }
+        Ok(())
+    }
+
+    // every nucleus with a message due in this revision.  a nucleus that has just been
+    // created has no content yet and is only known by the messages addressed to it
+    pub fn nuclei_due(&self, revision: &Revision )->Result<HashSet<Id>,Box<dyn Error+'_>>
+    {
+        let mut rtn = HashSet::new();
+        for (tron_key, chamber) in &self.chambers
+        {
+            if chamber.read()?.is_due(revision.cycle)
+            {
+                rtn.insert(tron_key.nucleus_id.clone());
+            }
+        }
+        Ok(rtn)
+    }
+
+    // remove and return every message addressed to a tron in this nucleus that is due for delivery in this revision.
+    // a chamber left with nothing to deliver is dropped, it is created again by the next message to its tron
+    pub fn query_messages(&mut self, nucleus_id: &Id, revision: &Revision )->Result<Vec<Message>,Box<dyn Error+'_>>
+    {
+        let mut rtn = vec!();
+        for (tron_key, chamber) in &self.chambers
+        {
+            if tron_key.nucleus_id != *nucleus_id
+            {
+                continue;
+            }
+
+            let mut chamber = chamber.write()?;
+            for delivery in chamber.take(revision.cycle)?
+            {
+                rtn.push(delivery.message);
+            }
+        }
+        self.chambers.retain(|_, chamber| match chamber.get_mut() {
+            Ok(chamber) => !chamber.is_empty(),
+            Err(_) => true
+        });
+        rtn.sort_by(|a,b| a.id.cmp(&b.id) );
+        Ok(rtn)
+    }
+}
+
+// the cycle in which a message should be delivered
+pub fn delivery_cycle( message: &Message )->i64
+{
+    match &message.to.cycle
+    {
+        Cycle::Future(cycle) => cycle.clone(),
+        Cycle::Present => message.from.cycle,
+        Cycle::Next => message.from.cycle + 1
+    }
+}
+
+// a message leaving a cycle must be addressed to a cycle after 'current' and within 'horizon' cycles of it
+pub fn validate_delivery_cycle( message: &Message, current: &Revision, horizon: i64 )->Result<(),Box<dyn Error>>
+{
+    let cycle = delivery_cycle(message);
+    if cycle <= current.cycle
+    {
+        return Err(format!("message {:?} is addressed to cycle {} which is not after the current cycle {}", message.id, cycle, current.cycle).into());
+    }
+    if cycle > current.cycle + horizon
+    {
+        return Err(format!("message {:?} is addressed to cycle {} which is beyond the delivery horizon of {} cycles from the current cycle {}", message.id, cycle, horizon, current.cycle).into());
+    }
+    Ok(())
 }
 
 
@@ -69,7 +250,7 @@ pub struct MessagePipeline
 
 impl MessageIntake for MessagePipeline{
 
-    fn intake(&mut self, message: Message) -> Result<(), Box<dyn Error>> {
+    fn intake(&self, message: Message) -> Result<(), Box<dyn Error>> {
         let mut pipeline = self.pipeline.lock()?;
         let delivery = MessageDelivery{
             received: Instant::now(),
@@ -88,7 +269,19 @@ impl MessagePipeline{
        }
     }
 
-    pub fn flood(&mut self)->Vec<MessageDelivery>
+    pub fn has_pending(&self)->Result<bool,Box<dyn Error+'_>>
+    {
+        let pipeline = self.pipeline.lock()?;
+        Ok(!pipeline.is_empty())
+    }
+
+    pub fn messages(&self)->Result<Vec<Message>,Box<dyn Error+'_>>
+    {
+        let pipeline = self.pipeline.lock()?;
+        Ok(pipeline.iter().map(|delivery| delivery.message.clone() ).collect())
+    }
+
+    pub fn flood(&self)->Result<Vec<MessageDelivery>,Box<dyn Error+'_>>
     {
         let mut pipeline = self.pipeline.lock()?;
         let mut rtn = vec!();
@@ -96,30 +289,317 @@ impl MessagePipeline{
         {
             rtn.push(delivery );
         }
-        return rtn;
+        // the order messages arrive in depends on which nucleus finished first, so order by id instead
+        rtn.sort_by(|a,b| a.message.id.cmp(&b.message.id) );
+        return Ok(rtn);
+    }
+}
+
+// what becomes of a message handed to Transactions::track
+pub enum Tracking
+{
+    // the message is routed as is, or readdressed to the requester if it is a Response
+    Route(Message),
+    // a Response for a transaction that has already completed or timed out
+    Drop(String),
+    // the message cannot be tracked and is rejected back to its sender
+    Reject(Message,String)
+}
+
+// an outstanding Request waiting for its Response
+pub struct Transaction
+{
+    pub id: Id,
+    pub request: Message,
+    pub expires: i64
+}
+
+// tracks outstanding Requests so Responses can be routed back to the requester's
+// response port and unanswered Requests can be timed out
+pub struct Transactions
+{
+    timeout: i64,
+    outstanding: RwLock<HashMap<Id,Transaction>>
+}
+
+impl Transactions
+{
+    pub fn new( timeout: i64 )->Self
+    {
+        Transactions{
+            timeout: timeout,
+            outstanding: RwLock::new(HashMap::new())
+        }
+    }
+
+    // begin tracking Requests and route Responses.  a message that cannot be tracked is
+    // handed back to be rejected, errors are left for faults of the host
+    pub fn track( &self, mut message: Message )->Result<Tracking,Box<dyn Error+'_>>
+    {
+        match message.kind
+        {
+            MessageKind::Request => {
+                // the Response, or the timeout Reject, is sent back to this port
+                if message.response_port.is_none()
+                {
+                    let reason = format!("request {:?} does not have a response_port",message.id);
+                    return Ok(Tracking::Reject(message,reason));
+                }
+                if message.transaction.is_none()
+                {
+                    message.transaction = Option::Some(message.id.clone());
+                }
+                let id = message.transaction.as_ref().unwrap().clone();
+                let mut outstanding = self.outstanding.write()?;
+                if outstanding.contains_key(&id)
+                {
+                    let reason = format!("transaction {:?} is already outstanding",id);
+                    return Ok(Tracking::Reject(message,reason));
+                }
+                outstanding.insert(id.clone(), Transaction{
+                    id: id,
+                    request: message.clone(),
+                    expires: message.from.cycle + self.timeout
+                });
+                Ok(Tracking::Route(message))
+            }
+            MessageKind::Response => {
+                let id = match &message.transaction {
+                    None => {
+                        let reason = format!("response {:?} does not have a transaction",message.id);
+                        return Ok(Tracking::Reject(message,reason));
+                    },
+                    Some(id) => id.clone()
+                };
+                let mut outstanding = self.outstanding.write()?;
+                match outstanding.remove(&id)
+                {
+                    None => Ok(Tracking::Drop("response for an unknown or expired transaction".to_string())),
+                    Some(transaction) => {
+                        message.to.tron = transaction.request.from.tron.clone();
+                        message.to.port = transaction.request.response_port.as_ref().unwrap().clone();
+                        Ok(Tracking::Route(message))
+                    }
+                }
+            }
+            MessageKind::Reject => {
+                // a Request that is rejected will never be answered, so its transaction is
+                // closed rather than left to time out with a second Reject
+                if let (Some(id), Some(rejected)) = (&message.transaction, message.rejected_message_id())
+                {
+                    let mut outstanding = self.outstanding.write()?;
+                    let closes = match outstanding.get(id) {
+                        None => false,
+                        Some(transaction) => transaction.request.id == rejected
+                    };
+                    if closes
+                    {
+                        outstanding.remove(id);
+                    }
+                }
+                Ok(Tracking::Route(message))
+            }
+            _ => Ok(Tracking::Route(message))
+        }
+    }
+
+    // every outstanding transaction either receives its Response or times out
+    pub fn has_outstanding( &self )->Result<bool,Box<dyn Error+'_>>
+    {
+        Ok(!self.outstanding.read()?.is_empty())
+    }
+
+    // the Requests of every transaction still waiting for a Response
+    pub fn outstanding( &self )->Result<Vec<Message>,Box<dyn Error+'_>>
+    {
+        let outstanding = self.outstanding.read()?;
+        Ok(outstanding.values().map(|transaction| transaction.request.clone() ).collect())
+    }
+
+    // track the Requests captured by outstanding() again.  they expire at the same cycle
+    // they would have originally
+    pub fn restore( &self, requests: Vec<Message> )->Result<(),Box<dyn Error+'_>>
+    {
+        for request in requests
+        {
+            let tracked = match request.kind {
+                MessageKind::Request => request.transaction.is_some(),
+                _ => false
+            };
+            if !tracked
+            {
+                return Err(format!("message {:?} is not a tracked Request and cannot be restored as a transaction",request.id).into());
+            }
+            if let Tracking::Reject(_,reason) = self.track(request)?
+            {
+                return Err(reason.into());
+            }
+        }
+        Ok(())
+    }
+
+    // remove every transaction that has not received a Response by this cycle and synthesize
+    // a timeout Reject to the requester's response port for each
+    pub fn expire( &self, seq: &IdSeq, revision: &Revision, timestamp: i64 )->Result<Vec<Message>,Box<dyn Error+'_>>
+    {
+        let mut outstanding = self.outstanding.write()?;
+        let mut expired: Vec<Id> = outstanding.values().filter(|t| t.expires <= revision.cycle ).map(|t| t.id.clone() ).collect();
+        expired.sort();
+
+        let mut rtn = vec!();
+        for id in expired
+        {
+            let transaction = outstanding.remove(&id).unwrap();
+            let mut meta = HashMap::new();
+            meta.insert("reason".to_string(), format!("request timed out after {} cycles", self.timeout) );
+
+            let reject = Message::longform( seq,
+                                            MessageKind::Reject,
+                                            From{ tron: transaction.request.to.tron.clone(), cycle: revision.cycle, timestamp: timestamp },
+                                            To::basic( transaction.request.from.tron.clone(), transaction.request.response_port.as_ref().unwrap().clone() ),
+                                            vec!(),
+                                            Option::Some(meta),
+                                            Option::Some(transaction.id.clone()) );
+            rtn.push(reject);
+        }
+
+        Ok(rtn)
     }
 }
 
+// intakes are shared between the nuclei that send to them, so they lock internally
 pub trait MessageIntake
 {
-    fn intake(&mut self, message: Message) -> Result<(),Box<dyn Error>>;
+    fn intake(&self, message: Message) -> Result<(),Box<dyn Error>>;
 }
 
 
 pub trait MessageRouter
 {
-    fn send( &self, messages: Vec<Message> );
+    fn send( &self, messages: Vec<Message> )->Result<(),Box<dyn Error+'_>>;
 }
 
 
-struct IntraCyclicMessagingStructure
+// messages waiting to be delivered within a single cycle of a nucleus, grouped by the phase they are addressed to
+pub struct IntraCyclicMessagingStructure
+{
+    phases: Mutex<HashMap<u8,Vec<Message>>>
+}
+
+impl IntraCyclicMessagingStructure
 {
-    hash: HashMap<To,Message>
+    pub fn new()->Self
+    {
+        IntraCyclicMessagingStructure{
+            phases: Mutex::new(HashMap::new())
+        }
+    }
+
+    pub fn take(&mut self, phase: u8 )->Vec<Message>
+    {
+        match self.phases.get_mut().unwrap().remove(&phase)
+        {
+            None => vec!(),
+            Some(messages) => messages
+        }
+    }
 }
 
 impl MessageIntake for IntraCyclicMessagingStructure
 {
-    fn intake(&mut self, message: Message) -> Result<(), Box<dyn Error>> {
+    fn intake(&self, message: Message) -> Result<(), Box<dyn Error>> {
+        let phase = message.to.phase;
+        let mut phases = self.phases.lock()?;
+        if !phases.contains_key(&phase)
+        {
+            phases.insert(phase, vec!());
+        }
+        phases.get_mut(&phase).unwrap().push(message);
+        Ok(())
+    }
+}
 
+#[cfg(test)]
+mod tests
+{
+    use mechtron_common::id::{Id, IdSeq, Revision, TronKey};
+    use mechtron_common::message::{Cycle, From, InterDeliveryType, Message, MessageKind, To};
+
+    use crate::message::{delivery_cycle, MessageIntake, MessagingStructure, Tracking, Transactions};
+
+    fn message( from_cycle: i64, cycle: Cycle )->Message
+    {
+        let from = From{ tron: TronKey::new(Id::new(0,1), Id::new(0,2)), cycle: from_cycle, timestamp: 0 };
+        let to = To{
+            tron: TronKey::new(Id::new(0,1), Id::new(0,3)),
+            port: "port".to_string(),
+            cycle: cycle,
+            phase: 0,
+            inter_delivery_type: InterDeliveryType::Cyclic
+        };
+        Message::longform(&IdSeq::new(0), MessageKind::Update, from, to, vec!(), Option::None, Option::None)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn delivered_in_the_cycle_it_is_addressed_to()
+    {
+        assert_eq!(delivery_cycle(&message(3, Cycle::Next)), 4);
+        assert_eq!(delivery_cycle(&message(3, Cycle::Present)), 3);
+        assert_eq!(delivery_cycle(&message(3, Cycle::Future(7))), 7);
+    }
+
+    // a rejected Request is answered by its Reject alone, it does not time out as well
+    #[test]
+    fn a_rejected_request_is_rejected_exactly_once()
+    {
+        let seq = IdSeq::new(0);
+        let transactions = Transactions::new(3);
+
+        let mut request = message(3, Cycle::Next);
+        request.kind = MessageKind::Request;
+        request.response_port = Option::Some("answer".to_string());
+        let request = match transactions.track(request).unwrap() {
+            Tracking::Route(request) => request,
+            _ => panic!("the request was not routed")
+        };
+
+        let from = From{ tron: request.to.tron.clone(), cycle: 4, timestamp: 0 };
+        let reject = match transactions.track(request.reject(&seq, from, "no")).unwrap() {
+            Tracking::Route(reject) => reject,
+            _ => panic!("the reject was not routed")
+        };
+        assert_eq!(reject.to.tron, request.from.tron);
+        assert_eq!(reject.to.port, "answer");
+
+        assert!(!transactions.has_outstanding().unwrap());
+        assert!(transactions.expire(&seq, &Revision{ cycle: 10 }, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn messages_past_the_delivery_horizon_are_refused()
+    {
+        let mut messaging = MessagingStructure::new(4);
+        messaging.cyclic_intake().intake(message(3, Cycle::Future(8))).unwrap();
+        assert!(messaging.flood(&Revision{ cycle: 3 }).is_err());
+
+        let mut messaging = MessagingStructure::new(4);
+        messaging.cyclic_intake().intake(message(3, Cycle::Future(7))).unwrap();
+        assert!(messaging.flood(&Revision{ cycle: 3 }).is_ok());
+    }
+
+    // chambers are kept only while they hold messages
+    #[test]
+    fn a_chamber_is_dropped_once_it_has_delivered_everything()
+    {
+        let mut messaging = MessagingStructure::new(4);
+        messaging.cyclic_intake().intake(message(3, Cycle::Next)).unwrap();
+        messaging.cyclic_intake().intake(message(3, Cycle::Future(5))).unwrap();
+        messaging.flood(&Revision{ cycle: 3 }).unwrap();
+
+        assert_eq!(messaging.query_messages(&Id::new(0,1), &Revision{ cycle: 4 }).unwrap().len(), 1);
+        assert_eq!(messaging.chambers.len(), 1);
+        assert_eq!(messaging.query_messages(&Id::new(0,1), &Revision{ cycle: 5 }).unwrap().len(), 1);
+        assert!(messaging.chambers.is_empty());
+        assert!(!messaging.has_pending().unwrap());
+    }
+}
@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use mechtron_common::artifact::Artifact;
+use mechtron_common::configs::Configs;
+use mechtron_common::id::{ContentKey, IdSeq, Revision};
+use mechtron_common::message::Message;
+
+use crate::app::SYS;
+use crate::checkpoint::{messages_from_bytes, messages_to_bytes};
+use crate::source::Source;
+use crate::store::{ContentStore, FileSystemContentStore, read_section, write_section};
+
+static LOG_HEADER_FILE: &'static str = "log";
+static LOG_MESSAGES_DIR: &'static str = "messages";
+
+// every message delivered in each cycle of a recorded run.  together with the content
+// store of the recorded run this is enough to replay the run and compare the results
+pub struct MessageLog
+{
+    dir: PathBuf
+}
+
+impl MessageLog
+{
+    pub fn new( dir: &str )->Result<Self,Box<dyn Error>>
+    {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(dir.join(LOG_MESSAGES_DIR))?;
+        Ok(MessageLog{
+            dir: dir
+        })
+    }
+
+    // the sim that was recorded and the id sequence it was issued ids from, a replay must issue the same ids
+    pub fn write_header( &self, sim_config: &Artifact, seq_id: i64 )->Result<(),Box<dyn Error>>
+    {
+        let mut file = File::create(self.dir.join(LOG_HEADER_FILE))?;
+        file.write_all(format!("{}\n{}", sim_config.to(), seq_id).as_bytes())?;
+        Ok(())
+    }
+
+    pub fn header( &self )->Result<(Artifact,i64),Box<dyn Error>>
+    {
+        let header = fs::read_to_string(self.dir.join(LOG_HEADER_FILE))?;
+        let lines: Vec<&str> = header.lines().collect();
+        if lines.len() != 2
+        {
+            return Err(format!("message log in {:?} has a malformed header",self.dir).into());
+        }
+        Ok((Artifact::from(lines[0])?, lines[1].parse()?))
+    }
+
+    pub fn record( &self, revision: &Revision, messages: &Vec<Message> )->Result<(),Box<dyn Error>>
+    {
+        let mut bytes = vec!();
+        write_section(&mut bytes, messages.len().to_string().as_bytes());
+        write_section(&mut bytes, &messages_to_bytes(messages)?);
+
+        let mut file = File::create(self.dir.join(LOG_MESSAGES_DIR).join(revision.cycle.to_string()))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn read( &self, revision: &Revision, seq: &IdSeq, configs: &Configs )->Result<Vec<Message>,Box<dyn Error>>
+    {
+        let path = self.dir.join(LOG_MESSAGES_DIR).join(revision.cycle.to_string());
+        if !path.exists()
+        {
+            return Err(format!("message log in {:?} does not have a record of cycle {}",self.dir,revision.cycle).into());
+        }
+        let mut file = File::open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut offset = 0;
+        let count = String::from_utf8(read_section(&bytes, &mut offset)?)?.parse()?;
+        let messages = read_section(&bytes, &mut offset)?;
+        messages_from_bytes(seq, configs, messages, count)
+    }
+}
+
+// the first content that differs between a recorded run and its replay
+pub struct Divergence
+{
+    pub key: ContentKey,
+    pub reason: String
+}
+
+impl fmt::Display for Divergence
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "content diverged at {:?}: {}", self.key, self.reason )
+    }
+}
+
+// replay the run recorded in 'dir', writing the replayed content to 'replay_dir', and compare
+// the content of each cycle against the recording.  returns the first divergence or None if
+// every recorded cycle was reproduced exactly
+pub fn replay( dir: String, replay_dir: String )->Result<Option<Divergence>,Box<dyn Error>>
+{
+    let log = Arc::new(MessageLog::new(&dir)?);
+    let (sim_artifact, _) = log.header()?;
+
+    let recorded = FileSystemContentStore::new(dir.clone())?;
+    let head = match recorded.head()?
+    {
+        None => return Err(format!("{} does not contain a recorded run",dir).into()),
+        Some((_,head)) => head
+    };
+
+    SYS.local.configs.sim_config_keeper.cache(&sim_artifact)?;
+    let sim_config = SYS.local.configs.sim_config_keeper.get(&sim_artifact)?;
+
+    let replayed = Arc::new(FileSystemContentStore::new(replay_dir)?);
+    let source = Source::launch_replay(sim_config, replayed.clone(), log)?;
+
+    loop {
+        let revision = source.head()?;
+        let divergence = compare(&recorded, replayed.as_ref(), &revision)?;
+        if divergence.is_some() || revision >= head
+        {
+            return Ok(divergence);
+        }
+        source.next()?;
+    }
+}
+
+fn compare( recorded: &dyn ContentStore, replayed: &dyn ContentStore, revision: &Revision )->Result<Option<Divergence>,Box<dyn Error>>
+{
+    let configs = &SYS.local.configs;
+
+    let mut keys = recorded.keys(revision)?;
+    keys.sort();
+    for key in keys
+    {
+        let expected = recorded.get(&key, configs)?.unwrap();
+        let actual = match replayed.get(&key, configs)?
+        {
+            None => return Ok(Option::Some(Divergence{ key: key, reason: "content was not created in the replay".to_string() })),
+            Some(actual) => actual
+        };
+
+        if expected.artifact != actual.artifact
+        {
+            return Ok(Option::Some(Divergence{ key: key, reason: format!("artifact {} was replayed as {}", expected.artifact.to(), actual.artifact.to()) }));
+        }
+        if expected.meta.bytes() != actual.meta.bytes()
+        {
+            return Ok(Option::Some(Divergence{ key: key, reason: "meta bytes differ".to_string() }));
+        }
+        if expected.data.bytes() != actual.data.bytes()
+        {
+            return Ok(Option::Some(Divergence{ key: key, reason: "data bytes differ".to_string() }));
+        }
+    }
+
+    let mut keys = replayed.keys(revision)?;
+    keys.sort();
+    for key in keys
+    {
+        if recorded.get(&key, configs)?.is_none()
+        {
+            return Ok(Option::Some(Divergence{ key: key, reason: "content was only created in the replay".to_string() }));
+        }
+    }
+
+    Ok(Option::None)
+}
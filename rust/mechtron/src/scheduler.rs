@@ -1,16 +1,261 @@
-use std::time::Instant;
+use std::error::Error;
+use std::sync::{RwLock, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
-struct Scheduler
+use mechtron_common::id::{Id, Revision};
+
+use crate::app::SYS;
+
+// how long a paused scheduler sleeps before checking if it has been resumed or stopped
+static PAUSE_POLL_MILLIS: u64 = 10;
+
+#[derive(Clone)]
+pub enum Run
 {
+    // run exactly this many cycles and then stop
+    Cycles(i64),
+    // run forever at a fixed number of cycles per second (until stopped)
+    Rate(f64),
+    // run until a cycle completes with no messages left to deliver
+    UntilQuiescent
+}
 
+impl Run
+{
+    pub fn validate(&self) -> Result<(),Box<dyn Error>>
+    {
+        match self
+        {
+            Run::Cycles(cycles) => {
+                if *cycles < 0
+                {
+                    return Err(format!("cannot run a negative number of cycles: {}", cycles).into());
+                }
+            }
+            Run::Rate(cycles_per_second) => {
+                if !cycles_per_second.is_finite() || *cycles_per_second <= 0.0
+                {
+                    return Err(format!("cycles per second must be a positive number, not {}", cycles_per_second).into());
+                }
+            }
+            Run::UntilQuiescent => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum SchedulerState
+{
+    // not running, start() or step() may be called
+    Idle,
+    Running,
+    Paused,
+    // stop() was called, the run ends once the cycle in progress completes
+    Stopping
+}
+
+pub struct Scheduler
+{
+    sim_id: Id,
+    run: Run,
+    state: RwLock<SchedulerState>,
 }
 
 impl Scheduler{
 
+    pub fn new( sim_id: Id, run: Run ) -> Result<Self,Box<dyn Error>>
+    {
+        run.validate()?;
+        Ok(Scheduler{
+            sim_id: sim_id,
+            run: run,
+            state: RwLock::new(SchedulerState::Idle)
+        })
+    }
+
     pub fn now()->Instant
     {
         let now = Instant::now();
         return now;
     }
 
-}
\ No newline at end of file
+    fn lock(&self) -> Result<RwLockWriteGuard<SchedulerState>,Box<dyn Error>>
+    {
+        match self.state.write()
+        {
+            Ok(lock) => Ok(lock),
+            Err(_) => Err(format!("state of the scheduler for sim {:?} is poisoned", self.sim_id).into())
+        }
+    }
+
+    pub fn state(&self) -> Result<SchedulerState,Box<dyn Error>>
+    {
+        Ok(self.lock()?.clone())
+    }
+
+    // move from one of the given states to another, the check and the change are made under one lock
+    fn transition(&self, from: &[SchedulerState], to: SchedulerState ) -> Result<(),Box<dyn Error>>
+    {
+        let mut state = self.lock()?;
+        if !from.contains(&*state)
+        {
+            return Err(format!("scheduler for sim {:?} cannot become {:?} while it is {:?}", self.sim_id, to, *state).into());
+        }
+        *state = to;
+        Ok(())
+    }
+
+    // blocks the calling thread and drives the source cycle by cycle until the Run
+    // completes or the scheduler is stopped.  pause() and stop() are meant to be
+    // called from another thread.  however the run ends the scheduler is Idle again
+    // and may be started for another run
+    pub fn start(&self) -> Result<(),Box<dyn Error>>
+    {
+        self.transition(&[SchedulerState::Idle], SchedulerState::Running)?;
+        let result = self.drive();
+        *self.lock()? = SchedulerState::Idle;
+        result
+    }
+
+    fn drive(&self) -> Result<(),Box<dyn Error>>
+    {
+        let mut cycles = 0;
+        loop {
+            match self.state()?
+            {
+                SchedulerState::Stopping => break,
+                SchedulerState::Paused => {
+                    thread::sleep(Duration::from_millis(PAUSE_POLL_MILLIS));
+                    continue;
+                }
+                _ => {}
+            }
+
+            // checked before stepping so that a run of zero cycles does not revise the source at all
+            if let Run::Cycles(max) = &self.run
+            {
+                if cycles >= *max
+                {
+                    break;
+                }
+            }
+
+            let started = Scheduler::now();
+            self.next()?;
+            cycles = cycles + 1;
+
+            match &self.run {
+                Run::Cycles(_) => {}
+                Run::Rate(cycles_per_second) => {
+                    let period = Duration::from_secs_f64(1.0 / cycles_per_second);
+                    let elapsed = started.elapsed();
+                    if elapsed < period
+                    {
+                        thread::sleep(period - elapsed);
+                    }
+                }
+                Run::UntilQuiescent => {
+                    let source = SYS.local.sources.get(&self.sim_id)?;
+                    if source.is_quiescent()?
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn pause(&self) -> Result<(),Box<dyn Error>>
+    {
+        self.transition(&[SchedulerState::Running], SchedulerState::Paused)
+    }
+
+    pub fn resume(&self) -> Result<(),Box<dyn Error>>
+    {
+        self.transition(&[SchedulerState::Paused], SchedulerState::Running)
+    }
+
+    // advance the source exactly one cycle, returns the new head revision.  a running
+    // scheduler must be paused first
+    pub fn step(&self) -> Result<Revision,Box<dyn Error>>
+    {
+        match self.state()?
+        {
+            SchedulerState::Idle | SchedulerState::Paused => self.next(),
+            state => Err(format!("scheduler for sim {:?} cannot step while it is {:?}", self.sim_id, state).into())
+        }
+    }
+
+    fn next(&self) -> Result<Revision,Box<dyn Error>>
+    {
+        let source = SYS.local.sources.get(&self.sim_id)?;
+        let revision = source.next()?;
+        Ok(revision)
+    }
+
+    // ask a running or paused scheduler to end its run, stopping an idle scheduler does nothing
+    pub fn stop(&self) -> Result<(),Box<dyn Error>>
+    {
+        let mut state = self.lock()?;
+        match *state
+        {
+            SchedulerState::Running | SchedulerState::Paused => *state = SchedulerState::Stopping,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+
+    use mechtron_common::id::Id;
+
+    use crate::scheduler::{Run, Scheduler, SchedulerState};
+
+    #[test]
+    fn runs_are_validated_up_front()
+    {
+        assert!(Scheduler::new(Id::new(0,1), Run::Cycles(-1)).is_err());
+        assert!(Scheduler::new(Id::new(0,1), Run::Rate(0.0)).is_err());
+        assert!(Scheduler::new(Id::new(0,1), Run::Rate(f64::NAN)).is_err());
+    }
+
+    // a run of zero cycles never revises the source, so no source is needed
+    #[test]
+    fn a_finished_run_leaves_the_scheduler_idle() -> Result<(),Box<dyn Error>>
+    {
+        let scheduler = Scheduler::new(Id::new(0,1), Run::Cycles(0))?;
+        scheduler.start()?;
+        assert_eq!(scheduler.state()?, SchedulerState::Idle);
+        scheduler.start()?;
+        assert_eq!(scheduler.state()?, SchedulerState::Idle);
+        Ok(())
+    }
+
+    // there is no source for this sim so the first cycle fails
+    #[test]
+    fn a_failed_run_leaves_the_scheduler_idle() -> Result<(),Box<dyn Error>>
+    {
+        let scheduler = Scheduler::new(Id::new(-1,-1), Run::Cycles(1))?;
+        assert!(scheduler.start().is_err());
+        assert_eq!(scheduler.state()?, SchedulerState::Idle);
+        Ok(())
+    }
+
+    #[test]
+    fn an_idle_scheduler_cannot_be_paused_or_resumed() -> Result<(),Box<dyn Error>>
+    {
+        let scheduler = Scheduler::new(Id::new(0,1), Run::Cycles(0))?;
+        assert!(scheduler.pause().is_err());
+        assert!(scheduler.resume().is_err());
+        scheduler.stop()?;
+        assert_eq!(scheduler.state()?, SchedulerState::Idle);
+        Ok(())
+    }
+}
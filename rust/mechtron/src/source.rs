@@ -1,32 +1,59 @@
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 
 use no_proto::memory::NP_Memory_Owned;
 
 use mechtron_common::artifact::{Artifact, ArtifactCacher};
 use mechtron_common::buffers::{get, set};
-use mechtron_common::configs::{Configs, SimConfig};
+use mechtron_common::configs::{Configs, ContentRetention, SimConfig};
 use mechtron_common::content::{Content, ReadOnlyContent};
-use mechtron_common::id::{ContentKey, Id};
+use mechtron_common::error::GuestError;
+use mechtron_common::id::{ContentKey, Id, IdSeq};
 use mechtron_common::id::Revision;
 use mechtron_common::id::TronKey;
 use mechtron_common::message::{Cycle, InterDeliveryType, Message, MessageKind, Payload, To};
 use mechtron_common::revision::Revision;
 
-use crate::app::SYS;
-use crate::content::{Content, ContentIntake, ContentRetrieval, InterCyclicContentStructure, IntraCyclicContentStructure};
-use crate::message::{MessageIntake, MessagingStructure};
+use crate::app::{LocalMessageRouter, SYS};
+use crate::content::{Content, ContentAccess, ContentIntake, ContentRetrieval, InterCyclicContentStructure, IntraCyclicContentStructure, ReadOnlyContentIntake};
+use crate::message::{IntraCyclicMessagingStructure, MessageIntake, MessageRouter, MessagingStructure, Tracking, Transactions, validate_delivery_cycle};
 use crate::nucleus::{NeuTron, NucleiStore};
-use crate::tron::{Context, CreatePayloadsBuilder, init_tron, init_tron_of_kind, Neutron, Tron, TronShell};
+use crate::checkpoint::Checkpoint;
+use crate::incident::{Incident, TronFailure};
+use crate::lookup::LookupCache;
+use crate::replay::MessageLog;
+use crate::store::{ContentStore, FileSystemContentStore};
+use crate::wasm::LimitExceeded;
+use crate::tron::{Context, CreatePayloadsBuilder, MechtronError, init_tron, init_tron_of_kind, Neutron, SIMTRON, SIMULATION_NUCLEUS, Tron, tron_content, TronShell};
 
 pub struct Source
 {
     sim_id: Id,
+    sim_config: Arc<SimConfig>,
     pub content: InterCyclicContentStructure,
-    pub messaging: MessagingStructure,
-    pub head: Revision,
+    pub messaging: RwLock<MessagingStructure>,
+    head: RwLock<Revision>,
+    // held while a revision is being made so a source shared by a scheduler is only ever
+    // revised one cycle at a time
+    cycling: Mutex<()>,
+    phases: Arc<Vec<String>>,
+    transactions: Arc<Transactions>,
+    router: LocalMessageRouter,
+    retention: ContentRetention,
+    checkpoint_dir: Option<String>,
+    clock: Clock,
+    message_log: Option<Arc<MessageLog>>,
+    replaying: bool,
+    incidents: Mutex<Vec<Incident>>,
+    // every id issued inside the simulation comes from its own sequence so a run issues the
+    // same ids no matter what else the process is doing
+    id_seq: Arc<IdSeq>,
 }
 
+// the phase every simulation runs when it does not declare any of its own
+pub static DEFAULT_PHASE: &'static str = "default";
+
 pub fn timestamp()->i64
 {
     let timestamp = since_the_epoch.as_secs() * 1000 +
@@ -35,108 +62,306 @@ pub fn timestamp()->i64
     return timestamp;
 }
 
+// where the timestamps of a revision come from.  a logical clock uses the cycle so that
+// a run produces exactly the same content every time it is run
+#[derive(Clone)]
+pub enum Clock
+{
+    Wall,
+    Logical
+}
+
+impl Clock
+{
+    pub fn timestamp( &self, revision: &Revision )->i64
+    {
+        match self
+        {
+            Clock::Wall => timestamp(),
+            Clock::Logical => revision.cycle
+        }
+    }
+}
+
 impl Source
 {
-    pub fn launch(sim_config:Arc<SimConfig>)->Result<Self,Box<dyn Error>> {
+    pub fn launch(sim_config:Arc<SimConfig>, store: Option<Arc<dyn ContentStore>>)->Result<Self,Box<dyn Error>> {
         sim_config.cache(&mut SYS.local.configs)?;
 
         let sim_id = SYS.net.id_seq.next();
+        let id_seq = IdSeq::new(sim_id.id);
 
-        let mut source = Source {
-            sim_id: sim_id,
-            content: InterCyclicContentStructure::new(),
-            messaging: MessagingStructure::new(),
-            head: Revision { cycle: 0 }
-        };
+        let mut source = Source::init(sim_id, &sim_config, store, id_seq);
 
-        source.bootstrap(sim_config);
+        source.bootstrap(sim_config)?;
 
         Ok(source)
     }
 
-    fn bootstrap(&mut self, sim_config: Arc<SimConfig>) -> Result<(), Box<dyn Error>>
-    {
-        let timestamp = timestamp();
+    // launch a simulation that keeps its content in 'dir' and writes a checkpoint there at the
+    // end of every cycle so it can be continued with resume()
+    pub fn launch_with_checkpoints(sim_config:Arc<SimConfig>, dir: String)->Result<Self,Box<dyn Error>> {
+        sim_config.cache(&mut SYS.local.configs)?;
+
+        if Checkpoint::exists(&dir)
+        {
+            return Err(format!("{} already contains a checkpoint, resume it or choose another directory",dir).into());
+        }
 
-        let nucleus_id = SYS.net.id_seq.next();
-        self.add_nuclues(nucleus_id.clone());
+        let sim_id = SYS.net.id_seq.next();
+        let id_seq = IdSeq::new(sim_id.id);
+        let store = Arc::new(FileSystemContentStore::new(dir.clone())?);
 
-        let neutron_config = SYS.local.configs.core_tron_config("tron/neutron")?;
-        let neutron_key = TronKey::new(nucleus_id: nucleus_id.clone(), Id::new(SYS.net.id_seq().clone(), 0));
+        let mut source = Source::init(sim_id, &sim_config, Option::Some(store), id_seq);
+        source.checkpoint_dir = Option::Some(dir);
 
-        let context = Context {
-            sim_id: sim_id.clone(),
-            id: neutron_key.clone(),
-            revision: self.head.clone(),
-            tron_config: neutron_config.clone(),
-            timestamp: timestamp.clone(),
-        };
+        source.bootstrap(sim_config)?;
+
+        Ok(source)
+    }
+
+    // launch a deterministic simulation that records its content and every message delivered
+    // in each cycle to 'dir' so that it can later be replayed
+    pub fn launch_recorded(sim_config:Arc<SimConfig>, dir: String)->Result<Self,Box<dyn Error>> {
+        sim_config.cache(&mut SYS.local.configs)?;
+
+        let sim_id = SYS.net.id_seq.next();
+        let id_seq = IdSeq::new(sim_id.id);
 
-        // first we create a neutron for the simulation nucleus
-        let mut neutron_create_payload_builder = CreatePayloadsBuilder::new(&SYS.local.configs, &neutron_config)?;
+        let log = Arc::new(MessageLog::new(&dir)?);
+        log.write_header(&sim_config.source, id_seq.seq_id())?;
 
-        let create = Message::multi_payload(&mut SYS.net.id_seq,
-                                            MesssageKind::Create,
-                                            mechtron_common::message::From { tron: context.id.clone(), cycle: 0, timestamp },
-                                            To {
-                                                tron: context.id.clone(),
-                                                port: "create".to_string(),
-                                                cycle: Cycle::Next,
-                                                phase: 0,
-                                                inter_delivery_type: InterDeliveryType::Cyclic,
-                                            },
-                                            CreatePayloadsBuilder::payloads(&SYS.local.configs, neutron_create_payload_builder));
+        let store = Arc::new(FileSystemContentStore::new(dir)?);
 
+        let mut source = Source::init(sim_id, &sim_config, Option::Some(store), id_seq);
+        source.clock = Clock::Logical;
+        source.message_log = Option::Some(log);
 
-        let neutron_content_artifact = neutron_config.content.unwrap().artifact;
-        let mut content = Content::new(&SYS.local.configs, neutron_content_artifact.clone() );
+        source.bootstrap(sim_config)?;
 
-        let neutron = TronShell::new( init_tron_of_kind("neutron", &context )? );
-        neutron.create(&context,&mut content,&create);
+        Ok(source)
+    }
+
+    // launch a simulation whose messages are read from a recorded log instead of being delivered from its own pipeline
+    pub fn launch_replay(sim_config:Arc<SimConfig>, store: Arc<dyn ContentStore>, log: Arc<MessageLog>)->Result<Self,Box<dyn Error>> {
+        sim_config.cache(&mut SYS.local.configs)?;
+
+        // the replay issues the same ids as the recorded run by drawing from the same sequence
+        let (_, seq_id) = log.header()?;
+        let sim_id = SYS.net.id_seq.next();
 
-        let content_key = ContentKey {
-            tron_id: context.id,
-            revision: revision,
+        let mut source = Source::init(sim_id, &sim_config, Option::Some(store), IdSeq::new(seq_id));
+        source.clock = Clock::Logical;
+        source.message_log = Option::Some(log);
+        source.replaying = true;
+
+        source.bootstrap(sim_config)?;
+
+        Ok(source)
+    }
+
+    // continue a simulation from the last checkpoint written to 'dir'.  checkpoints continue to be written there
+    pub fn resume(dir: String)->Result<Self,Box<dyn Error>> {
+        let checkpoint = Checkpoint::read(&dir, &SYS.net.id_seq, &SYS.local.configs)?;
+
+        SYS.local.configs.sim_config_keeper.cache(&checkpoint.sim_config)?;
+        let sim_config = SYS.local.configs.sim_config_keeper.get(&checkpoint.sim_config)?;
+        sim_config.cache(&mut SYS.local.configs)?;
+
+        let id_seq = IdSeq::new(checkpoint.id_seq);
+        id_seq.resume(checkpoint.next_id);
+
+        let store = Arc::new(FileSystemContentStore::new(dir.clone())?);
+
+        let mut source = Source::init(checkpoint.sim_id, &sim_config, Option::Some(store), id_seq);
+        source.checkpoint_dir = Option::Some(dir);
+        source.content.restore(&checkpoint.head, &SYS.local.configs)?;
+        source.messaging.read()?.restore(checkpoint.pending)?;
+        source.transactions.restore(checkpoint.transactions)?;
+        source.head = RwLock::new(checkpoint.head);
+
+        Ok(source)
+    }
+
+    // capture the state of this source at the current head.  must be called between cycles
+    pub fn checkpoint(&self)->Result<Checkpoint,Box<dyn Error>>
+    {
+        Ok(Checkpoint{
+            sim_config: self.sim_config.source.clone(),
+            sim_id: self.sim_id.clone(),
+            head: self.head()?,
+            id_seq: self.id_seq.seq_id(),
+            next_id: self.id_seq.peek(),
+            pending: self.messaging.read()?.pending()?,
+            transactions: self.transactions.outstanding()?
+        })
+    }
+
+    // resume a simulation from the last revision committed to its content store
+    pub fn restore(sim_config:Arc<SimConfig>, store: Arc<dyn ContentStore>)->Result<Self,Box<dyn Error>> {
+        sim_config.cache(&mut SYS.local.configs)?;
+
+        let (sim_id, head) = match store.head()?
+        {
+            None => return Err("content store does not have a committed revision to resume from".into()),
+            Some(head) => head
         };
 
-        self.content.intake(content, content_key)?;
+        // the content store keeps no record of the sequence, so ids continue past every id in use at the head
+        let id_seq = IdSeq::new(sim_id.id);
+        for key in store.keys(&head)?
+        {
+            for id in vec![key.tron_id.nucleus_id, key.tron_id.tron_id]
+            {
+                if id.seq_id == id_seq.seq_id()
+                {
+                    id_seq.resume(id.id + 1);
+                }
+            }
+        }
 
-        // now we send a request to the neutron to create the simtron
+        let mut source = Source::init(sim_id, &sim_config, Option::Some(store), id_seq);
+        source.content.restore(&head, &SYS.local.configs)?;
+        source.head = RwLock::new(head);
+
+        Ok(source)
+    }
+
+    fn init(sim_id: Id, sim_config: &Arc<SimConfig>, store: Option<Arc<dyn ContentStore>>, id_seq: IdSeq)->Self {
+        Source {
+            sim_id: sim_id,
+            sim_config: sim_config.clone(),
+            content: match store {
+                None => InterCyclicContentStructure::new(),
+                Some(store) => InterCyclicContentStructure::with_store(store)
+            },
+            messaging: RwLock::new(MessagingStructure::new(sim_config.delivery_horizon_cycles)),
+            head: RwLock::new(Revision { cycle: 0 }),
+            cycling: Mutex::new(()),
+            phases: Arc::new(match sim_config.phases.is_empty() {
+                true => vec![DEFAULT_PHASE.to_string()],
+                false => sim_config.phases.clone()
+            }),
+            transactions: Arc::new(Transactions::new(sim_config.request_timeout_cycles)),
+            router: LocalMessageRouter::new(),
+            retention: sim_config.retention.clone(),
+            checkpoint_dir: Option::None,
+            clock: match sim_config.deterministic {
+                true => Clock::Logical,
+                false => Clock::Wall
+            },
+            message_log: Option::None,
+            replaying: false,
+            incidents: Mutex::new(vec!()),
+            id_seq: Arc::new(id_seq)
+        }
+    }
+
+    // the simulation nucleus is bootstrapped the same way as any other nucleus, by sending a
+    // Create to its neutron.  the SimTron is then created in that nucleus and creates the rest
+    // of the simulation from the sim config
+    fn bootstrap(&mut self, sim_config: Arc<SimConfig>) -> Result<(), Box<dyn Error>>
+    {
+        let head = self.head()?;
+        let timestamp = self.clock.timestamp(&head);
 
-        let simtron_config = SYS.local.configs.core_tron_config("trons/sim")?;
+        let nucleus_id = self.id_seq.next();
+        self.add_nucleus(nucleus_id.clone())?;
+
+        let neutron_key = Neutron::key(&nucleus_id);
+        let from = mechtron_common::message::From { tron: neutron_key.clone(), cycle: head.cycle, timestamp };
+
+        let neutron_create = Neutron::create_payloads(&SYS.local.configs, Option::Some(SIMULATION_NUCLEUS), &nucleus_id)?;
+        let message = Message::multi_payload(&self.id_seq,
+                                             MessageKind::Create,
+                                             from.clone(),
+                                             To::basic( neutron_key.clone(), "create".to_string() ),
+                                             neutron_create);
+        self.messaging.read()?.cyclic_intake().intake(message)?;
+
+        let simtron_config = SYS.local.configs.core_tron_config("tron/sim")?;
         let mut sim_create_payload_builder = CreatePayloadsBuilder::new(&SYS.local.configs, &simtron_config)?;
-        sim_create_payload_builder.set_lookup_name("simtron");
+        sim_create_payload_builder.set_lookup_name(SIMTRON)?;
         set(&mut sim_create_payload_builder.constructor, &[&"sim_config_artifact"], sim_config.source.to())?;
 
-
-        let message = Message::multi_payload(&mut SYS.net.id_seq,
-                                             MesssageKind::Create,
-                                             mechtron_common::message::From { tron: context.id.clone(), cycle: 0, timestamp },
-                                             To::basic(
-                                                 neutron_key.clone(),
-                                                 "create".to_string(),
-                                             ),
+        let message = Message::multi_payload(&self.id_seq,
+                                             MessageKind::Create,
+                                             from,
+                                             To::basic( neutron_key, "create".to_string() ),
                                              CreatePayloadsBuilder::payloads(&SYS.local.configs, sim_create_payload_builder));
+        self.messaging.read()?.cyclic_intake().intake(message)?;
 
-
-        self.messaging.cyclic_intake().intake(message)?;
-        self.revise( revision.clone(), Revision{ cycle: 1 });
+        self.next()?;
 
         Ok(())
     }
 
-    pub fn id(&self)->i64
+    pub fn id(&self)->&Id
+    {
+        &self.sim_id
+    }
+
+    // the last revision this source completed
+    pub fn head(&self)->Result<Revision,Box<dyn Error>>
+    {
+        Ok(self.head.read()?.clone())
+    }
+
+    // the incidents of every revision since they were last taken
+    pub fn take_incidents(&self)->Result<Vec<Incident>,Box<dyn Error>>
     {
-        self.id
+        let mut incidents = self.incidents.lock()?;
+        Ok(incidents.drain(..).collect())
     }
 
-    pub fn add_nucleus(&mut self, nucleus_id: Id, nuctron_id: Id) -> Result<(),Box<dyn Error>>
+    // a nucleus has no content until its neutron has been created, so adding one only
+    // makes it reachable.  the Create to its neutron brings it to life in the next revision
+    pub fn add_nucleus(&self, nucleus_id: Id) -> Result<(),Box<dyn Error>>
     {
-        self.content.create(&TronKey::new(nucleus_id, nuctron_id ) )?;
+        self.router.register(nucleus_id, self.messaging.read()?.cyclic_intake())?;
         return Ok(())
     }
 
-    pub fn revise( &mut self, from: Revision, to: Revision )->Result<(),Box<dyn Error>>
+    // revise from the current head to the next cycle and advance the head.  trons read the
+    // content of earlier cycles through the source while it is being revised, so only the
+    // revising itself is serialized
+    pub fn next( &self )->Result<Revision,Box<dyn Error>>
+    {
+        let _cycling = self.cycling.lock()?;
+
+        let from = self.head()?;
+        let to = Revision{ cycle: from.cycle+1 };
+        self.revise( from, to.clone() )?;
+        *self.head.write()? = to.clone();
+
+        if let Option::Some(dir) = &self.checkpoint_dir
+        {
+            self.checkpoint()?.write(dir)?;
+        }
+
+        // pruning waits for the checkpoint so the content it resumes from is never removed before it is replaced
+        self.content.prune(&self.retention)?;
+
+        Ok(to)
+    }
+
+    pub fn phase_index( &self, name: &str )->Result<u8,Box<dyn Error>>
+    {
+        match self.phases.iter().position(|phase| phase.as_str() == name )
+        {
+            None => Err(format!("unknown phase '{}', the phases declared for this simulation are: {:?}", name, self.phases).into()),
+            Some(index) => Ok(index as u8)
+        }
+    }
+
+    // a source is quiescent when it has no messages waiting to be delivered and no Requests
+    // waiting on a Response, every one of which will produce a timeout Reject if unanswered
+    pub fn is_quiescent( &self )->Result<bool,Box<dyn Error>>
+    {
+        Ok(!self.messaging.read()?.has_pending()? && !self.transactions.has_outstanding()?)
+    }
+
+    pub fn revise( &self, from: Revision, to: Revision )->Result<(),Box<dyn Error>>
     {
         if from.cycle != to.cycle - 1
         {
@@ -145,30 +370,88 @@ impl Source
 
         let context = RevisionContext{
             revision: to.clone(),
-            timestamp: timestamp(),
-            phase: "dunno".to_string()
+            timestamp: self.clock.timestamp(&to),
+            phases: self.phases.clone(),
+            phase: 0,
+            delivery_horizon: self.sim_config.delivery_horizon_cycles,
+            id_seq: self.id_seq.clone()
+        };
+
+        // messages that arrived since the last revision are placed in the chambers for the moment they are to be delivered
+        self.messaging.write()?.flood(&from)?;
+
+        let mut nucleus_ids = self.content.query_nuclei(&from)?;
+        for nucleus_id in self.messaging.read()?.nuclei_due(&to)?
+        {
+            nucleus_ids.insert(nucleus_id);
+        }
+        let mut nucleus_ids: Vec<Id> = nucleus_ids.into_iter().collect();
+        nucleus_ids.sort();
+
+        // when replaying, the messages delivered are the ones recorded rather than those in the chambers
+        let logged = match (&self.message_log, self.replaying) {
+            (Some(log), true) => Option::Some(log.read(&to, &self.id_seq, &SYS.local.configs)?),
+            _ => Option::None
         };
+        let mut delivered = vec!();
 
         let mut nuclei = vec!();
-        for nucleus_id in self.content.query_nuclei(&to)?
+        for nucleus_id in nucleus_ids
         {
-            nuclei.push(Nucleus::init(nucleus_id.clone(), context.clone()));
+            self.router.register(nucleus_id.clone(), self.messaging.read()?.cyclic_intake())?;
+            nuclei.push(Nucleus::init(self.sim_id.clone(), nucleus_id.clone(), context.clone(), self.transactions.clone()));
         }
 
+        let mut outbound = vec!();
         for mut nucleus in nuclei{
-            for (content,content_key) in self.content.query_nucleus_content(nucleus_id, &to, &SYS.local.configs )?
+            for (content,content_key) in self.content.query_nucleus_content(&nucleus.id, &from, &SYS.local.configs )?
             {
                 nucleus.content.intake(content,content_key)?;
             }
-            for message in self.messaging.query_messages(nucleus_id, &SYS.local.configs )?
+            let mut messages = self.messaging.write()?.query_messages(&nucleus.id, &to )?;
+            if let Option::Some(logged) = &logged
+            {
+                messages = logged.iter().filter(|message| message.to.tron.nucleus_id == nucleus.id ).cloned().collect();
+            }
+            for message in messages
             {
+                delivered.push(message.clone());
                 nucleus.messaging.intake(message)?;
             }
             nucleus.update()?;
+
+            let (contents, mut messages, mut incidents) = nucleus.finish()?;
+            for (content,content_key) in contents
+            {
+                self.content.put(content,content_key)?;
+            }
+            outbound.append(&mut messages);
+            self.incidents.lock()?.append(&mut incidents);
         }
 
+        if let (Some(log), false) = (&self.message_log, self.replaying)
+        {
+            log.record(&to, &delivered)?;
+        }
+
+        outbound.append(&mut self.transactions.expire(&self.id_seq, &to, context.timestamp)?);
+
+        // a Create addressed to a neutron brings a new nucleus to life, it must be reachable before the Create is routed
+        for message in &outbound
+        {
+            if let MessageKind::Create = message.kind
+            {
+                if message.to.tron == Neutron::key(&message.to.tron.nucleus_id)
+                {
+                    self.add_nucleus(message.to.tron.nucleus_id.clone())?;
+                }
+            }
+        }
 
+        // messages may cross from one nucleus to another, so routing waits until every nucleus has been revised
+        self.router.send(outbound)?;
 
+        self.content.commit(&self.sim_id, &to)?;
 
         Ok(())
     }
@@ -177,65 +460,609 @@ impl Source
 struct Nucleus
 {
     id: Id,
+    sim_id: Id,
     content: IntraCyclicContentStructure,
+    messaging: IntraCyclicMessagingStructure,
     context: RevisionContext,
+    transactions: Arc<Transactions>,
+    // shared by every tron in the nucleus and discarded with the nucleus at the end of the cycle
+    lookups: Arc<LookupCache>,
+    // each tron is instantiated the first time it is called and kept for the life of the nucleus
+    trons: HashMap<TronKey,Arc<TronShell>>,
+    // trons that could not be instantiated and why, they fail every call for the life of the nucleus
+    failed: HashMap<TronKey,String>,
+    incidents: Vec<Incident>,
+    outbound: Vec<Message>
 }
 
 impl Nucleus
 {
-    fn init(id: Id, context: RevisionContext, ) -> Self
+    fn init(sim_id: Id, id: Id, context: RevisionContext, transactions: Arc<Transactions>) -> Self
     {
         Nucleus {
             id: id,
-            content: IntraCyclicContentStructure::new(revision),
-            context: context
+            sim_id: sim_id,
+            content: IntraCyclicContentStructure::new(context.revision.clone()),
+            messaging: IntraCyclicMessagingStructure::new(),
+            lookups: Arc::new(LookupCache::new(context.revision.clone())),
+            context: context,
+            transactions: transactions,
+            trons: HashMap::new(),
+            failed: HashMap::new(),
+            incidents: vec!(),
+            outbound: vec!()
+        }
+    }
+
+    // run every phase of this cycle in order.  at the start of each phase the messages
+    // addressed to that phase are delivered, then each tron that declares an update for
+    // the phase has its update function invoked
+    fn update(&mut self) -> Result<(), Box<dyn Error>>
+    {
+        for phase in 0..self.context.phases.len()
+        {
+            self.context.phase = phase as u8;
+
+            let messages = self.messaging.take(phase as u8);
+            self.deliver(messages)?;
+
+            for tron_id in self.content.tron_ids()
+            {
+                let tron_key = TronKey::new(self.id.clone(), tron_id);
+                let context = self.context(&tron_key)?;
+                let tron = match self.tron(&context) {
+                    None => continue,
+                    Some(tron) => tron
+                };
+                let content = self.content.get(&ContentKey{ tron_id: tron_key.clone(), revision: self.context.revision.clone() })?;
+                let messages = match tron.update(&context, content, self.context.phase())
+                {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        Nucleus::failed(&mut self.incidents, &context, e);
+                        Option::None
+                    }
+                };
+                if messages.is_some()
+                {
+                    self.route(messages.unwrap())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // phasic messages addressed to a later phase of this cycle in this nucleus are held and
+    // delivered before that phase begins.  everything else leaves the nucleus at the end of the cycle.
+    // a message that can never be delivered is rejected back to its sender and the cycle carries on
+    fn route(&mut self, messages: Vec<Message>) -> Result<(), Box<dyn Error>>
+    {
+        let mut rejects = vec!();
+        for message in messages
+        {
+            let id = message.id.clone();
+            let message = match self.transactions.track(message)? {
+                Tracking::Route(message) => message,
+                Tracking::Drop(reason) => {
+                    self.incidents.push(Incident::MessageDropped{ revision: self.context.revision.clone(), message: id, reason: reason });
+                    continue;
+                },
+                Tracking::Reject(message, reason) => {
+                    if let Option::Some(reject) = self.refuse(&message, reason)
+                    {
+                        rejects.push(reject);
+                    }
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.validate_route(&message)
+            {
+                if let Option::Some(reject) = self.refuse(&message, e.to_string())
+                {
+                    rejects.push(reject);
+                }
+                continue;
+            }
+
+            match message.to.inter_delivery_type
+            {
+                InterDeliveryType::Phasic => self.messaging.intake(message)?,
+                InterDeliveryType::Cyclic => self.outbound.push(message)
+            }
         }
+
+        // a Reject is never rejected in turn, so these are routed at most once more
+        if rejects.is_empty()
+        {
+            return Ok(());
+        }
+        self.route(rejects)
     }
 
+    // check that a message can be delivered from where it is being routed this phase
+    fn validate_route(&self, message: &Message) -> Result<(), Box<dyn Error>>
+    {
+        // every nucleus runs the same phases so a phase that does not exist here will
+        // not exist in any other nucleus either, the message could never be delivered
+        if message.to.phase as usize >= self.context.phases.len()
+        {
+            return Err(format!("message {:?} addressed to phase {} but there are only {} phases", message.id, message.to.phase, self.context.phases.len()).into());
+        }
+
+        match message.to.inter_delivery_type
+        {
+            InterDeliveryType::Phasic => {
+                if message.to.tron.nucleus_id != self.id
+                {
+                    return Err(format!("phasic message {:?} cannot be delivered to another nucleus {:?}", message.id, message.to.tron.nucleus_id).into());
+                }
+                if message.to.phase <= self.context.phase
+                {
+                    return Err(format!("phasic message {:?} addressed to phase {} which is not after the current phase {}", message.id, message.to.phase, self.context.phase).into());
+                }
+                Ok(())
+            }
+            InterDeliveryType::Cyclic => validate_delivery_cycle(message, &self.context.revision, self.context.delivery_horizon)
+        }
+    }
 
-    fn process(&mut self, message: &Message) -> Result<(), Box<dyn Error>>
+    // a message that cannot be routed is reported and rejected back to its sender on behalf of
+    // the tron it was addressed to.  a Reject that cannot be routed is dropped
+    fn refuse(&mut self, message: &Message, reason: String) -> Option<Message>
     {
         match message.kind {
-            MessageKind::Create => self.process_create( message),
-            _ => Err("not implemented yet".into())
+            MessageKind::Reject => {
+                self.incidents.push(Incident::MessageDropped{ revision: self.context.revision.clone(), message: message.id.clone(), reason: reason });
+                Option::None
+            },
+            _ => {
+                let from = mechtron_common::message::From { tron: message.to.tron.clone(), cycle: self.context.revision.cycle, timestamp: self.context.timestamp };
+                let reject = message.reject(&self.context.id_seq, from, reason.as_str());
+                self.incidents.push(Incident::MessageRejected{ revision: self.context.revision.clone(), message: message.id.clone(), reason: reason });
+                Option::Some(reject)
+            }
+        }
+    }
+
+    // the context a tron is handed with every call it receives this cycle
+    fn context(&self, tron_key: &TronKey) -> Result<Context, Box<dyn Error>>
+    {
+        let content = self.content.get(&ContentKey{ tron_id: tron_key.clone(), revision: self.context.revision.clone() })?;
+        let artifact = Artifact::from(get::<String,NP_Memory_Owned>(&content.meta, &[&"artifact"])?.as_str())?;
+        let (tron_config, mechtron_config) = self.context.configs().tron_config_of(&artifact)?;
+
+        let context = Context {
+            sim_id: self.sim_id.clone(),
+            id: tron_key.clone(),
+            revision: self.context.revision.clone(),
+            tron_config: tron_config.clone(),
+            mechtron_config: mechtron_config,
+            timestamp: self.context.timestamp.clone(),
+            lookups: self.lookups.clone(),
+            id_seq: self.context.id_seq.clone(),
+        };
+
+        Ok(context)
+    }
+
+    // a wasm tron keeps its instance, and the limits it has used, from one call to the next.
+    // a tron that cannot be instantiated fails only itself and None is returned for it
+    fn tron(&mut self, context: &Context) -> Option<Arc<TronShell>>
+    {
+        if let Option::Some(tron) = self.trons.get(&context.id)
+        {
+            return Option::Some(tron.clone());
         }
+        if self.failed.contains_key(&context.id)
+        {
+            return Option::None;
+        }
+        match init_tron(&context.tron_config, context)
+        {
+            Ok(tron) => {
+                let tron = Arc::new(TronShell::new(tron));
+                self.trons.insert(context.id.clone(), tron.clone());
+                Option::Some(tron)
+            },
+            Err(e) => {
+                let reason = format!("could not instantiate tron {:?} ({}): {}", context.id, context.source().to(), e);
+                self.incidents.push(Incident::TronFailed{ revision: self.context.revision.clone(), tron: context.id.clone(), failure: TronFailure::Init(reason.clone()) });
+                self.failed.insert(context.id.clone(), reason);
+                Option::None
+            }
+        }
+    }
+
+    // hand back the revised content, the messages bound for a future cycle and what went wrong along the way
+    fn finish(mut self) -> Result<(Vec<(Content,ContentKey)>,Vec<Message>,Vec<Incident>), Box<dyn Error>>
+    {
+        let contents = self.content.drain(&self.id)?;
+        Ok((contents, self.outbound, self.incidents))
+    }
+
+    // Create and Destroy messages are handled by the neutron, all other messages are grouped by
+    // tron and port and handed to the port handler the destination tron provides.  messages
+    // to trons that do not exist, including trons destroyed this cycle, are rejected
+    fn deliver(&mut self, messages: Vec<Message>) -> Result<(), Box<dyn Error>>
+    {
+        let mut creates = vec!();
+        let mut destroys = vec!();
+        // ordered so that trons receive their messages in the same order every run
+        let mut trons: BTreeMap<TronKey,BTreeMap<String,Vec<Message>>> = BTreeMap::new();
+        for message in messages
+        {
+            match message.kind {
+                MessageKind::Create => creates.push(message),
+                MessageKind::Destroy => destroys.push(message),
+                _ => {
+                    if !trons.contains_key(&message.to.tron)
+                    {
+                        trons.insert(message.to.tron.clone(), BTreeMap::new());
+                    }
+                    let ports = trons.get_mut(&message.to.tron).unwrap();
+                    if !ports.contains_key(&message.to.port)
+                    {
+                        ports.insert(message.to.port.clone(), vec!());
+                    }
+                    ports.get_mut(&message.to.port).unwrap().push(message);
+                }
+            }
+        }
+
+        for create in creates
+        {
+            self.process_create(&create)?;
+        }
+
+        let mut outbound = vec!();
+        for destroy in destroys
+        {
+            if let Option::Some(reject) = self.process_destroy(&destroy)?
+            {
+                outbound.push(reject);
+            }
+        }
+
+        for (tron_key, ports) in trons
+        {
+            if !self.content.contains(&tron_key.tron_id)
+            {
+                for (_, messages) in ports
+                {
+                    for message in messages
+                    {
+                        if let Option::Some(reject) = self.reject_missing(&message)
+                        {
+                            outbound.push(reject);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let context = self.context(&tron_key)?;
+            let tron = match self.tron(&context) {
+                Some(tron) => tron,
+                None => {
+                    let reason = self.failed.get(&tron_key).cloned().unwrap_or_default();
+                    let from = mechtron_common::message::From { tron: tron_key.clone(), cycle: self.context.revision.cycle, timestamp: self.context.timestamp };
+                    for (_, messages) in ports
+                    {
+                        for message in messages
+                        {
+                            if let Option::Some(reject) = Nucleus::reject(&mut self.incidents, &context, from.clone(), &message, reason.as_str())
+                            {
+                                outbound.push(reject);
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+            let content = self.content.get(&ContentKey{ tron_id: tron_key.clone(), revision: self.context.revision.clone() })?;
+            for (port, messages) in ports
+            {
+                let mut accepted = vec!();
+                for message in messages
+                {
+                    match Nucleus::validate_inbound(&context, &message)
+                    {
+                        Ok(_) => accepted.push(message),
+                        Err(e) => {
+                            if let Option::Some(reject) = Nucleus::reject(&mut self.incidents, &context, tron.from(&context), &message, e.to_string().as_str())
+                            {
+                                outbound.push(reject);
+                            }
+                        }
+                    }
+                }
+
+                if accepted.is_empty()
+                {
+                    continue;
+                }
+
+                match tron.receive(&context, content, port.as_str(), &accepted)
+                {
+                    Ok(Some(mut messages)) => outbound.append(&mut messages),
+                    Ok(None) => {},
+                    Err(e) => {
+                        // the messages the tron could not handle are rejected back to their senders
+                        let reason = Nucleus::failed(&mut self.incidents, &context, e);
+                        for message in accepted
+                        {
+                            if let Option::Some(reject) = Nucleus::reject(&mut self.incidents, &context, tron.from(&context), &message, reason.as_str())
+                            {
+                                outbound.push(reject);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.route(outbound)
+    }
+
+    // a tron that goes past the limits of its mechtron, whose guest returns an error or panics,
+    // or that sends a message the host cannot build, fails only the call it was making.  the
+    // failure is reported and the cycle carries on
+    fn failed(incidents: &mut Vec<Incident>, context: &Context, error: Box<dyn Error>) -> String
+    {
+        let failure = if let Some(limit_exceeded) = error.downcast_ref::<LimitExceeded>()
+        {
+            TronFailure::LimitExceeded(limit_exceeded.clone())
+        }
+        else if let Some(mechtron_error) = error.downcast_ref::<MechtronError>()
+        {
+            TronFailure::Guest(mechtron_error.clone())
+        }
+        else if let Some(guest_error) = error.downcast_ref::<GuestError>()
+        {
+            TronFailure::Guest(MechtronError{ tron: context.id.clone(), source: context.source(), call: "unknown".to_string(), error: guest_error.clone() })
+        }
+        else
+        {
+            TronFailure::Call(error.to_string())
+        };
+        let reason = format!("tron {:?} ({}) failed: {}", context.id, context.source().to(), failure);
+        incidents.push(Incident::TronFailed{ revision: context.revision.clone(), tron: context.id.clone(), failure: failure });
+        reason
     }
 
+    fn reject(incidents: &mut Vec<Incident>, context: &Context, from: mechtron_common::message::From, message: &Message, reason: &str) -> Option<Message>
+    {
+        match message.kind {
+            // never reject a reject
+            MessageKind::Reject => {
+                incidents.push(Incident::MessageDropped{ revision: context.revision.clone(), message: message.id.clone(), reason: format!("reject addressed to tron {:?}: {}", context.id, reason) });
+                Option::None
+            },
+            _ => Option::Some(message.reject(&context.id_seq, from, reason))
+        }
+    }
+
+    // check the message against the inbound port declared in the destination tron's config.
+    // trons that declare no inbound ports accept anything, but a Reject is only ever delivered
+    // to a tron that declares a port to receive it.  a port receives one payload for each
+    // artifact it declares, except for a Reject which carries its reason in meta
+    fn validate_inbound(context: &Context, message: &Message) -> Result<(), Box<dyn Error>>
+    {
+        let must_declare = match message.kind {
+            MessageKind::Reject => true,
+            _ => false
+        };
+
+        let inbound = match &context.tron_config.messages {
+            None => vec!(),
+            Some(messages) => messages.inbound.clone()
+        };
+
+        match inbound.iter().find(|port| port.name == message.to.port )
+        {
+            None => {
+                if inbound.is_empty() && !must_declare
+                {
+                    return Ok(());
+                }
+                Err(format!("tron {} does not declare an inbound port named '{}'", context.tron_config.source.to(), message.to.port).into())
+            }
+            Some(port) => {
+                if must_declare
+                {
+                    return Ok(());
+                }
+                if message.payloads.len() != port.artifact.len()
+                {
+                    return Err(format!("port '{}' of tron {} receives {} payloads but message {:?} carries {}", port.name, context.tron_config.source.to(), port.artifact.len(), message.id, message.payloads.len()).into());
+                }
+                for payload in &message.payloads
+                {
+                    if !port.artifact.contains(&payload.artifact)
+                    {
+                        return Err(format!("port '{}' of tron {} does not accept payload artifact {}", port.name, context.tron_config.source.to(), payload.artifact.to()).into());
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // a Create that cannot be carried out is rejected back to its sender
     fn process_create(&mut self, message: &Message) -> Result<(), Box<dyn Error>>
     {
         // ensure this is addressed to a neutron
         if !Neutron::valid_neutron_id(message.to.tron.tron_id.clone())
         {
-            Err(format!("not a valid neutron id: {}", message.to.tron.tron_id.id.clone()).into())
+            let reason = format!("not a valid neutron id: {}", message.to.tron.tron_id.id.clone());
+            if let Option::Some(reject) = self.refuse(message, reason)
+            {
+                self.route(vec![reject])?;
+            }
+            return Ok(());
+        }
+
+        // the neutron reads the create meta and the constructor of the new tron from the two payloads
+        if message.payloads.len() != 2
+        {
+            let reason = format!("a Create carries a create meta and a constructor but message {:?} carries {} payloads", message.id, message.payloads.len());
+            if let Option::Some(reject) = self.refuse(message, reason)
+            {
+                self.route(vec![reject])?;
+            }
+            return Ok(());
+        }
+
+        // a nucleus comes to life when it receives the Create for its own neutron
+        if !self.content.contains(&message.to.tron.tron_id)
+        {
+            return self.create_neutron(message);
+        }
+
+        let context = self.context(&message.to.tron)?;
+        let content_key = ContentKey { tron_id: message.to.tron.clone(), revision: self.context.revision.clone() };
+        let neutron_content = self.content.get(&content_key)?;
+
+        let neutron = Neutron {};
+        let (tron_key, tron_content, messages) = match neutron.create_tron(&context, neutron_content, message)
+        {
+            Ok(created) => created,
+            Err(e) => {
+                let reason = Nucleus::failed(&mut self.incidents, &context, e);
+                let from = mechtron_common::message::From { tron: context.id.clone(), cycle: self.context.revision.cycle, timestamp: self.context.timestamp };
+                if let Option::Some(reject) = Nucleus::reject(&mut self.incidents, &context, from, message, reason.as_str())
+                {
+                    self.route(vec![reject])?;
+                }
+                return Ok(());
+            }
+        };
+
+        self.content.create(ContentKey { tron_id: tron_key, revision: self.context.revision.clone() }, tron_content)?;
+
+        if messages.is_some()
+        {
+            self.route(messages.unwrap())?;
+        }
+
+        Ok(())
+    }
+
+    // a Destroy addressed to the neutron tears down the whole nucleus, otherwise the
+    // neutron forgets the tron and its content is not carried into the next revision
+    fn process_destroy(&mut self, message: &Message) -> Result<Option<Message>, Box<dyn Error>>
+    {
+        if !self.content.contains(&message.to.tron.tron_id)
+        {
+            return Ok(self.reject_missing(message));
+        }
+
+        let neutron_key = Neutron::key(&self.id);
+        if message.to.tron == neutron_key
+        {
+            for tron_id in self.content.tron_ids()
+            {
+                self.content.destroy(&tron_id);
+            }
+            self.trons.clear();
+            return Ok(Option::None);
         }
 
-        let content_key = ContentKey { tron_id: message.to.tron.clone(), revision: Revision { cycle: context.cycle } };
+        let neutron_content = self.content.get(&ContentKey { tron_id: neutron_key, revision: self.context.revision.clone() })?;
+        Neutron {}.destroy_tron(neutron_content, &message.to.tron)?;
+        self.content.destroy(&message.to.tron.tron_id);
+        self.trons.remove(&message.to.tron);
 
-        let content = self.content.copy(&content_key)?;
+        Ok(Option::None)
+    }
+
+    // the Reject sent back for a message addressed to a tron that does not exist in this nucleus
+    fn reject_missing(&mut self, message: &Message) -> Option<Message>
+    {
+        let reason = format!("tron {:?} does not exist", message.to.tron);
+        match message.kind {
+            // never reject a reject
+            MessageKind::Reject => {
+                self.incidents.push(Incident::MessageDropped{ revision: self.context.revision.clone(), message: message.id.clone(), reason: reason });
+                Option::None
+            },
+            _ => {
+                let from = mechtron_common::message::From { tron: message.to.tron.clone(), cycle: self.context.revision.cycle, timestamp: self.context.timestamp };
+                Option::Some(message.reject(&self.context.id_seq, from, reason.as_str()))
+            }
+        }
+    }
+
+    fn create_neutron(&mut self, message: &Message) -> Result<(), Box<dyn Error>>
+    {
+        let neutron_config = self.context.configs().core_tron_config("tron/neutron")?;
+        let artifact = message.payload(0).and_then(|meta| get::<String,NP_Memory_Owned>(&meta.buffer, &[&"artifact"]));
+        let refused = match artifact {
+            Err(e) => Option::Some(format!("the Create for the neutron of nucleus {:?} is malformed: {}", self.id, e)),
+            Ok(artifact) if artifact != neutron_config.source.to() => Option::Some(format!("nucleus {:?} does not have a neutron yet and cannot create a tron of {}", self.id, artifact)),
+            Ok(_) => Option::None
+        };
+        if let Option::Some(reason) = refused
+        {
+            if let Option::Some(reject) = self.refuse(message, reason)
+            {
+                self.route(vec![reject])?;
+            }
+            return Ok(());
+        }
 
         let context = Context {
             sim_id: self.sim_id.clone(),
             id: message.to.tron.clone(),
-            revision: Revision { cycle: self.context.cycle },
+            revision: self.context.revision.clone(),
             tron_config: neutron_config.clone(),
+            mechtron_config: Option::None,
             timestamp: self.context.timestamp.clone(),
+            lookups: self.lookups.clone(),
+            id_seq: self.context.id_seq.clone(),
         };
 
+        let mut content = tron_content(&context, &neutron_config)?;
+        let neutron = Arc::new(TronShell::new(init_tron(&neutron_config, &context)?));
+        self.trons.insert(context.id.clone(), neutron.clone());
+        let messages = match neutron.create(&context, &mut content, message)
+        {
+            Ok(messages) => messages,
+            Err(e) => {
+                // the nucleus is left without a neutron and does not come to life
+                self.trons.remove(&context.id);
+                let reason = Nucleus::failed(&mut self.incidents, &context, e);
+                if let Option::Some(reject) = self.refuse(message, reason)
+                {
+                    self.route(vec![reject])?;
+                }
+                return Ok(());
+            }
+        };
 
+        self.content.create(ContentKey { tron_id: message.to.tron.clone(), revision: self.context.revision.clone() }, content)?;
 
-        let neutron = Neutron {};
-        neutron.create_tron(&context, message);
+        if messages.is_some()
+        {
+            self.route(messages.unwrap())?;
+        }
 
         Ok(())
     }
 }
 
 
+#[derive(Clone)]
 struct RevisionContext
 {
     revision: Revision,
     timestamp: i64,
-    phase: String,
+    phases: Arc<Vec<String>>,
+    phase: u8,
+    delivery_horizon: i64,
+    id_seq: Arc<IdSeq>
 }
 
 impl RevisionContext
@@ -255,6 +1082,10 @@ impl RevisionContext
     }
 
     pub fn phase(&self) -> &str {
-        self.phase.as_str()
+        self.phases[self.phase as usize].as_str()
+    }
+
+    pub fn phase_index(&self) -> u8 {
+        self.phase
     }
 }
\ No newline at end of file
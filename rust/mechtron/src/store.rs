@@ -0,0 +1,286 @@
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use mechtron_common::artifact::Artifact;
+use mechtron_common::configs::{Configs, ContentRetention};
+use mechtron_common::content::ReadOnlyContent;
+use mechtron_common::id::{ContentKey, Id, Revision, TronKey};
+
+// where the content history of a simulation is persisted.  the InterCyclicContentStructure
+// writes every revision through to its store and only keeps the head revision in memory
+pub trait ContentStore: Send+Sync
+{
+    fn put( &self, key: &ContentKey, content: &ReadOnlyContent )->Result<(),Box<dyn Error+'_>>;
+    fn get( &self, key: &ContentKey, configs: &Configs )->Result<Option<ReadOnlyContent>,Box<dyn Error+'_>>;
+
+    // every content key that was stored for the given revision
+    fn keys( &self, revision: &Revision )->Result<Vec<ContentKey>,Box<dyn Error+'_>>;
+
+    // remove the history of this tron that is not kept by the retention policy
+    fn prune( &self, tron_key: &TronKey, retention: &ContentRetention, last: &Revision )->Result<(),Box<dyn Error+'_>>;
+
+    // called once all content of a revision has been put, marks the revision as safe to resume from
+    fn commit( &self, sim_id: &Id, revision: &Revision )->Result<(),Box<dyn Error+'_>>;
+
+    // the sim id and last committed revision, or None if nothing has been committed
+    fn head( &self )->Result<Option<(Id,Revision)>,Box<dyn Error+'_>>;
+}
+
+// stores each content revision in its own file:  <path>/<nucleus_id>/<tron_id>/<cycle>
+// where ids are written as <seq_id>_<id>.  a content file holds the artifact, meta and data
+// buffers each prefixed by their length
+pub struct FileSystemContentStore
+{
+    path: PathBuf
+}
+
+static HEAD_FILE: &'static str = "head";
+
+impl FileSystemContentStore
+{
+    pub fn new( path: String ) -> Result<Self,Box<dyn Error>>
+    {
+        fs::create_dir_all(&path)?;
+        return Ok(FileSystemContentStore {
+            path: PathBuf::from(path)
+        });
+    }
+
+    fn tron_path( &self, tron_key: &TronKey )->PathBuf
+    {
+        self.path.join(id_to_name(&tron_key.nucleus_id)).join(id_to_name(&tron_key.tron_id))
+    }
+
+    fn content_path( &self, key: &ContentKey )->PathBuf
+    {
+        self.tron_path(&key.tron_id).join(key.revision.cycle.to_string())
+    }
+}
+
+impl ContentStore for FileSystemContentStore
+{
+    fn put(&self, key: &ContentKey, content: &ReadOnlyContent) -> Result<(), Box<dyn Error + '_>> {
+        fs::create_dir_all(self.tron_path(&key.tron_id))?;
+
+        let mut bytes = vec!();
+        write_section(&mut bytes, content.artifact.to().as_bytes());
+        write_section(&mut bytes, &content.meta.bytes());
+        write_section(&mut bytes, &content.data.bytes());
+
+        // write to a temporary file first so a crash never leaves a partially written revision
+        let path = self.content_path(key);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &ContentKey, configs: &Configs) -> Result<Option<ReadOnlyContent>, Box<dyn Error + '_>> {
+        let path = self.content_path(key);
+        if !path.exists()
+        {
+            return Ok(Option::None);
+        }
+
+        let mut file = File::open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut offset = 0;
+        let artifact = read_section(&bytes, &mut offset)?;
+        let meta = read_section(&bytes, &mut offset)?;
+        let data = read_section(&bytes, &mut offset)?;
+
+        let artifact = Artifact::from(String::from_utf8(artifact)?.as_str())?;
+        let meta = configs.core_buffer_factory("schema/content/meta")?.open_buffer(meta).finish();
+        let data = configs.buffer_factory_keeper.get(&artifact)?.open_buffer(data).finish();
+
+        Ok(Option::Some(ReadOnlyContent{
+            artifact: artifact,
+            meta: meta,
+            data: data
+        }))
+    }
+
+    // the store may share its directory with a checkpoint or a message log, anything that
+    // is not named for an id is not content and is skipped
+    fn keys(&self, revision: &Revision) -> Result<Vec<ContentKey>, Box<dyn Error + '_>> {
+        let mut rtn = vec!();
+        for nucleus_dir in fs::read_dir(&self.path)?
+        {
+            let nucleus_dir = nucleus_dir?.path();
+            if !nucleus_dir.is_dir()
+            {
+                continue;
+            }
+            let nucleus_id = match name_to_id(&nucleus_dir)
+            {
+                Ok(nucleus_id) => nucleus_id,
+                Err(_) => continue
+            };
+            for tron_dir in fs::read_dir(&nucleus_dir)?
+            {
+                let tron_dir = tron_dir?.path();
+                let tron_id = match name_to_id(&tron_dir)
+                {
+                    Ok(tron_id) => tron_id,
+                    Err(_) => continue
+                };
+                if tron_dir.join(revision.cycle.to_string()).exists()
+                {
+                    rtn.push(ContentKey{
+                        tron_id: TronKey::new(nucleus_id.clone(), tron_id),
+                        revision: revision.clone()
+                    });
+                }
+            }
+        }
+        Ok(rtn)
+    }
+
+    fn prune(&self, tron_key: &TronKey, retention: &ContentRetention, last: &Revision) -> Result<(), Box<dyn Error + '_>> {
+        let tron_path = self.tron_path(tron_key);
+        if !tron_path.exists()
+        {
+            return Ok(());
+        }
+        for entry in fs::read_dir(tron_path)?
+        {
+            let entry = entry?.path();
+            let cycle = match entry.file_name().and_then(|name| name.to_str()).and_then(|name| name.parse::<i64>().ok())
+            {
+                None => continue,
+                Some(cycle) => cycle
+            };
+            if !retention.retains(cycle, last.cycle)
+            {
+                fs::remove_file(entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn commit(&self, sim_id: &Id, revision: &Revision) -> Result<(), Box<dyn Error + '_>> {
+        let path = self.path.join(HEAD_FILE);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(format!("{} {} {}", sim_id.seq_id, sim_id.id, revision.cycle).as_bytes())?;
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn head(&self) -> Result<Option<(Id, Revision)>, Box<dyn Error + '_>> {
+        let path = self.path.join(HEAD_FILE);
+        if !path.exists()
+        {
+            return Ok(Option::None);
+        }
+        let head = fs::read_to_string(path)?;
+        let parts: Vec<&str> = head.split_whitespace().collect();
+        if parts.len() != 3
+        {
+            return Err(format!("head file of content store {:?} is malformed",self.path).into());
+        }
+        let sim_id = Id::new(parts[0].parse()?, parts[1].parse()?);
+        let revision = Revision{ cycle: parts[2].parse()? };
+        Ok(Option::Some((sim_id,revision)))
+    }
+}
+
+fn id_to_name( id: &Id )->String
+{
+    format!("{}_{}", id.seq_id, id.id)
+}
+
+fn name_to_id( path: &Path )->Result<Id,Box<dyn Error>>
+{
+    let name = match path.file_name().and_then(|name| name.to_str())
+    {
+        None => return Err(format!("content store path {:?} is not a valid id",path).into()),
+        Some(name) => name
+    };
+    let mut parts = name.split('_');
+    match (parts.next(), parts.next())
+    {
+        (Some(seq_id), Some(id)) => Ok(Id::new(seq_id.parse()?, id.parse()?)),
+        _ => Err(format!("content store path {:?} is not a valid id",path).into())
+    }
+}
+
+pub fn write_section( bytes: &mut Vec<u8>, section: &[u8] )
+{
+    bytes.extend_from_slice(&(section.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(section);
+}
+
+pub fn read_section( bytes: &Vec<u8>, offset: &mut usize )->Result<Vec<u8>,Box<dyn Error>>
+{
+    if *offset + 4 > bytes.len()
+    {
+        return Err("content file is truncated".into());
+    }
+    let mut len = [0u8;4];
+    len.copy_from_slice(&bytes[*offset..*offset+4]);
+    let len = u32::from_be_bytes(len) as usize;
+    *offset = *offset + 4;
+
+    if *offset + len > bytes.len()
+    {
+        return Err("content file is truncated".into());
+    }
+    let rtn = bytes[*offset..*offset+len].to_vec();
+    *offset = *offset + len;
+    Ok(rtn)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+    use std::fs;
+
+    use mechtron_common::content::Content;
+    use mechtron_common::id::{ContentKey, Id, Revision, TronKey};
+
+    use crate::app::SYS;
+    use crate::store::{ContentStore, FileSystemContentStore};
+
+    #[test]
+    fn put_get_and_keys() -> Result<(),Box<dyn Error>>
+    {
+        SYS.local.configs.cache_core()?;
+
+        let dir = std::env::temp_dir().join(format!("mechtron-store-{}", std::process::id()));
+        let store = FileSystemContentStore::new(dir.to_str().unwrap().to_string())?;
+
+        // a message log or checkpoint kept alongside the content is not mistaken for a nucleus
+        fs::create_dir_all(dir.join("messages"))?;
+        store.commit(&Id::new(0,0), &Revision{ cycle: 0 })?;
+
+        let artifact = SYS.local.configs.core_artifact("schema/empty")?;
+        let content = Content::new(&SYS.local.configs, artifact.clone()).read_only()?;
+        let key = ContentKey{ tron_id: TronKey::new(Id::new(0,1), Id::new(0,2)), revision: Revision{ cycle: 1 } };
+        store.put(&key, &content)?;
+
+        let read = store.get(&key, &SYS.local.configs)?.unwrap();
+        let keys = store.keys(&Revision{ cycle: 1 })?;
+        let other = store.keys(&Revision{ cycle: 2 })?;
+        let missing = store.get(&ContentKey{ tron_id: key.tron_id.clone(), revision: Revision{ cycle: 2 } }, &SYS.local.configs)?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(read.artifact, artifact);
+        assert_eq!(read.meta.bytes(), content.meta.bytes());
+        assert_eq!(read.data.bytes(), content.data.bytes());
+        assert_eq!(keys, vec!(key));
+        assert!(other.is_empty());
+        assert!(missing.is_none());
+
+        Ok(())
+    }
+}
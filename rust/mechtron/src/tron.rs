@@ -1,22 +1,36 @@
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
 use std::sync::Arc;
 
 use no_proto::buffer::NP_Buffer;
 use no_proto::error::NP_Error;
+use no_proto::json_flex::NP_JSON;
 use no_proto::memory::NP_Memory_Owned;
 
 use mechtron_common::artifact::Artifact;
 use mechtron_common::buffers;
-use mechtron_common::buffers::{get, set};
-use mechtron_common::configs::{Configs, CreateMessageConfig, MessagesConfig, TronConfig};
+use mechtron_common::buffers::{get, get_option, set};
+use mechtron_common::configs::{Configs, CreateMessageConfig, MechtronConfig, MessagesConfig, SimTronConfig, TronConfig};
 use mechtron_common::content::{Content, ReadOnlyContent};
-use mechtron_common::id::{ContentKey, Id, NucleusKey, Revision, TronKey};
-use mechtron_common::message::{Message, MessageBuilder, Payload};
+use mechtron_common::error::GuestError;
+use mechtron_common::id::{ContentKey, Id, IdSeq, NucleusKey, Revision, TronKey};
+use mechtron_common::json::buffer_from_json_str;
+use mechtron_common::message::{Cycle, Message, MessageBuilder, MessageKind, Payload};
 
 use crate::app::{Local, SYS};
 use crate::content::ContentRetrieval;
+use crate::lookup::LookupCache;
+use crate::wasm::WasmTron;
 use crate::nucleus::NeuTron;
 
+// the lookup name of the SimTron within the simulation nucleus
+pub static SIMTRON: &'static str = "simtron";
+// the lookup name of the nucleus the SimTron lives in
+pub static SIMULATION_NUCLEUS: &'static str = "simulation";
+// the nucleus of sim config trons that do not name one
+pub static DEFAULT_NUCLEUS: &'static str = "default";
+
 pub trait Tron
 {
     fn init(context: Context) -> Result<Box<Self>, Box<dyn Error>> where Self: Sized;
@@ -26,11 +40,39 @@ pub trait Tron
               content: &mut Content,
               create: &Message) -> Result<(Option<Vec<MessageBuilder>>), Box<dyn Error>>;
 
-    fn update(&self, phase: &str) -> Result<fn(context: &Context, content: &Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>>;
+    fn update(&self, phase: &str) -> Result<fn(context: &Context, content: &mut Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>>;
 
-    fn port(&self, port: &str) -> Result<fn(context: &Context, content: &Content, message: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>>;
+    fn port(&self, port: &str) -> Result<fn(context: &Context, content: &mut Content, message: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>>;
 
     fn update_phases(&self) -> UpdatePhases;
+
+    // trons that cannot hand out a function pointer for their handlers (such as a tron
+    // implemented by a wasm guest) override these instead of update and port
+    fn invoke_update(&self, context: &Context, content: &mut Content, phase: &str) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>
+    {
+        let update = self.update(phase)?;
+        update(context, content)
+    }
+
+    fn invoke_port(&self, context: &Context, content: &mut Content, port: &str, messages: &Vec<Message>) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>
+    {
+        let handler = self.port(port)?;
+        let mut builders = vec!();
+        for message in messages
+        {
+            let result = handler(context, content, message)?;
+            if result.is_some()
+            {
+                builders.append(&mut result.unwrap());
+            }
+        }
+
+        if builders.is_empty()
+        {
+            return Ok(Option::None);
+        }
+        Ok(Option::Some(builders))
+    }
 }
 
 pub enum UpdatePhases
@@ -42,19 +84,33 @@ pub enum UpdatePhases
 
 pub struct MessagePort
 {
-    pub receive: fn(context: &Context, content: &Content, message: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>
+    pub receive: fn(context: &Context, content: &mut Content, message: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>
 }
 
+#[derive(Clone)]
 pub struct Context
 {
     pub sim_id: Id,
     pub id: TronKey,
     pub revision: Revision,
     pub tron_config: Arc<TronConfig>,
+    pub mechtron_config: Option<Arc<MechtronConfig>>,
     pub timestamp: i64,
+    pub lookups: Arc<LookupCache>,
+    // the id sequence of the simulation the tron belongs to
+    pub id_seq: Arc<IdSeq>,
 }
 
 impl Context {
+    // the artifact the tron was created from, a mechtron config for trons implemented in wasm
+    pub fn source(&self) -> Artifact
+    {
+        match &self.mechtron_config {
+            None => self.tron_config.source.clone(),
+            Some(mechtron_config) => mechtron_config.source.clone()
+        }
+    }
+
     pub fn configs(&self) -> &mut Configs
     {
         return &mut SYS.local.configs;
@@ -67,50 +123,85 @@ impl Context {
         {
             return Err(format!("tron {:?} attempted to read the content of tron {:?} in a present or future cycle, which is not allowed", self.id, key.content_id).into());
         }
-        let content = source.content.read_only(key)?;
+        let content = source.content.read_only(key, &SYS.local.configs)?;
         Ok(content)
     }
 
-    fn lookup_nucleus(&self, context: &Context, name: &str) -> Result<Id, Box<dyn Error>>
+    // the nucleus the SimTron lives in, as recorded by the neutron of this tron's nucleus
+    pub fn simulation_nucleus_id(&self) -> Result<Id, Box<dyn Error>>
     {
-        let neutron_key = TronKey { nucleus_id: context.id.nucleus_id.clone(), tron_id: Id::new(context.id.nucleus_id.seq_id, 0) };
-        let content_key = ContentKey { tron_id: neutron_key, revision: Revision { cycle: context.revision.cycle - 1 } };
-        let neutron_content = context.get_content(&content_key)?;
-        let simulation_nucleus_id = Id::new(neutron_content.data.get::<i64>(&[&"simulation_nucleus_id", &"seq_id"])?.unwrap(),
-                                            neutron_content.data.get::<i64>(&[&"simulation_nucleus_id", &"id"])?.unwrap());
-
-        let simtron_key = TronKey {
-            nucleus_id: simulation_nucleus_id.clone(),
-            tron_id: Id::new(simulation_nucleus_id.seq_id, 1),
-        };
-
-        let content_key = ContentKey { tron_id: simtron_key, revision: Revision { cycle: context.revision.cycle - 1 } };
-        let simtron_content = context.get_content(&content_key)?;
+        self.lookups.simulation_nucleus_id(self, &self.id.nucleus_id)
+    }
 
-        let nucleus_id = Id::new(simtron_content.data.get::<i64>(&[&"nucleus_names", name, &"seq_id"])?.unwrap(),
-                                 simtron_content.data.get::<i64>(&[&"nucleus_names", name, &"id"])?.unwrap());
+    // nuclei are registered with the SimTron a cycle after their neutron is created, so a
+    // nucleus can be looked up by name from the cycle after that
+    pub fn lookup_nucleus(&self, name: &str) -> Result<Id, Box<dyn Error>>
+    {
+        self.lookups.lookup_nucleus(self, name)
+    }
 
+    // request a new nucleus.  the returned builder is the Create for the nucleus' neutron and
+    // must be emitted by the tron, the nucleus id can be used as soon as it is returned
+    pub fn create_nucleus(&self, nucleus_lookup_name: Option<&str>) -> Result<(Id, MessageBuilder), Box<dyn Error>>
+    {
+        let nucleus_id = self.id_seq.next();
+        let payloads = Neutron::create_payloads(self.configs(), nucleus_lookup_name, &self.simulation_nucleus_id()?)?;
+        Ok((nucleus_id.clone(), Neutron::create_builder(&nucleus_id, payloads)))
+    }
 
-        Ok(nucleus_id)
+    // request the teardown of a nucleus by name.  the SimTron forgets the name and
+    // destroys every tron in the nucleus
+    pub fn destroy_nucleus(&self, nucleus_lookup_name: &str) -> Result<MessageBuilder, Box<dyn Error>>
+    {
+        let mut payload = self.configs().core_buffer_factory("schema/sim/destroy-nucleus")?.new_buffer(Option::None);
+        set(&mut payload, &[&"nucleus_lookup_name"], nucleus_lookup_name.to_string())?;
+
+        let mut builder = MessageBuilder::new();
+        builder.kind = Option::Some(MessageKind::Update);
+        builder.to_nucleus_id = Option::Some(self.simulation_nucleus_id()?);
+        builder.to_tron_lookup_name = Option::Some(SIMTRON.to_string());
+        builder.to_port = Option::Some("destroy_nucleus".to_string());
+        builder.to_cycle_kind = Option::Some(Cycle::Next);
+        builder.to_phase = Option::Some(0);
+        builder.internal = true;
+        builder.payloads = Option::Some(vec![Payload { artifact: self.configs().core_artifact("schema/sim/destroy-nucleus")?, buffer: Arc::new(payload) }]);
+        Ok(builder)
+    }
+
+    // resolve a phase name declared in the SimConfig to its index
+    pub fn lookup_phase(&self, name: &str) -> Result<u8, Box<dyn Error>>
+    {
+        let source = SYS.local.sources.get(&self.sim_id)?;
+        source.phase_index(name)
     }
 
-    fn lookup_tron(&self, context: &Context, nucleus_id: &Id, name: &str) -> Result<TronKey, Box<dyn Error>>
+    pub fn lookup_tron(&self, nucleus_id: &Id, name: &str) -> Result<TronKey, Box<dyn Error>>
     {
-        let neutron_key = TronKey { nucleus_id: nucleus_id.clone(), tron_id: Id::new(nucleus_id.seq_id, 0) };
-        let content_key = ContentKey { tron_id: neutron_key, revision: Revision { cycle: context.revision.cycle - 1 } };
-        let neutron_content = context.get_content(&content_key)?;
-        let tron_id = Id::new(neutron_content.data.get::<i64>(&[&"tron_names", name, &"seq_id"])?.unwrap(),
-                              neutron_content.data.get::<i64>(&[&"tron_names", name, &"id"])?.unwrap());
+        self.lookups.lookup_tron(self, nucleus_id, name)
+    }
+}
 
-        let tron_key = TronKey {
-            nucleus_id: nucleus_id.clone(),
-            tron_id: tron_id,
-        };
+// an error reported by the wasm guest of a tron along with which tron it was and what
+// the tron was asked to do when it failed
+#[derive(Clone,Debug)]
+pub struct MechtronError
+{
+    pub tron: TronKey,
+    pub source: Artifact,
+    pub call: String,
+    pub error: GuestError
+}
 
-        Ok(tron_key)
+impl fmt::Display for MechtronError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "tron {:?} ({}) failed in {}: {}", self.tron, self.source.to(), self.call, self.error)
     }
 }
 
+impl Error for MechtronError {}
+
 pub struct TronShell
 {
     pub tron: Box<dyn Tron>
@@ -124,7 +215,7 @@ impl TronShell
         }
     }
 
-    fn from(&self, context: Context) -> mechtron_common::message::From
+    pub fn from(&self, context: &Context) -> mechtron_common::message::From
     {
         mechtron_common::message::From {
             tron: context.id.clone(),
@@ -141,70 +232,320 @@ impl TronShell
             return Ok(Option::None);
         }
 
-        let mut builders = builders.unwrap();
+        let builders = builders.unwrap();
 
-        let messages = builders.iter().map(|builder: &mut MessageBuilder| {
-            builder.from = Option::Some(from(context));
+        let mut messages = vec!();
+        for mut builder in builders
+        {
+            self.validate_outbound(context, &builder)?;
+
+            builder.from = Option::Some(self.from(context));
 
             if builder.to_nucleus_lookup_name.is_some()
             {
-                builder.to_nucleus_id = Option::Some(self.lookup_nucleus(context, builder.to_nucleus_lookup_name.unwrap().as_str())?);
+                builder.to_nucleus_id = Option::Some(context.lookup_nucleus(builder.to_nucleus_lookup_name.as_ref().unwrap().as_str())?);
+                builder.to_nucleus_lookup_name = Option::None;
             }
 
             if builder.to_tron_lookup_name.is_some()
             {
-                builder.to_tron_id = Option::Some(self.lookup_tron(context, &builder.to_nucleus_id.unwrap(), builder.to_tron_lookup_name.unwrap().as_str())?.tron_id);
+                builder.to_tron_id = Option::Some(context.lookup_tron(builder.to_nucleus_id.as_ref().unwrap(), builder.to_tron_lookup_name.as_ref().unwrap().as_str())?.tron_id);
+                builder.to_tron_lookup_name = Option::None;
             }
 
+            if builder.to_phase_name.is_some()
+            {
+                builder.to_phase = Option::Some(context.lookup_phase(builder.to_phase_name.as_ref().unwrap().as_str())?);
+                builder.to_phase_name = Option::None;
+            }
 
-            builder.build(&mut SYS.net.id_seq)
-        }).collect();
+            messages.push(builder.build(&context.id_seq)?);
+        }
 
         return Ok(Option::Some(messages));
     }
 
+    // trons that declare outbound ports may only emit messages to those ports carrying the declared artifact
+    fn validate_outbound(&self, context: &Context, builder: &MessageBuilder) -> Result<(), Box<dyn Error>>
+    {
+        let messages_config = match &context.tron_config.messages {
+            None => return Ok(()),
+            Some(messages) => messages
+        };
+
+        if messages_config.outbound.is_empty()
+        {
+            return Ok(());
+        }
+
+        // responses are addressed to the requester's response port by the runtime
+        match builder.kind
+        {
+            Option::Some(MessageKind::Response) => return Ok(()),
+            // Creates and Destroys are handled by a neutron, not by a port of the tron
+            // they are addressed to, and carry the payloads the runtime builds for them
+            Option::Some(MessageKind::Create) | Option::Some(MessageKind::Destroy) => return Ok(()),
+            _ => {}
+        }
+
+        // destroy_nucleus() and the neutron ask the SimTron to manage nuclei on the tron's behalf
+        if builder.internal
+        {
+            return Ok(());
+        }
+
+        let port = match &builder.to_port {
+            None => return Err(format!("tron {:?} ({}) emitted a message builder with no to_port", context.id, context.tron_config.source.to()).into()),
+            Some(port) => port
+        };
+
+        let declared = match messages_config.outbound_port(port.as_str()) {
+            None => return Err(format!("tron {:?} ({}) emitted a message to port '{}' which is not one of its declared outbound ports", context.id, context.tron_config.source.to(), port).into()),
+            Some(declared) => declared
+        };
+
+        if builder.payloads.is_some()
+        {
+            for payload in builder.payloads.as_ref().unwrap()
+            {
+                if payload.artifact != declared.artifact
+                {
+                    return Err(format!("tron {:?} ({}) emitted a message to outbound port '{}' with payload artifact {} but the port declares {}", context.id, context.tron_config.source.to(), port, payload.artifact.to(), declared.artifact.to()).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // the guest only knows itself so the tron and call are attached here
+    fn guest_error(&self, context: &Context, call: String, error: Box<dyn Error>) -> Box<dyn Error>
+    {
+        match error.downcast::<GuestError>()
+        {
+            Ok(guest_error) => Box::new(MechtronError {
+                tron: context.id.clone(),
+                source: context.source(),
+                call: call,
+                error: *guest_error
+            }),
+            Err(error) => error
+        }
+    }
+
     pub fn create(&self, context: &Context,
                   content: &mut Content,
                   create: &Message) -> Result<Option<Vec<Message>>, Box<dyn Error>> {
-        let mut builders = self.tron.create(context, content, create)?;
-        match builders {
-            None => Ok(Option::None),
-            Some(builders) =>
-                Option::Some(builders.iter().map(|builder: &mut MessageBuilder| {
-                    builder.from = Option::Some(from(context));
-                }).collect())
-        }
+        let builders = self.tron.create(context, content, create).map_err(|e| self.guest_error(context, "create".to_string(), e))?;
+        self.builders_to_messages(context, builders)
     }
 
-    pub fn update(&self, context: &Context, content: &mut Content, inbound_messages: Vec<&Message>) -> Result<Option<Vec<Message>>, Box<dyn Error>> {
-        unimplemented!()
+    // hand the messages to the handler the tron provides for this port
+    pub fn receive(&self, context: &Context, content: &mut Content, port: &str, messages: &Vec<Message>) -> Result<Option<Vec<Message>>, Box<dyn Error>> {
+        let builders = self.tron.invoke_port(context, content, port, messages).map_err(|e| self.guest_error(context, format!("port '{}'", port), e))?;
+        self.builders_to_messages(context, builders)
+    }
+
+    // invoke the tron's update function for this phase, trons that do not declare
+    // the phase are skipped
+    pub fn update(&self, context: &Context, content: &mut Content, phase: &str) -> Result<Option<Vec<Message>>, Box<dyn Error>> {
+        let invoke = match self.tron.update_phases() {
+            UpdatePhases::All => true,
+            UpdatePhases::Some(phases) => phases.iter().any(|p| p.as_str() == phase),
+            UpdatePhases::None => false
+        };
+
+        if !invoke
+        {
+            return Ok(Option::None);
+        }
+
+        let builders = self.tron.invoke_update(context, content, phase).map_err(|e| self.guest_error(context, format!("update of phase '{}'", phase), e))?;
+        self.builders_to_messages(context, builders)
     }
 }
 
 
+// new content for a tron.  the meta records the tron config the content belongs to and when it was created
+pub fn tron_content(context: &Context, tron_config: &TronConfig) -> Result<Content, Box<dyn Error>>
+{
+    let artifact = match &tron_config.content {
+        None => context.configs().core_artifact("schema/empty")?,
+        Some(content) => content.artifact.clone()
+    };
+
+    let mut content = Content::new(context.configs(), artifact);
+    set(&mut content.meta, &[&"artifact"], context.source().to())?;
+    set(&mut content.meta, &[&"creation_timestamp"], context.timestamp)?;
+    set(&mut content.meta, &[&"creation_cycle"], context.revision.cycle)?;
+    Ok(content)
+}
+
+// the SimTron creates the simulation described by its sim config.  each nucleus named
+// in the sim config is created by sending a Create to its neutron, after which the
+// neutron of that nucleus is sent a Create for each of its trons
 pub struct SimTron
 {}
 
+impl SimTron
+{
+    // registering the same name for the same nucleus again is allowed since the SimTron
+    // records the nuclei it creates before their neutrons register them
+    fn add_nucleus(content: &mut Content, name: &str, nucleus_id: &Id) -> Result<(), Box<dyn Error>>
+    {
+        if let Ok(Some(_)) = content.data.get::<i64>(&[&"nucleus_names", name, &"id"])
+        {
+            let registered = Id::new(get::<i64, NP_Memory_Owned>(&content.data, &[&"nucleus_names", name, &"seq_id"])?,
+                                     get::<i64, NP_Memory_Owned>(&content.data, &[&"nucleus_names", name, &"id"])?);
+            if registered == *nucleus_id
+            {
+                return Ok(());
+            }
+            return Err(format!("nucleus lookup name '{}' is already registered to nucleus {:?}", name, registered).into());
+        }
+
+        let index = match content.data.get_length(&[&"nucleus_ids"]) {
+            Ok(length) => length.unwrap_or(0).to_string(),
+            Err(_) => return Err("could not read nucleus_ids from simtron content".into())
+        };
+        set(&mut content.data, &[&"nucleus_ids", &index, &"seq_id"], nucleus_id.seq_id)?;
+        set(&mut content.data, &[&"nucleus_ids", &index, &"id"], nucleus_id.id)?;
+        set(&mut content.data, &[&"nucleus_names", name, &"seq_id"], nucleus_id.seq_id)?;
+        set(&mut content.data, &[&"nucleus_names", name, &"id"], nucleus_id.id)?;
+        Ok(())
+    }
+}
+
+impl SimTron
+{
+    fn remove_nucleus(content: &mut Content, name: &str) -> Result<Id, Box<dyn Error>>
+    {
+        if name == SIMULATION_NUCLEUS
+        {
+            return Err("the simulation nucleus cannot be destroyed".into());
+        }
+
+        match content.data.get::<i64>(&[&"nucleus_names", name, &"id"])
+        {
+            Ok(Some(_)) => {},
+            _ => return Err(format!("no nucleus is registered with the lookup name '{}'", name).into())
+        }
+        let nucleus_id = Id::new(get::<i64, NP_Memory_Owned>(&content.data, &[&"nucleus_names", name, &"seq_id"])?,
+                                 get::<i64, NP_Memory_Owned>(&content.data, &[&"nucleus_names", name, &"id"])?);
+
+        let nucleus_ids = match content.data.json_encode(&[&"nucleus_ids"]) {
+            Ok(json) => json,
+            Err(_) => return Err("could not read nucleus_ids from simtron content".into())
+        };
+        let remaining: Vec<Id> = ids_from_json(&nucleus_ids).into_iter().filter(|id| *id != nucleus_id ).collect();
+
+        match (content.data.del(&[&"nucleus_names", name]), content.data.del(&[&"nucleus_ids"]))
+        {
+            (Ok(_), Ok(_)) => {},
+            _ => return Err(format!("could not remove nucleus '{}' from simtron content", name).into())
+        }
+        for (index, id) in remaining.iter().enumerate()
+        {
+            let index = index.to_string();
+            set(&mut content.data, &[&"nucleus_ids", &index, &"seq_id"], id.seq_id)?;
+            set(&mut content.data, &[&"nucleus_ids", &index, &"id"], id.id)?;
+        }
+
+        Ok(nucleus_id)
+    }
+}
+
+// the ids in a json list or map of seq_id, id tables
+fn ids_from_json(json: &NP_JSON) -> Vec<Id>
+{
+    let values: Vec<&NP_JSON> = match json {
+        NP_JSON::Array(values) => values.iter().collect(),
+        NP_JSON::Dictionary(map) => map.values.iter().map(|(_, value)| value ).collect(),
+        _ => vec!()
+    };
+
+    let mut rtn = vec!();
+    for value in values
+    {
+        if let (Some(seq_id), Some(id)) = (value["seq_id"].into_i64(), value["id"].into_i64())
+        {
+            rtn.push(Id::new(seq_id.clone(), id.clone()));
+        }
+    }
+    rtn
+}
+
 impl Tron for SimTron
 {
     fn init(context: Context) -> Result<Box<Self>, Box<dyn Error>> where Self: Sized {
-        unimplemented!()
+        Ok(Box::new(SimTron {}))
     }
 
     fn create(&self, context: &Context, content: &mut Content, create: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>> {
-        unimplemented!()
+        let sim_config = Artifact::from(get::<String, NP_Memory_Owned>(&create.payload(1)?.buffer, &[&"sim_config_artifact"])?.as_str())?;
+        let sim_config = context.configs().sim_config_keeper.get(&sim_config)?;
+
+        // grouped by nucleus and ordered by name so that nucleus ids are issued in the same order every run
+        let mut nuclei: BTreeMap<String, Vec<&SimTronConfig>> = BTreeMap::new();
+        for sim_tron in &sim_config.trons
+        {
+            let nucleus = match &sim_tron.nucleus {
+                None => DEFAULT_NUCLEUS.to_string(),
+                Some(nucleus) => nucleus.clone()
+            };
+            if !nuclei.contains_key(&nucleus)
+            {
+                nuclei.insert(nucleus.clone(), vec!());
+            }
+            nuclei.get_mut(&nucleus).unwrap().push(sim_tron);
+        }
+
+        // the neutron of the simulation nucleus cannot register it since the SimTron does not exist until after that neutron
+        SimTron::add_nucleus(content, SIMULATION_NUCLEUS, &context.id.nucleus_id)?;
+
+        let mut builders = vec!();
+        for (name, sim_trons) in nuclei
+        {
+            let nucleus_id = context.id_seq.next();
+            SimTron::add_nucleus(content, name.as_str(), &nucleus_id)?;
+
+            // the Create for the neutron is issued first so it is delivered before the Creates for the trons it will hold
+            builders.push(Neutron::create_builder(&nucleus_id, Neutron::create_payloads(context.configs(), Option::Some(name.as_str()), &context.id.nucleus_id)?));
+            for sim_tron in sim_trons
+            {
+                builders.push(Neutron::create_builder(&nucleus_id, CreatePayloadsBuilder::sim_tron(context.configs(), sim_tron)?));
+            }
+        }
+
+        Ok(Option::Some(builders))
     }
 
-    fn update(&self, phase: &str) -> Result<fn(&Context, &Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
-        unimplemented!()
+    fn update(&self, phase: &str) -> Result<fn(&Context, &mut Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+        Err("simtron does not have any updates".into())
     }
 
-    fn port(&self, port: &str) -> Result<fn(&Context, &Content, &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
-        unimplemented!()
+    fn port(&self, port: &str) -> Result<fn(&Context, &mut Content, &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+        match port {
+            "register_nucleus" => Ok(|context, content, message| {
+                let registration = &message.payload(0)?.buffer;
+                let name = get::<String, NP_Memory_Owned>(registration, &[&"nucleus_lookup_name"])?;
+                let nucleus_id = Id::new(get::<i64, NP_Memory_Owned>(registration, &[&"nucleus_id", &"seq_id"])?,
+                                         get::<i64, NP_Memory_Owned>(registration, &[&"nucleus_id", &"id"])?);
+                SimTron::add_nucleus(content, name.as_str(), &nucleus_id)?;
+                Ok(Option::None)
+            }),
+            "destroy_nucleus" => Ok(|context, content, message| {
+                let name = get::<String, NP_Memory_Owned>(&message.payload(0)?.buffer, &[&"nucleus_lookup_name"])?;
+                let nucleus_id = SimTron::remove_nucleus(content, name.as_str())?;
+                // a Destroy addressed to a neutron destroys its whole nucleus
+                Ok(Option::Some(vec![Neutron::destroy_builder(&Neutron::key(&nucleus_id))]))
+            }),
+            _ => Err(format!("simtron does not have port {}", port).into())
+        }
     }
 
     fn update_phases(&self) -> UpdatePhases {
-        unimplemented!()
+        UpdatePhases::None
     }
 }
 
@@ -218,22 +559,69 @@ impl NeutronContentInterface
 {
     fn add_tron_np_error(&self, content: &mut Content, tron: &TronKey, kind: u8) -> Result<(), NP_Error>
     {
-        let index = content.data.get_length(&[&"trons"])?.unwrap();
-        content.data.set(&[&"trons", &index.to_string(), &"seq_id"], tron.tron_id.seq_id)?;
-        content.data.set(&[&"trons", &index.to_string(), &"id"], tron.tron_id.id)?;
-        content.data.set(&[&"trons", &index.to_string(), &"kind"], kind)?;
+        let index = content.data.get_length(&[&"tron"])?.unwrap_or(0);
+        content.data.set(&[&"tron", &index.to_string(), &"seq_id"], tron.tron_id.seq_id)?;
+        content.data.set(&[&"tron", &index.to_string(), &"id"], tron.tron_id.id)?;
+        content.data.set(&[&"tron", &index.to_string(), &"kind"], kind)?;
 
         Ok(())
     }
 
     fn set_tron_name_np_error(&self, content: &mut Content, name: &str, tron: &TronKey) -> Result<(), NP_Error>
     {
-        content.set(&[&"tron_names", name, &"seq_id"], context.id.tron_id.seq_id)?;
-        content.set(&[&"tron_names", name, &"id"], context.id.tron_id.id)?;
+        content.data.set(&[&"tron_names", name, &"seq_id"], tron.tron_id.seq_id)?;
+        content.data.set(&[&"tron_names", name, &"id"], tron.tron_id.id)?;
+
+        Ok(())
+    }
+
+    // lists cannot have holes so the tron list is rebuilt without the removed tron
+    fn remove_tron_np_error(&self, content: &mut Content, tron: &TronKey) -> Result<(), NP_Error>
+    {
+        let mut remaining = vec!();
+        let length = content.data.get_length(&[&"tron"])?.unwrap_or(0);
+        for index in 0..length
+        {
+            let index = index.to_string();
+            let id = Id::new(content.data.get::<i64>(&[&"tron", &index, &"seq_id"])?.unwrap_or(0),
+                             content.data.get::<i64>(&[&"tron", &index, &"id"])?.unwrap_or(0));
+            if id != tron.tron_id
+            {
+                remaining.push((id, content.data.get::<u8>(&[&"tron", &index, &"kind"])?.unwrap_or(0)));
+            }
+        }
+        content.data.del(&[&"tron"])?;
+        for (index, (id, kind)) in remaining.iter().enumerate()
+        {
+            let index = index.to_string();
+            content.data.set(&[&"tron", &index, &"seq_id"], id.seq_id)?;
+            content.data.set(&[&"tron", &index, &"id"], id.id)?;
+            content.data.set(&[&"tron", &index, &"kind"], kind.clone())?;
+        }
+
+        if let NP_JSON::Dictionary(names) = content.data.json_encode(&[&"tron_names"])?
+        {
+            for (name, value) in &names.values
+            {
+                if ids_from_json(&NP_JSON::Array(vec![value.clone()])).contains(&tron.tron_id)
+                {
+                    content.data.del(&[&"tron_names", name.as_str()])?;
+                }
+            }
+        }
 
         Ok(())
     }
 
+    pub fn remove_tron(&self, content: &mut Content, tron: &TronKey) -> Result<(), Box<dyn Error>>
+    {
+        match self.remove_tron_np_error(content, tron)
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err("encountered error when removing tron key from neutron context".into())
+        }
+    }
+
     pub fn add_tron(&self, content: &mut Content, tron: &TronKey, kind: u8) -> Result<(), Box<dyn Error>>
     {
         match self.add_tron_np_error(content, tron, kind)
@@ -258,51 +646,103 @@ impl Neutron {
         return id.id == 0;
     }
 
-    pub fn create_tron(&self, context: &Context, content: &mut Content, create: &Message) -> Result<(TronKey,Content), Box<dyn Error>>
+    // the neutron of a nucleus takes the id 0 of the nucleus' sequence
+    pub fn key( nucleus_id: &Id )->TronKey
     {
-        let tron_key = TronKey::new(nucleus_id: context.id.nucleus_id, SYS.net.id_seq.next());
-        let interface = NeutronContentInterface {};
-        interface.add_tron(content, &tron_key, 0)?;
+        TronKey::new(nucleus_id.clone(), Id::new(nucleus_id.seq_id, 0))
+    }
 
-        let create_meta = &message.payloads[0].buffer;
-        if create_meta.get(&[&"lookup_name"]).unwrap().is_some()
+    // a Create addressed to the neutron of a nucleus, either for the neutron itself or for a tron the neutron will create
+    pub fn create_builder(nucleus_id: &Id, payloads: Vec<Payload>) -> MessageBuilder
+    {
+        let mut builder = MessageBuilder::new();
+        builder.kind = Option::Some(MessageKind::Create);
+        builder.to_nucleus_id = Option::Some(nucleus_id.clone());
+        builder.to_tron_id = Option::Some(Neutron::key(nucleus_id).tron_id);
+        builder.to_port = Option::Some("create".to_string());
+        builder.to_cycle_kind = Option::Some(Cycle::Next);
+        builder.to_phase = Option::Some(0);
+        builder.payloads = Option::Some(payloads);
+        builder
+    }
+
+    // the payloads of the Create that brings a new nucleus to life by creating its neutron
+    pub fn create_payloads(configs: &Configs, nucleus_lookup_name: Option<&str>, simulation_nucleus_id: &Id) -> Result<Vec<Payload>, Box<dyn Error>>
+    {
+        let neutron_config = configs.core_tron_config("tron/neutron")?;
+        let mut builder = CreatePayloadsBuilder::new(configs, &neutron_config)?;
+        if let Option::Some(nucleus_lookup_name) = nucleus_lookup_name
         {
-            let name = create_meta.get::<String>(&[&"lookup_name"]).unwrap().unwrap();
-            interface.set_tron_name(contet, name.as_str(), &tron_key);
+            set(&mut builder.constructor, &[&"nucleus_lookup_name"], nucleus_lookup_name.to_string())?;
         }
+        set(&mut builder.constructor, &[&"simulation_nucleus_id", &"seq_id"], simulation_nucleus_id.seq_id)?;
+        set(&mut builder.constructor, &[&"simulation_nucleus_id", &"id"], simulation_nucleus_id.id)?;
+        Ok(CreatePayloadsBuilder::payloads(configs, builder))
+    }
 
-        let tron_config = create_meta.get::<String>(&[&"artifact"]).unwrap().unwrap();
-        let tron_config = Artifact::from(&tron_config)?;
-        let tron_config = context.configs().tron_config_keeper.get(&tron_config)?;
+    // a Destroy addressed to a tron.  when the tron is a neutron its whole nucleus is destroyed
+    pub fn destroy_builder(tron_key: &TronKey) -> MessageBuilder
+    {
+        let mut builder = MessageBuilder::new();
+        builder.kind = Option::Some(MessageKind::Destroy);
+        builder.to_nucleus_id = Option::Some(tron_key.nucleus_id.clone());
+        builder.to_tron_id = Option::Some(tron_key.tron_id.clone());
+        builder.to_port = Option::Some("destroy".to_string());
+        builder.to_cycle_kind = Option::Some(Cycle::Next);
+        builder.to_phase = Option::Some(0);
+        builder
+    }
+
+    // remove the tron from the registry of this neutron, the nucleus stops delivering to it and stops keeping its content
+    pub fn destroy_tron(&self, content: &mut Content, tron_key: &TronKey) -> Result<(), Box<dyn Error>>
+    {
+        let interface = NeutronContentInterface {};
+        interface.remove_tron(content, tron_key)
+    }
 
-        let tron_content_artifact = match tron_config.messages
-        {
-            None => context.configs().core_artifact("schema/empty"),
-            Some(_) => match tron_config.messages.unwrap().create {
-                None => context.configs().core_artifact("schema/empty"),
-                Some(_) => tron_config.messages.unwrap().create.unwrap().artifact
-            }
-        }?;
+    pub fn create_tron(&self, context: &Context, content: &mut Content, create: &Message) -> Result<(TronKey,Content,Option<Vec<Message>>), Box<dyn Error>>
+    {
+        let tron_key = TronKey::new(context.id.nucleus_id.clone(), context.id_seq.next());
+        let interface = NeutronContentInterface {};
+        interface.add_tron(content, &tron_key, 0)?;
 
-        let mut tron_content = Content::new(context.configs(), tron_content_artifact.clone());
+        let create_meta = &create.payload(0)?.buffer;
+        if let Option::Some(name) = get_option::<String, NP_Memory_Owned>(create_meta, &[&"lookup_name"])?
+        {
+            interface.set_tron_name(content, name.as_str(), &tron_key)?;
+        }
 
-        tron_content.meta.set(&[&"artifact"], tron_config.source.to());
-        tron_content.meta.set(&[&"creation_timestamp"], context.timestamp);
-        tron_content.meta.set(&[&"creation_cycle"], context.revision.cycle);
+        let artifact = get::<String, NP_Memory_Owned>(create_meta, &[&"artifact"])?;
+        let artifact = Artifact::from(&artifact)?;
+        let (tron_config, mechtron_config) = context.configs().tron_config_of(&artifact)?;
 
-        let tron = init_tron(&tron_config, context)?;
-        let tron = TronShell::new(tron);
         let tron_context = Context {
             sim_id: context.sim_id.clone(),
             id: tron_key.clone(),
             revision: context.revision.clone(),
             tron_config: tron_config.clone(),
+            mechtron_config: mechtron_config,
             timestamp: context.timestamp,
+            lookups: context.lookups.clone(),
+            id_seq: context.id_seq.clone(),
         };
 
-        tron.create(&tron_context, &mut tron_content, &message)?;
+        let mut tron_content = tron_content(&tron_context, &tron_config)?;
+
+        let tron = init_tron(&tron_config, &tron_context)?;
+        let tron = TronShell::new(tron);
+
+        // a tron that fails to create is not registered with the neutron
+        let messages = match tron.create(&tron_context, &mut tron_content, create)
+        {
+            Ok(messages) => messages,
+            Err(e) => {
+                interface.remove_tron(content, &tron_key)?;
+                return Err(e);
+            }
+        };
 
-        Ok((tron_key,tron_content))
+        Ok((tron_key,tron_content,messages))
     }
 }
 
@@ -316,15 +756,43 @@ impl Tron for Neutron {
         interface.add_tron(content, &context.id, 0)?;
         interface.set_tron_name(content, "neutron", &context.id)?;
 
-        if get(&create.payloads[0].buffer,&[&"nucleus_lookup_name"])?.is_some()
+        let constructor = &create.payload(1)?.buffer;
+        let simulation_nucleus_id = Id::new(get::<i64, NP_Memory_Owned>(constructor, &[&"simulation_nucleus_id", &"seq_id"])?,
+                                            get::<i64, NP_Memory_Owned>(constructor, &[&"simulation_nucleus_id", &"id"])?);
+        set(&mut content.data, &[&"simulation_nucleus_id", &"seq_id"], simulation_nucleus_id.seq_id)?;
+        set(&mut content.data, &[&"simulation_nucleus_id", &"id"], simulation_nucleus_id.id)?;
+
+        // the simulation nucleus is registered by the SimTron itself
+        if simulation_nucleus_id == context.id.nucleus_id
+        {
+            return Ok(Option::None);
+        }
+
+        if let Option::Some(name) = get_option::<String, NP_Memory_Owned>(constructor, &[&"nucleus_lookup_name"])?
         {
-            // then we need to pass a message to the simtron to add a lookup name for this nucleus
+            // pass a message to the simtron to add a lookup name for this nucleus
+            let artifact = context.configs().core_artifact("schema/sim/register-nucleus")?;
+            let mut registration = context.configs().core_buffer_factory("schema/sim/register-nucleus")?.new_buffer(Option::None);
+            set(&mut registration, &[&"nucleus_lookup_name"], name)?;
+            set(&mut registration, &[&"nucleus_id", &"seq_id"], context.id.nucleus_id.seq_id)?;
+            set(&mut registration, &[&"nucleus_id", &"id"], context.id.nucleus_id.id)?;
+
+            let mut builder = MessageBuilder::new();
+            builder.kind = Option::Some(MessageKind::Update);
+            builder.to_nucleus_id = Option::Some(simulation_nucleus_id);
+            builder.to_tron_lookup_name = Option::Some(SIMTRON.to_string());
+            builder.to_port = Option::Some("register_nucleus".to_string());
+            builder.to_cycle_kind = Option::Some(Cycle::Next);
+            builder.to_phase = Option::Some(0);
+            builder.internal = true;
+            builder.payloads = Option::Some(vec![Payload { artifact: artifact, buffer: Arc::new(registration) }]);
+            return Ok(Option::Some(vec![builder]));
         }
 
         Ok(Option::None)
     }
 
-    fn update(&self, phase: &str) -> Result<fn(&Context, &Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+    fn update(&self, phase: &str) -> Result<fn(&Context, &mut Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
         Err("does not have an update for phase".into())
     }
 
@@ -360,9 +828,41 @@ impl CreatePayloadsBuilder
         })
     }
 
-    pub fn set_lookup_name(&mut self, lookup_name: &str )
+    pub fn set_lookup_name(&mut self, lookup_name: &str ) -> Result<(), Box<dyn Error>>
     {
-        set(&mut self.meta, &[&"lookup_name"], lookup_name ).unwrap();
+        set(&mut self.meta, &[&"lookup_name"], lookup_name )?;
+        Ok(())
+    }
+
+    // replace the constructor with one built from json, the json must match the tron's create schema
+    pub fn set_constructor_json(&mut self, configs: &Configs, json: &str ) -> Result<(), Box<dyn Error>>
+    {
+        self.constructor = buffer_from_json_str(configs, &self.constructor_artifact, json)?;
+        Ok(())
+    }
+
+    // the payloads of the Create message for a tron listed in a sim config.  the create data
+    // artifact is json that is converted into the constructor using the tron's create schema
+    pub fn sim_tron(configs: &Configs, sim_tron: &SimTronConfig) -> Result<Vec<Payload>, Box<dyn Error>>
+    {
+        let tron_config = sim_tron.tron_config(configs)?;
+        let mut builder = CreatePayloadsBuilder::new(configs, &tron_config)?;
+        // the neutron finds the mechtron config (if any) through the artifact the tron was listed as
+        set( &mut builder.meta, &[&"artifact"], sim_tron.artifact.to() )?;
+        if let Option::Some(name) = &sim_tron.name
+        {
+            builder.set_lookup_name(name.as_str())?;
+        }
+        if let Option::Some(create) = &sim_tron.create
+        {
+            let data = configs.artifact_cache.get(&create.data.artifact)?;
+            match builder.set_constructor_json(configs, data.as_str())
+            {
+                Ok(_) => {},
+                Err(e) => return Err(format!("create data {} for tron {} could not be converted: {}", create.data.artifact.to(), sim_tron.artifact.to(), e).into())
+            }
+        }
+        Ok(CreatePayloadsBuilder::payloads(configs, builder))
     }
 
 
@@ -410,15 +910,15 @@ impl Tron for StdOut
         Ok(Option::None)
     }
 
-    fn update(&self, phase: &str) -> Result<fn(&Context, &Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+    fn update(&self, phase: &str) -> Result<fn(&Context, &mut Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
         Err("stdout does not have any updates".into())
     }
 
-    fn port(&self, port: &str) -> Result<fn(&Context, &Content, &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+    fn port(&self, port: &str) -> Result<fn(&Context, &mut Content, &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
         match port {
             "println" => Ok(|context, content, message| {
-                let line = get::<String, NP_Memory_Owned>(&message.payloads[0].buffer, &[])?;
-                println!(line);
+                let line = get::<String, NP_Memory_Owned>(&message.payload(0)?.buffer, &[])?;
+                println!("{}", line);
                 Ok(Option::None)
             }),
             _ => Err(format!("could not find port {}", port).into())
@@ -432,10 +932,16 @@ impl Tron for StdOut
 
 pub fn init_tron(config: &TronConfig, context: &Context) -> Result<Box<dyn Tron>, Box<dyn Error>>
 {
-    let rtn = match config.kind.as_str() {
-        "sim" => SimTron.init(context),
-        "neutron" => NeuTron.init(context),
-        _ => return Err(format!("we don't have a tron of kind {}", kind).into())
+    if context.mechtron_config.is_some()
+    {
+        return Ok(WasmTron::init(context.clone())?);
+    }
+
+    let rtn: Box<dyn Tron> = match config.kind.as_str() {
+        "sim" => SimTron::init(context.clone())?,
+        "neutron" => Neutron::init(context.clone())?,
+        "stdout" => StdOut::init(context.clone())?,
+        _ => return Err(format!("we don't have a tron of kind {}", config.kind).into())
     };
 
     Ok(rtn)
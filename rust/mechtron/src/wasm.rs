@@ -2,33 +2,76 @@ use std::any::Any;
 use std::borrow::{Borrow, BorrowMut};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Debug;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 use std::pin::Pin;
 use std::rc::Rc;
 use std::string::FromUtf8Error;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::atomic::{{AtomicUsize, Ordering}};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use no_proto::buffer::NP_Buffer;
 use no_proto::error::NP_Error;
 use no_proto::pointer::{NP_Scalar, NP_Value};
-use wasmer::{Array, Function, FunctionType, ImportObject, imports, Instance, Module, Resolver, Val, ValType, Value, WasmerEnv, WasmPtr};
+use wasmer::{Array, Function, FunctionType, ImportObject, imports, Instance, LazyInit, Memory, Module, Resolver, RuntimeError, Val, ValType, Value, WasmerEnv, WasmPtr};
 
 use mechtron_common::artifact::{Artifact, ArtifactRepository};
 use mechtron_common::buffers::BufferFactories;
-use mechtron_common::message::Message;
+use mechtron_common::configs::{Configs, MechtronConfig, MechtronLimits, TronConfig};
+use mechtron_common::content::Content;
+use mechtron_common::error::{GuestError, GuestErrorKind};
+use mechtron_common::message::{Message, MessageBuilder};
 
-pub struct WasmBinder<'a>
+use crate::app::SYS;
+use crate::limits::{points_exhausted, set_remaining_points};
+use crate::tron::{Context, MechtronError, Tron, UpdatePhases};
+
+pub struct WasmBinder
 {
-    module: &'a Module,
+    module: Arc<Module>,
     guest: WasmGuest,
-    host: Arc<Mutex<WasmHost>>
+    host: Arc<Mutex<WasmHost>>,
+    limits: MechtronLimits,
+    // buffers written into the guest for the next call, whatever the guest did not consume
+    // is dealloced once the call is done
+    written: Vec<i32>,
+    // a guest stopped part way through a call may have left its own state half updated so
+    // it is never called again
+    exceeded: bool,
+    // the same goes for a guest that trapped or panicked, a panic also poisons the locks
+    // the guest keeps its state behind
+    poisoned: bool
+}
+
+// the content and messages the guest handed to the host during a call
+struct GuestOutput
+{
+    content: Option<Bytes>,
+    messages: Option<Bytes>
+}
+
+// a call into a wasm guest that was stopped because it went past the limits of its mechtron
+#[derive(Clone,Debug)]
+pub struct LimitExceeded
+{
+    pub export: String,
+    pub reason: String
+}
+
+impl fmt::Display for LimitExceeded
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "wasm guest export {} exceeded its limits: {}", self.export, self.reason)
+    }
 }
 
+impl Error for LimitExceeded {}
+
 struct WasmGuest
 {
     instance: Instance
@@ -39,7 +82,8 @@ struct WasmHost
     buffer_map: HashMap<i32,BytesMut>,
     buffer_index: i32,
     content_buffer_id: Option<i32>,
-    messages_buffer_id: Option<i32>
+    messages_buffer_id: Option<i32>,
+    error_buffer_id: Option<i32>
 }
 
 impl WasmHost
@@ -50,7 +94,8 @@ impl WasmHost
             buffer_map: HashMap::new(),
             buffer_index: 0,
             content_buffer_id: Option::None,
-            messages_buffer_id: Option::None
+            messages_buffer_id: Option::None,
+            error_buffer_id: Option::None
         }
     }
 
@@ -80,6 +125,25 @@ impl WasmHost
         return self.messages_buffer_id.is_some();
     }
 
+    fn has_content( &self ) -> bool
+    {
+        return self.content_buffer_id.is_some();
+    }
+
+    fn has_error( &self ) -> bool
+    {
+        return self.error_buffer_id.is_some();
+    }
+
+    // drop every buffer the guest allocated in the host, nothing is kept from one call to the next
+    fn clear( &mut self )
+    {
+        self.buffer_map.clear();
+        self.content_buffer_id = Option::None;
+        self.messages_buffer_id = Option::None;
+        self.error_buffer_id = Option::None;
+    }
+
     fn consume_content( &mut self ) -> Result<Bytes,Box<std::error::Error>>
     {
         let content_buffer_id_option = self.content_buffer_id;
@@ -115,6 +179,35 @@ impl WasmHost
     }
 
 
+    // the error the guest reported with host_error during the last call
+    fn consume_error( &mut self ) -> Result<GuestError,Box<std::error::Error>>
+    {
+        let error_buffer_id = self.error_buffer_id;
+        if error_buffer_id.is_none()
+        {
+            return Err("error buffer was not created by wasm guest".into());
+        }
+        let error_buffer_option = self.consume_buffer(error_buffer_id.unwrap());
+        if error_buffer_option.is_none()
+        {
+            return Err("error buffer is not available".into());
+        }
+        self.error_buffer_id = Option::None;
+        return GuestError::from_bytes(error_buffer_option.unwrap().to_vec());
+    }
+
+    // the guest only ever fills host buffers out of its own memory so all of the buffers it
+    // has allocated during a call never need to be bigger than that memory
+    fn reserve( &self, len: i32, memory_size: u64 ) -> Result<(),Box<dyn Error>>
+    {
+        let allocated: u64 = self.buffer_map.values().map(|buffer| buffer.capacity() as u64).sum();
+        if allocated + len.max(0) as u64 > memory_size
+        {
+            return Err(format!("wasm guest asked for a buffer of {} bytes on top of the {} it has already which is more than its {} bytes of memory", len, allocated, memory_size).into());
+        }
+        Ok(())
+    }
+
     fn log(&mut self, buffer_id: i32)
     {
         let option = self.consume_string(buffer_id);
@@ -131,7 +224,6 @@ impl WasmHost
 
     fn content_update( &mut self, buffer_id: i32 )
     {
-println!("content_update: {}",buffer_id);
         self.content_buffer_id = Option::Some(buffer_id);
     }
 
@@ -139,127 +231,613 @@ println!("content_update: {}",buffer_id);
     {
         self.messages_buffer_id = Option::Some(buffer_id);
     }
+
+    fn error_update( &mut self, buffer_id: i32 )
+    {
+        self.error_buffer_id = Option::Some(buffer_id);
+    }
 }
 
 #[derive(WasmerEnv, Clone)]
 struct Env {
-    host: Arc<Mutex<WasmHost>>
+    host: Arc<Mutex<WasmHost>>,
+    #[wasmer(export)]
+    memory: LazyInit<Memory>
 }
 
+impl Env
+{
+    fn new( host: Arc<Mutex<WasmHost>> ) -> Self
+    {
+        Env {
+            host: host,
+            memory: LazyInit::new()
+        }
+    }
+
+    fn host( &self ) -> Result<MutexGuard<WasmHost>,RuntimeError>
+    {
+        match self.host.lock()
+        {
+            Ok(host) => Ok(host),
+            Err(_) => Err(RuntimeError::new("wasm host is poisoned"))
+        }
+    }
+
+    fn memory( &self ) -> Result<&Memory,RuntimeError>
+    {
+        match self.memory_ref()
+        {
+            Some(memory) => Ok(memory),
+            None => Err(RuntimeError::new("wasm guest does not export its memory"))
+        }
+    }
+}
 
-impl <'a> WasmBinder<'a>
+// a host function that fails traps the guest, the call into the guest then returns the trap as its error
+fn trap( error: Box<dyn Error> ) -> RuntimeError
 {
-    pub fn new( module: &'a Module ) -> Result<Self,Box<dyn Error>>
+    RuntimeError::new(error.to_string())
+}
+
+// pointers and lengths handed over by the guest are checked against its memory before anything is copied
+fn guest_range( memory: &Memory, ptr: i32, len: i32 ) -> Result<Range<usize>,Box<dyn Error>>
+{
+    if ptr < 0 || len < 0
+    {
+        return Err(format!("wasm guest passed a negative pointer {} or length {}", ptr, len).into());
+    }
+    let start = ptr as usize;
+    match start.checked_add(len as usize)
+    {
+        Some(end) if end as u64 <= memory.data_size() => Ok(start..end),
+        _ => Err(format!("wasm guest passed {} bytes at {} which is outside of its {} bytes of memory", len, ptr, memory.data_size()).into())
+    }
+}
+
+
+impl WasmBinder
+{
+    pub fn new( module: Arc<Module>, limits: MechtronLimits ) -> Result<Self,Box<dyn Error>>
     {
 
         let host = Arc::new( Mutex::new( WasmHost::new() ));
         let imports = imports!{ "env"=>{
-        "host_alloc_buffer"=>Function::new_native_with_env(module.store(),Env{host:host.clone()},|env:&Env,len:i32| {
-                 return env.host.lock().unwrap().alloc_buffer(len);
+        "host_alloc_buffer"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,len:i32| -> Result<i32,RuntimeError> {
+                 let memory_size = env.memory()?.data_size();
+                 let mut host = env.host()?;
+                 host.reserve(len, memory_size).map_err(trap)?;
+                 host.alloc_buffer(len).map_err(trap)
             } ),
-        "host_append_to_buffer"=>Function::new_native_with_env(module.store(),Env{host:host.clone()},|env:&Env,id:i32,value:i32| {
-                 env.host.lock().unwrap().append_to_buffer(id,value as u8);
+        // the guest hands over len bytes starting at ptr in its linear memory which are copied in one go
+        "host_write_to_buffer"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,id:i32,ptr:i32,len:i32| -> Result<(),RuntimeError> {
+                 let memory = env.memory()?;
+                 let range = guest_range(memory, ptr, len).map_err(trap)?;
+                 let bytes = unsafe { &memory.data_unchecked()[range] };
+                 env.host()?.write_to_buffer(id,bytes).map_err(trap)
             } ),
-        "host_dealloc_buffer"=>Function::new_native_with_env(module.store(),Env{host:host.clone()},|env:&Env,id:i32| {
-                 env.host.lock().unwrap().dealloc_buffer(id);
+        "host_dealloc_buffer"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,id:i32| -> Result<(),RuntimeError> {
+                 env.host()?.dealloc_buffer(id).map_err(trap)
             } ),
-        "host_log"=>Function::new_native_with_env(module.store(),Env{host:host.clone()},|env:&Env,buffer_id:i32| {
-                 env.host.lock().unwrap().log(buffer_id);
+        "host_log"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,buffer_id:i32| -> Result<(),RuntimeError> {
+                 env.host()?.log(buffer_id);
+                 Ok(())
             } ),
 
-        "host_content_update"=>Function::new_native_with_env(module.store(),Env{host:host.clone()},|env:&Env,buffer_id:i32| {
-                 env.host.lock().unwrap().content_update(buffer_id);
+        "host_content_update"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,buffer_id:i32| -> Result<(),RuntimeError> {
+                 env.host()?.content_update(buffer_id);
+                 Ok(())
             } ),
-        "host_messages"=>Function::new_native_with_env(module.store(),Env{host:host.clone()},|env:&Env,buffer_id:i32| {
-                 env.host.lock().unwrap().messages_update(buffer_id);
+        "host_messages"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,buffer_id:i32| -> Result<(),RuntimeError> {
+                 env.host()?.messages_update(buffer_id);
+                 Ok(())
+            } ),
+        "host_error"=>Function::new_native_with_env(module.store(),Env::new(host.clone()),|env:&Env,buffer_id:i32| -> Result<(),RuntimeError> {
+                 env.host()?.error_update(buffer_id);
+                 Ok(())
             } ),
         } };
 
-        let instance = Instance::new( module, &imports )?;
+        let instance = Instance::new( &module, &imports )?;
 
         let guest = WasmGuest { instance: instance };
 
-        let binder = WasmBinder{ module: module, guest: guest, host:host };
+        let binder = WasmBinder{ module: module, guest: guest, host:host, limits: limits, written: vec!(), exceeded: false, poisoned: false };
 
         return Ok(binder);
     }
 
     fn log( &self, ptr: i32, len: i32 )
     {}
-}
 
+    fn host( &self ) -> Result<MutexGuard<WasmHost>,Box<dyn Error>>
+    {
+        match self.host.lock()
+        {
+            Ok(host) => Ok(host),
+            Err(_) => Err("wasm host is poisoned".into())
+        }
+    }
 
-pub trait Buffers
-{
-    fn alloc_buffer(&mut self, len: i32) -> i32;
-    fn dealloc_buffer(&mut self,id: i32);
-    fn append_to_buffer(&mut self,id: i32, value: u8 );
-}
+    // the guest opens every buffer it is handed with a factory bound here, so each schema
+    // the tron's content and messages can carry is bound before the guest is called
+    pub fn bind( &mut self, configs: &Configs, tron_config: &TronConfig ) -> Result<(),Box<dyn Error>>
+    {
+        self.meter("bind")?;
+        let mut artifacts = vec!(configs.core_artifact("schema/create/meta")?, configs.core_artifact("schema/empty")?);
+        if let Option::Some(content) = &tron_config.content
+        {
+            artifacts.push(content.artifact.clone());
+        }
+        if let Option::Some(messages) = &tron_config.messages
+        {
+            if let Option::Some(create) = &messages.create
+            {
+                artifacts.push(create.artifact.clone());
+            }
+            for port in &messages.inbound
+            {
+                for artifact in &port.artifact
+                {
+                    artifacts.push(artifact.clone());
+                }
+            }
+            for port in &messages.outbound
+            {
+                artifacts.push(port.artifact.clone());
+            }
+        }
 
-pub trait BuffersSupport
-{
-    fn write_string( &mut self, str: &str ) -> i32;
-    fn write_buffer( &mut self, bytes: &[u8] ) -> i32;
+        let mut bound = vec!();
+        for artifact in artifacts
+        {
+            if !bound.contains(&artifact)
+            {
+                let schema = configs.artifact_cache.get(&artifact)?;
+                self.meter("bind_message_artifact")?;
+                let artifact_buffer_id = self.write_string(artifact.to().as_str())?;
+                let schema_buffer_id = self.write_string(schema.as_str())?;
+                self.call("bind_message_artifact", &[artifact_buffer_id, schema_buffer_id])?;
+                bound.push(artifact);
+            }
+        }
+        Ok(())
+    }
+
+    // the guest is given its mechtron config and tron config in the yaml they were written in
+    pub fn init( &mut self, configs: &Configs, mechtron_config: &MechtronConfig, tron_config: &TronConfig ) -> Result<(),Box<dyn Error>>
+    {
+        self.meter("mechtron_init")?;
+        let mechtron_yaml = configs.artifact_cache.get(&mechtron_config.source)?;
+        let mechtron_config_buffer_id = self.write_string(mechtron_yaml.as_str())?;
+        let mechtron_artifact_buffer_id = self.write_string(mechtron_config.source.to().as_str())?;
+        let tron_yaml = configs.artifact_cache.get(&tron_config.source)?;
+        let tron_config_buffer_id = self.write_string(tron_yaml.as_str())?;
+        let tron_artifact_buffer_id = self.write_string(tron_config.source.to().as_str())?;
+        self.call("mechtron_init", &[mechtron_config_buffer_id, mechtron_artifact_buffer_id, tron_config_buffer_id, tron_artifact_buffer_id])?;
+        Ok(())
+    }
+
+    // nothing a call allocated in the host or in the guest is carried into the next call
+    fn call( &mut self, export: &str, args: &[i32] ) -> Result<GuestOutput,Box<dyn Error>>
+    {
+        let output = match self.call_export(export, args)
+        {
+            Ok(_) => self.take_output(),
+            Err(e) => Err(e)
+        };
+        self.host()?.clear();
+        let released = self.release();
+        let output = output?;
+        released?;
+        Ok(output)
+    }
+
+    // every guest export returns 0 on success
+    fn call_export( &mut self, export: &str, args: &[i32] ) -> Result<(),Box<dyn Error>>
+    {
+        self.meter(export)?;
+
+        let args: Vec<Value> = args.iter().map(|arg| Value::I32(*arg)).collect();
+        let result = match self.guest.instance.exports.get_function(export)?.call(&args)
+        {
+            Ok(result) => result,
+            Err(e) => return Err(self.check_failure(export, e.into()))
+        };
+
+        match result.get(0)
+        {
+            Some(Value::I32(0)) => Ok(()),
+            Some(Value::I32(code)) => {
+                let error = match self.guest_error()?
+                {
+                    Some(guest_error) => guest_error,
+                    None => format!("wasm guest export {} failed with code {}", export, code).into()
+                };
+                Err(self.poison_on_panic(error))
+            }
+            _ => Err(format!("wasm guest export {} did not return a result code", export).into())
+        }
+    }
+
+    fn take_output( &mut self ) -> Result<GuestOutput,Box<dyn Error>>
+    {
+        let mut host = self.host()?;
+        let content = match host.has_content()
+        {
+            true => Option::Some(host.consume_content()?),
+            false => Option::None
+        };
+        let messages = match host.has_messages()
+        {
+            true => Option::Some(host.consume_messages()?),
+            false => Option::None
+        };
+        Ok(GuestOutput{ content: content, messages: messages })
+    }
+
+    // dealloc the buffers written for the call that the guest did not consume.  the deallocs
+    // get a budget of their own so a call that only just fit in its budget is not failed for them
+    fn release( &mut self ) -> Result<(),Box<dyn Error>>
+    {
+        let written: Vec<i32> = self.written.drain(..).collect();
+        if self.exceeded || self.poisoned || written.is_empty()
+        {
+            return Ok(());
+        }
+        self.meter("dealloc_buffer")?;
+        for buffer_id in written
+        {
+            if let Err(e) = self.guest.dealloc_buffer(buffer_id)
+            {
+                return Err(self.check_failure("dealloc_buffer", e));
+            }
+        }
+        Ok(())
+    }
+
+    // the guest runs alloc_buffer and buffer_ptr under the budget of the call the buffer is
+    // written for so a guest stopped in either is failed like it was stopped in the call
+    fn write_buffer( &mut self, bytes: &[u8] ) -> Result<i32,Box<dyn Error>>
+    {
+        let buffer_id = match self.guest.alloc_buffer(i32::try_from(bytes.len())?)
+        {
+            Ok(buffer_id) => buffer_id,
+            Err(e) => return Err(self.check_failure("alloc_buffer", e))
+        };
+        self.written.push(buffer_id);
+        if let Err(e) = self.guest.write_to_buffer(buffer_id, bytes)
+        {
+            return Err(self.check_failure("buffer_ptr", e));
+        }
+        Ok(buffer_id)
+    }
+
+    fn write_string( &mut self, str: &str ) -> Result<i32,Box<dyn Error>>
+    {
+        self.write_buffer(str.as_bytes())
+    }
+
+    // the error the guest reported for the call, if it reported one
+    fn guest_error( &mut self ) -> Result<Option<Box<dyn Error>>,Box<dyn Error>>
+    {
+        let mut host = self.host()?;
+        if !host.has_error()
+        {
+            return Ok(Option::None);
+        }
+        match host.consume_error()
+        {
+            Ok(error) => Ok(Option::Some(Box::new(error))),
+            Err(e) => Ok(Option::Some(e))
+        }
+    }
+
+    fn poison_on_panic( &mut self, error: Box<dyn Error> ) -> Box<dyn Error>
+    {
+        if let Some(guest_error) = error.downcast_ref::<GuestError>()
+        {
+            if guest_error.kind == GuestErrorKind::Panic
+            {
+                self.poisoned = true;
+            }
+        }
+        error
+    }
+
+    // each call gets the full instruction budget of the mechtron.  once the budget runs out
+    // the guest traps in the middle of whatever it was doing
+    fn meter( &self, export: &str ) -> Result<(),Box<dyn Error>>
+    {
+        if self.exceeded
+        {
+            return Err(Box::new(LimitExceeded{ export: export.to_string(), reason: "exceeded its limits on an earlier call and cannot be called again".to_string() }));
+        }
+        if self.poisoned
+        {
+            return Err(format!("wasm guest export {} cannot be called after the guest trapped or panicked on an earlier call", export).into());
+        }
+        set_remaining_points(&self.guest.instance, self.limits.instructions)
+    }
+
+    // a trap after the budget is spent is reported as the limit being exceeded rather than
+    // as whatever instruction the guest happened to be stopped on
+    fn check_failure( &mut self, export: &str, error: Box<dyn Error> ) -> Box<dyn Error>
+    {
+        if let Ok(true) = points_exhausted(&self.guest.instance)
+        {
+            self.exceeded = true;
+            return Box::new(LimitExceeded{ export: export.to_string(), reason: format!("used all of its {} instructions", self.limits.instructions) });
+        }
+        // a trap stops the guest wherever it happened to be so it is not called again
+        if error.is::<RuntimeError>()
+        {
+            self.poisoned = true;
+        }
+        // a guest that panics reports the panic before it traps
+        let error = match self.guest_error()
+        {
+            Ok(Some(guest_error)) => guest_error,
+            Ok(None) => error,
+            Err(e) => e
+        };
+        self.poison_on_panic(error)
+    }
+
+    // replace the data of the content with the buffer the guest handed to host_content_update,
+    // content the guest did not update is left as it was
+    fn consume_content( configs: &Configs, content: &mut Content, output: &GuestOutput ) -> Result<(),Box<dyn Error>>
+    {
+        let bytes = match &output.content
+        {
+            None => return Ok(()),
+            Some(bytes) => bytes
+        };
+        content.data = configs.create_buffer_from_array(&content.artifact, bytes.to_vec())?;
+        Ok(())
+    }
+
+    fn consume_messages( configs: &Configs, output: &GuestOutput ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+    {
+        let bytes = match &output.messages
+        {
+            None => return Ok(Option::None),
+            Some(bytes) => bytes
+        };
+        let builders = MessageBuilder::message_builders_from_bytes(configs, bytes.to_vec())?;
+        if builders.is_empty()
+        {
+            return Ok(Option::None);
+        }
+        Ok(Option::Some(builders))
+    }
+
+    // hand the content data and a MESSAGE_SCHEMA buffer of messages to a guest export and collect what it returns
+    fn invoke( &mut self, configs: &Configs, export: &str, args: &[i32], content: &mut Content, messages: &[&Message] ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+    {
+        let mut args = args.to_vec();
+        args.push(self.write_buffer(content.data.read_bytes())?);
+        if !messages.is_empty()
+        {
+            let messages = Message::messages_to_buffer(messages)?;
+            args.push(self.write_buffer(messages.read_bytes())?);
+        }
+        let output = self.call(export, &args)?;
+        WasmBinder::consume_content(configs, content, &output)?;
+        WasmBinder::consume_messages(configs, &output)
+    }
+
+    pub fn create( &mut self, configs: &Configs, content: &mut Content, create: &Message ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+    {
+        self.meter("mechtron_create")?;
+        self.invoke(configs, "mechtron_create", &[], content, &[create])
+    }
+
+    pub fn update( &mut self, configs: &Configs, content: &mut Content, phase: &str ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+    {
+        self.meter("mechtron_update")?;
+        let phase_buffer_id = self.write_string(phase)?;
+        self.invoke(configs, "mechtron_update", &[phase_buffer_id], content, &[])
+    }
+
+    pub fn port( &mut self, configs: &Configs, content: &mut Content, port: &str, messages: &Vec<Message> ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+    {
+        self.meter("mechtron_port")?;
+        let port_buffer_id = self.write_string(port)?;
+        let messages: Vec<&Message> = messages.iter().collect();
+        self.invoke(configs, "mechtron_port", &[port_buffer_id], content, &messages)
+    }
 }
 
-pub trait BufferConsume
+// a tron implemented by a wasm guest.  the module is the wasm of the tron's mechtron config,
+// it is instantiated when the tron is initialized and create, update and port calls are
+// forwarded to the guest's exports
+pub struct WasmTron
 {
-    fn consume_buffer(&mut self, id: i32) -> Option<Bytes>;
+    mechtron_config: Arc<MechtronConfig>,
+    binder: Mutex<WasmBinder>
 }
 
-impl <'a> Buffers for WasmGuest
+impl WasmTron
 {
-    fn alloc_buffer(&mut self, len: i32) -> i32 {
-        return self.instance.exports.get_native_function::<i32, i32>("alloc_buffer").unwrap().call(len).unwrap();
+    fn module( mechtron_config: &MechtronConfig ) -> Result<Arc<Module>,Box<dyn Error>>
+    {
+        Ok(SYS.local.wasm_modules.get(mechtron_config)?)
     }
 
-    fn dealloc_buffer(&mut self, id: i32) {
-        self.instance.exports.get_function("dealloc_buffer").unwrap().call(&[Value::I32(id)]);
+    fn binder( &self ) -> Result<MutexGuard<WasmBinder>,Box<dyn Error>>
+    {
+        match self.binder.lock()
+        {
+            Ok(binder) => Ok(binder),
+            Err(_) => Err(format!("the wasm binder of mechtron {} is poisoned", self.mechtron_config.source.to()).into())
+        }
+    }
+
+    // init is not called through a TronShell so a guest error is attached to the tron here
+    fn init_error( context: &Context, mechtron_config: &MechtronConfig, call: &str, error: Box<dyn Error> ) -> Box<dyn Error>
+    {
+        match error.downcast::<GuestError>()
+        {
+            Ok(guest_error) => Box::new(MechtronError {
+                tron: context.id.clone(),
+                source: mechtron_config.source.clone(),
+                call: call.to_string(),
+                error: *guest_error
+            }),
+            Err(error) => error
+        }
     }
 
-    fn append_to_buffer(&mut self, id: i32, value: u8) {
-        self.instance.exports.get_function("append_to_buffer").unwrap().call(&[Value::I32(id), Value::I32(value as i32)]);
+    // the nucleus needs to know when a limit was exceeded and the TronShell attaches the
+    // tron to errors reported by the guest, so both are passed through as is
+    fn error( &self, context: &Context, call: &str, error: Box<dyn Error> ) -> Box<dyn Error>
+    {
+        if error.is::<LimitExceeded>() || error.is::<GuestError>()
+        {
+            return error;
+        }
+        format!("mechtron {} of tron {:?} failed in {}: {}", self.mechtron_config.source.to(), context.id, call, error).into()
     }
 }
 
-impl <'a> BuffersSupport for WasmGuest
+impl Tron for WasmTron
 {
-    fn write_string( &mut self, str: &str ) -> i32
+    fn init(context: Context) -> Result<Box<Self>, Box<dyn Error>> where Self: Sized {
+        let mechtron_config = match &context.mechtron_config {
+            None => return Err(format!("tron {:?} ({}) is not a mechtron", context.id, context.tron_config.source.to()).into()),
+            Some(mechtron_config) => mechtron_config.clone()
+        };
+
+        let mut binder = WasmBinder::new(WasmTron::module(&mechtron_config)?, mechtron_config.limits.clone())?;
+        if let Err(error) = binder.bind(context.configs(), &context.tron_config)
+        {
+            return Err(WasmTron::init_error(&context, &mechtron_config, "bind", error));
+        }
+        if let Err(error) = binder.init(context.configs(), &mechtron_config, &context.tron_config)
+        {
+            return Err(WasmTron::init_error(&context, &mechtron_config, "init", error));
+        }
+
+        Ok(Box::new(WasmTron {
+            mechtron_config: mechtron_config,
+            binder: Mutex::new(binder)
+        }))
+    }
+
+    fn create(&self, context: &Context, content: &mut Content, create: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>> {
+        match self.binder()?.create(context.configs(), content, create)
+        {
+            Ok(builders) => Ok(builders),
+            Err(e) => Err(self.error(context, "create", e))
+        }
+    }
+
+    fn update(&self, phase: &str) -> Result<fn(&Context, &mut Content) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+        Err("a mechtron's update is invoked through its wasm guest".into())
+    }
+
+    fn port(&self, port: &str) -> Result<fn(&Context, &mut Content, &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>, Box<dyn Error>> {
+        Err("a mechtron's ports are invoked through its wasm guest".into())
+    }
+
+    fn update_phases(&self) -> UpdatePhases {
+        match &self.mechtron_config.phases {
+            None => UpdatePhases::All,
+            Some(phases) if phases.is_empty() => UpdatePhases::None,
+            Some(phases) => UpdatePhases::Some(phases.clone())
+        }
+    }
+
+    fn invoke_update(&self, context: &Context, content: &mut Content, phase: &str) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>
     {
-        let buffer_id = self.alloc_buffer(str.len() as i32 );
-        for b in str.bytes()
+        match self.binder()?.update(context.configs(), content, phase)
         {
-            self.append_to_buffer(buffer_id,b);
+            Ok(builders) => Ok(builders),
+            Err(e) => Err(self.error(context, format!("update of phase '{}'", phase).as_str(), e))
         }
-        return buffer_id;
     }
 
-    fn write_buffer( &mut self, bytes: &[u8] ) -> i32
+    fn invoke_port(&self, context: &Context, content: &mut Content, port: &str, messages: &Vec<Message>) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>
     {
-        let buffer_id = self.alloc_buffer(bytes.len() as i32 );
-        for b in bytes
+        match self.binder()?.port(context.configs(), content, port, messages)
         {
-            self.append_to_buffer(buffer_id, *b );
+            Ok(builders) => Ok(builders),
+            Err(e) => Err(self.error(context, format!("port '{}'", port).as_str(), e))
         }
-        return buffer_id;
     }
 }
 
-impl <'a> WasmGuest
+
+pub trait Buffers
+{
+    fn alloc_buffer(&mut self, len: i32) -> Result<i32,Box<dyn Error>>;
+    fn dealloc_buffer(&mut self,id: i32) -> Result<(),Box<dyn Error>>;
+    fn write_to_buffer(&mut self,id: i32, bytes: &[u8] ) -> Result<(),Box<dyn Error>>;
+}
+
+pub trait BuffersSupport
 {
+    fn write_string( &mut self, str: &str ) -> Result<i32,Box<dyn Error>>;
+    fn write_buffer( &mut self, bytes: &[u8] ) -> Result<i32,Box<dyn Error>>;
+}
 
-    pub fn bind_message_artifact(&mut self, artifact_file: &Artifact, artifact_file_contents: &str ) -> Result<(),Box<std::error::Error>>
+pub trait BufferConsume
+{
+    fn consume_buffer(&mut self, id: i32) -> Option<Bytes>;
+}
+
+impl <'a> Buffers for WasmGuest
+{
+    fn alloc_buffer(&mut self, len: i32) -> Result<i32,Box<dyn Error>> {
+        Ok(self.instance.exports.get_native_function::<i32, i32>("alloc_buffer")?.call(len)?)
+    }
+
+    fn dealloc_buffer(&mut self, id: i32) -> Result<(),Box<dyn Error>> {
+        self.instance.exports.get_function("dealloc_buffer")?.call(&[Value::I32(id)])?;
+        Ok(())
+    }
+
+    // the guest allocates the buffer in its linear memory and tells us where, the bytes
+    // are then copied straight into that memory
+    fn write_to_buffer(&mut self, id: i32, bytes: &[u8]) -> Result<(),Box<dyn Error>> {
+        let ptr = self.instance.exports.get_native_function::<i32, i32>("buffer_ptr")?.call(id)?;
+        // the guest answers -1 for a buffer it does not have
+        if ptr < 0
+        {
+            return Err(format!("wasm guest does not have a buffer with id {}", id).into());
+        }
+        let memory = self.instance.exports.get_memory("memory")?;
+        let range = guest_range(memory, ptr, i32::try_from(bytes.len())?)?;
+        unsafe {
+            memory.data_unchecked_mut()[range].copy_from_slice(bytes);
+        }
+        Ok(())
+    }
+}
+
+impl <'a> BuffersSupport for WasmGuest
+{
+    fn write_string( &mut self, str: &str ) -> Result<i32,Box<dyn Error>>
+    {
+        self.write_buffer(str.as_bytes())
+    }
+
+    fn write_buffer( &mut self, bytes: &[u8] ) -> Result<i32,Box<dyn Error>>
     {
-        let artifact_file_buffer_id = self.write_string(&artifact_file.to() );
-        let artifact_file_contents_buffer_id = self.write_string(artifact_file_contents);
-        self.instance.exports.get_function("bind_message_artifact").unwrap().call(&[Value::I32(artifact_file_buffer_id), Value::I32(artifact_file_contents_buffer_id)])?;
-        return Ok(());
+        let buffer_id = self.alloc_buffer(i32::try_from(bytes.len())?)?;
+        self.write_to_buffer(buffer_id, bytes)?;
+        Ok(buffer_id)
     }
 }
 
 impl Buffers for WasmHost
 {
-    fn alloc_buffer(&mut self, len: i32) -> i32 {
+    fn alloc_buffer(&mut self, len: i32) -> Result<i32,Box<dyn Error>> {
+        if len < 0
+        {
+            return Err(format!("wasm guest asked for a buffer of negative length {}", len).into());
+        }
         self.buffer_index = self.buffer_index+1;
         let ptr = self.buffer_index.clone();
 
@@ -267,17 +845,24 @@ impl Buffers for WasmHost
 
         self.buffer_map.insert( ptr, buffer );
 
-        return ptr;
+        return Ok(ptr);
     }
 
-    fn dealloc_buffer(&mut self, id: i32) {
+    fn dealloc_buffer(&mut self, id: i32) -> Result<(),Box<dyn Error>> {
         self.buffer_map.remove(&id);
+        Ok(())
     }
 
-    fn append_to_buffer(&mut self, id: i32, value: u8) {
-        let option = self.buffer_map.get_mut(&id);
-        let buffer = option.unwrap();
-        buffer.put_u8(value as u8)
+    fn write_to_buffer(&mut self, id: i32, bytes: &[u8]) -> Result<(),Box<dyn Error>> {
+        match self.buffer_map.get_mut(&id)
+        {
+            None => Err(format!("wasm guest wrote to host buffer {} which does not exist", id).into()),
+            Some(buffer) if buffer.capacity() - buffer.len() < bytes.len() => Err(format!("wasm guest wrote {} bytes to host buffer {} which only has room for {} more", bytes.len(), id, buffer.capacity() - buffer.len()).into()),
+            Some(buffer) => {
+                buffer.put_slice(bytes);
+                Ok(())
+            }
+        }
     }
 }
 
@@ -297,3 +882,90 @@ impl BufferConsume for WasmHost
 }
 
 
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+    use std::sync::Arc;
+
+    use mechtron_common::configs::MechtronLimits;
+
+    use crate::limits::compile;
+    use crate::wasm::{LimitExceeded, WasmBinder};
+
+    static GUEST: &'static str = r#"(module
+        (import "env" "host_alloc_buffer" (func $host_alloc_buffer (param i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "done") (result i32) i32.const 0)
+        (func (export "spin") (result i32) (loop br 0) i32.const 0)
+        (func (export "trap") (result i32) unreachable)
+        (func (export "grow") (result i32) i32.const 8 memory.grow drop i32.const 0)
+        (func (export "alloc_half") (result i32) i32.const 40000 call $host_alloc_buffer drop i32.const 0)
+        (func (export "alloc_double") (result i32) i32.const 131072 call $host_alloc_buffer drop i32.const 0))"#;
+
+    fn binder( wasm: &str, limits: MechtronLimits ) -> Result<WasmBinder,Box<dyn Error>>
+    {
+        let module = Arc::new(compile(wasm.as_bytes(), &limits)?);
+        WasmBinder::new(module, limits)
+    }
+
+    #[test]
+    fn a_looping_guest_is_stopped_at_its_instruction_limit() -> Result<(),Box<dyn Error>>
+    {
+        let mut binder = binder(GUEST, MechtronLimits{ instructions: 10_000, memory_pages: 2 })?;
+        binder.call("done", &[])?;
+
+        match binder.call("spin", &[])
+        {
+            Ok(_) => panic!("a guest that never stops returned"),
+            Err(e) => assert!(e.is::<LimitExceeded>())
+        }
+
+        // a guest stopped part way through a call is never called again
+        match binder.call("done", &[])
+        {
+            Ok(_) => panic!("a guest that exceeded its limits was called again"),
+            Err(e) => assert!(e.is::<LimitExceeded>())
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn a_guest_that_traps_is_not_called_again() -> Result<(),Box<dyn Error>>
+    {
+        let mut binder = binder(GUEST, MechtronLimits{ instructions: 10_000, memory_pages: 2 })?;
+        assert!(binder.call("trap", &[]).is_err());
+        assert!(binder.call("done", &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn memory_does_not_grow_past_its_limit() -> Result<(),Box<dyn Error>>
+    {
+        let mut binder = binder(GUEST, MechtronLimits{ instructions: 10_000, memory_pages: 2 })?;
+        binder.call("grow", &[])?;
+        assert_eq!(binder.guest.instance.exports.get_memory("memory")?.size().0, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn host_buffers_are_capped_at_the_guest_memory_and_dropped_after_each_call() -> Result<(),Box<dyn Error>>
+    {
+        let mut binder = binder(GUEST, MechtronLimits{ instructions: 10_000, memory_pages: 2 })?;
+        // two buffers of more than half of the guest's memory only fit when the first is dropped
+        binder.call("alloc_half", &[])?;
+        binder.call("alloc_half", &[])?;
+        assert!(binder.host.lock().unwrap().buffer_map.is_empty());
+
+        assert!(binder.call("alloc_double", &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn a_guest_that_starts_with_more_memory_than_its_limit_is_not_instantiated()
+    {
+        let guest = r#"(module (memory (export "memory") 4))"#;
+        assert!(binder(guest, MechtronLimits{ instructions: 10_000, memory_pages: 2 }).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::error::Error;
+
+use mechtron::app::SYS;
+use mechtron::content::{ContentIntake, InterCyclicContentStructure, IntraCyclicContentStructure};
+use mechtron_common::content::Content;
+use mechtron_common::id::{ContentKey, Id, Revision, TronKey};
+
+fn empty_content() -> Result<Content,Box<dyn Error>>
+{
+    let artifact = SYS.local.configs.core_artifact("schema/empty")?;
+    SYS.local.configs.buffer_factory_keeper.cache(&artifact)?;
+    Ok(Content::new(&SYS.local.configs, artifact))
+}
+
+// each nucleus is revised from its own content only, the trons of one nucleus must never be copied into another
+#[test]
+fn nucleus_content_holds_only_the_trons_of_that_nucleus() -> Result<(),Box<dyn Error>>
+{
+    let revision = Revision{ cycle: 1 };
+    let left = Id::new(0, 100);
+    let right = Id::new(0, 200);
+
+    let mut content = InterCyclicContentStructure::new();
+    for (nucleus_id, tron_id) in vec![(&left, 101), (&left, 102), (&right, 201)]
+    {
+        let key = ContentKey{ tron_id: TronKey::new(nucleus_id.clone(), Id::new(0, tron_id)), revision: revision.clone() };
+        content.intake(empty_content()?, key)?;
+    }
+
+    for (nucleus_id, trons) in vec![(&left, 2), (&right, 1)]
+    {
+        let nucleus_content = content.query_nucleus_content(nucleus_id, &revision, &SYS.local.configs)?;
+        assert_eq!(trons, nucleus_content.len());
+        for (_, key) in nucleus_content
+        {
+            assert_eq!(*nucleus_id, key.tron_id.nucleus_id);
+        }
+    }
+
+    Ok(())
+}
+
+// a tron is created once, a second Create for the same tron must not replace its content
+#[test]
+fn created_content_is_not_replaced() -> Result<(),Box<dyn Error>>
+{
+    let revision = Revision{ cycle: 1 };
+    let key = ContentKey{ tron_id: TronKey::new(Id::new(0, 100), Id::new(0, 101)), revision: revision.clone() };
+
+    let mut content = IntraCyclicContentStructure::new(revision);
+    content.create(key.clone(), empty_content()?)?;
+    assert!(content.create(key, empty_content()?).is_err());
+
+    Ok(())
+}
+
+// a tron destroyed in this cycle has no content in the next revision
+#[test]
+fn destroyed_content_is_not_kept() -> Result<(),Box<dyn Error>>
+{
+    let revision = Revision{ cycle: 1 };
+    let key = ContentKey{ tron_id: TronKey::new(Id::new(0, 100), Id::new(0, 101)), revision: revision.clone() };
+
+    let mut content = IntraCyclicContentStructure::new(revision);
+    content.create(key.clone(), empty_content()?)?;
+    content.destroy(&key.tron_id.tron_id);
+
+    assert!(!content.contains(&key.tron_id.tron_id));
+    assert!(content.tron_ids().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+use mechtron::app::SYS;
+use mechtron::scheduler::{Run, SchedulerState};
+use mechtron_common::artifact::Artifact;
+use mechtron_common::id::Id;
+
+fn launch() -> Result<Id,Box<dyn Error>>
+{
+    let sim_artifact = Artifact::from("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml")?;
+    SYS.local.configs.sim_config_keeper.cache(&sim_artifact)?;
+    let sim_config = SYS.local.configs.sim_config_keeper.get(&sim_artifact)?;
+    Ok(SYS.local.sources.launch(sim_config)?)
+}
+
+#[test]
+fn a_sim_can_be_driven_again_after_a_run() -> Result<(),Box<dyn Error>>
+{
+    let sim_id = launch()?;
+    let source = SYS.local.sources.get(&sim_id)?;
+    let head = source.head()?.cycle;
+
+    let scheduler = SYS.local.sources.schedule(&sim_id, Run::Cycles(2))?;
+    scheduler.start()?;
+    assert_eq!(source.head()?.cycle, head + 2);
+
+    scheduler.start()?;
+    assert_eq!(source.head()?.cycle, head + 4);
+
+    // the finished scheduler gives up its place to a scheduler with another run
+    let scheduler = SYS.local.sources.schedule(&sim_id, Run::Cycles(1))?;
+    scheduler.start()?;
+    assert_eq!(source.head()?.cycle, head + 5);
+
+    Ok(())
+}
+
+#[test]
+fn a_running_scheduler_keeps_its_place_until_it_is_stopped() -> Result<(),Box<dyn Error>>
+{
+    let sim_id = launch()?;
+    let scheduler = SYS.local.sources.schedule(&sim_id, Run::Rate(1000.0))?;
+
+    let running = scheduler.clone();
+    let run = thread::spawn(move || running.start().is_ok());
+    let mut waited = 0;
+    while scheduler.state()? == SchedulerState::Idle
+    {
+        assert!(waited < 1000, "the scheduler did not start");
+        thread::sleep(Duration::from_millis(1));
+        waited = waited + 1;
+    }
+
+    assert!(scheduler.start().is_err());
+    assert!(SYS.local.sources.schedule(&sim_id, Run::Cycles(1)).is_err());
+
+    scheduler.pause()?;
+    assert_eq!(scheduler.state()?, SchedulerState::Paused);
+    scheduler.resume()?;
+
+    scheduler.stop()?;
+    assert!(run.join().unwrap());
+    assert_eq!(scheduler.state()?, SchedulerState::Idle);
+    assert!(SYS.local.sources.schedule(&sim_id, Run::Cycles(1)).is_ok());
+
+    Ok(())
+}
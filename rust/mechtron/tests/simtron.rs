@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use no_proto::json_flex::NP_JSON;
+use no_proto::memory::NP_Memory_Owned;
+
+use mechtron::app::SYS;
+use mechtron::incident::Incident;
+use mechtron::lookup::LookupCache;
+use mechtron::message::MessageIntake;
+use mechtron::scheduler::Run;
+use mechtron::source::Source;
+use mechtron::tron::{Context, CreatePayloadsBuilder, Neutron, NeutronContentInterface, SIMTRON, SIMULATION_NUCLEUS};
+use mechtron_common::artifact::{Artifact, ArtifactCacher};
+use mechtron_common::buffers::get;
+use mechtron_common::configs::{DataRef, SimCreateTronConfig, SimTronConfig};
+use mechtron_common::content::Content;
+use mechtron_common::id::{Id, IdSeq, Revision, TronKey};
+use mechtron_common::message::{From, Message, MessageKind, To};
+
+fn run( sim: &str, cycles: i64 ) -> Result<Arc<Source>,Box<dyn Error>>
+{
+    let sim_artifact = Artifact::from(sim)?;
+    SYS.local.configs.sim_config_keeper.cache(&sim_artifact)?;
+    let sim_config = SYS.local.configs.sim_config_keeper.get(&sim_artifact)?;
+
+    let sim_id = SYS.local.sources.launch(sim_config)?;
+    let scheduler = SYS.local.sources.schedule(&sim_id, Run::Cycles(cycles))?;
+    scheduler.start()?;
+    Ok(SYS.local.sources.get(&sim_id)?)
+}
+
+// a map of lookup names to ids kept in the content of a tron at the head revision
+fn names( source: &Source, tron_key: &TronKey, column: &str ) -> Result<HashMap<String,Id>,Box<dyn Error>>
+{
+    for (content, key) in source.content.query_nucleus_content(&tron_key.nucleus_id, &source.head()?, &SYS.local.configs)?
+    {
+        if key.tron_id == *tron_key
+        {
+            let mut rtn = HashMap::new();
+            if let Ok(NP_JSON::Dictionary(names)) = content.data.json_encode(&[column])
+            {
+                for (name, value) in &names.values
+                {
+                    if let (Some(seq_id), Some(id)) = (value["seq_id"].into_i64(), value["id"].into_i64())
+                    {
+                        rtn.insert(name.clone(), Id::new(seq_id.clone(), id.clone()));
+                    }
+                }
+            }
+            return Ok(rtn);
+        }
+    }
+    Err(format!("there is no content for tron {:?}", tron_key).into())
+}
+
+// the lookup names registered with the neutron of a nucleus
+fn tron_names( source: &Source, nucleus_id: &Id ) -> Result<HashMap<String,Id>,Box<dyn Error>>
+{
+    names(source, &Neutron::key(nucleus_id), "tron_names")
+}
+
+// the create data of a sim tron is json that is converted into the constructor of its Create message
+#[test]
+fn create_data_becomes_the_constructor() -> Result<(),Box<dyn Error>>
+{
+    let sim_tron = SimTronConfig{
+        name: Option::None,
+        nucleus: Option::None,
+        artifact: Artifact::from("uberscott.com:examples:1.0.0:hello/mechtron/hello.yaml")?,
+        create: Option::Some(SimCreateTronConfig{
+            data: DataRef{ artifact: Artifact::from("uberscott.com:examples:1.0.0:hello/data/hello-message.json")? }
+        })
+    };
+    sim_tron.cache(&mut SYS.local.configs)?;
+
+    let payloads = CreatePayloadsBuilder::sim_tron(&SYS.local.configs, &sim_tron)?;
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[1].artifact, Artifact::from("uberscott.com:examples:1.0.0:hello/schema/greeting.json")?);
+    assert_eq!(get::<String,NP_Memory_Owned>(&payloads[1].buffer, &[])?, "Hello World");
+    Ok(())
+}
+
+// besides the simulation nucleus the SimTron creates one nucleus for each nucleus named in the
+// sim config, and each of them holds only the trons listed for it
+#[test]
+fn each_nucleus_in_the_sim_config_is_created_with_its_trons() -> Result<(),Box<dyn Error>>
+{
+    let source = run("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml", 4)?;
+
+    let nuclei = source.content.query_nuclei(&source.head()?)?;
+    assert_eq!(nuclei.len(), 3);
+
+    let mut simulation = 0;
+    let mut stdout = 0;
+    for nucleus_id in &nuclei
+    {
+        let names = tron_names(&source, nucleus_id)?;
+        if names.contains_key(SIMTRON)
+        {
+            simulation += 1;
+        }
+        if names.contains_key("stdout")
+        {
+            stdout += 1;
+        }
+    }
+    assert_eq!(simulation, 1);
+    assert_eq!(stdout, 2);
+
+    Ok(())
+}
+
+fn simtron_key( source: &Source ) -> Result<TronKey,Box<dyn Error>>
+{
+    for nucleus_id in source.content.query_nuclei(&source.head()?)?
+    {
+        if let Option::Some(tron_id) = tron_names(source, &nucleus_id)?.get(SIMTRON)
+        {
+            return Ok(TronKey::new(nucleus_id.clone(), tron_id.clone()));
+        }
+    }
+    Err("no nucleus holds the SimTron".into())
+}
+
+// every nucleus is registered with the SimTron under its lookup name, the simulation nucleus included
+#[test]
+fn nuclei_are_registered_with_the_simtron() -> Result<(),Box<dyn Error>>
+{
+    let source = run("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml", 4)?;
+
+    let nuclei = source.content.query_nuclei(&source.head()?)?;
+    let simtron_key = simtron_key(&source)?;
+    let nucleus_names = names(&source, &simtron_key, "nucleus_names")?;
+    assert_eq!(nucleus_names.len(), 3);
+    assert_eq!(nucleus_names.get(SIMULATION_NUCLEUS), Option::Some(&simtron_key.nucleus_id));
+    for name in &["left", "right"]
+    {
+        let nucleus_id = nucleus_names.get(*name).ok_or(format!("nucleus '{}' is not registered", name))?;
+        assert!(nuclei.contains(nucleus_id));
+        assert_ne!(*nucleus_id, simtron_key.nucleus_id);
+    }
+    assert_ne!(nucleus_names.get("left"), nucleus_names.get("right"));
+
+    Ok(())
+}
+
+// a destroyed tron is removed from the registry of its neutron, its lookup name included
+#[test]
+fn a_destroyed_tron_is_forgotten_by_its_neutron() -> Result<(),Box<dyn Error>>
+{
+    let neutron_config = SYS.local.configs.core_tron_config("tron/neutron")?;
+    let artifact = neutron_config.content.as_ref().ok_or("the neutron does not declare content")?.artifact.clone();
+    SYS.local.configs.buffer_factory_keeper.cache(&artifact)?;
+    let mut content = Content::new(&SYS.local.configs, artifact);
+
+    let kept = TronKey::new(Id::new(0, 100), Id::new(0, 101));
+    let destroyed = TronKey::new(Id::new(0, 100), Id::new(0, 102));
+    let interface = NeutronContentInterface{};
+    for (name, tron) in vec![("kept", &kept), ("destroyed", &destroyed)]
+    {
+        interface.add_tron(&mut content, tron, 0)?;
+        interface.set_tron_name(&mut content, name, tron)?;
+    }
+
+    Neutron{}.destroy_tron(&mut content, &destroyed)?;
+
+    match content.data.json_encode(&["tron"])
+    {
+        Ok(NP_JSON::Array(trons)) => {
+            assert_eq!(trons.len(), 1);
+            assert_eq!(trons[0]["id"].into_i64(), Option::Some(&kept.tron_id.id));
+        },
+        _ => panic!("the neutron content does not have a tron list")
+    }
+    match content.data.json_encode(&["tron_names"])
+    {
+        Ok(NP_JSON::Dictionary(names)) => {
+            assert!(names.has("kept"));
+            assert!(!names.has("destroyed"));
+        },
+        _ => panic!("the neutron content does not have tron names")
+    }
+
+    Ok(())
+}
+
+// names are resolved from the content of the previous revision, starting from the neutron of the nucleus of the tron looking them up
+#[test]
+fn nuclei_and_trons_are_looked_up_by_name() -> Result<(),Box<dyn Error>>
+{
+    let source = run("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml", 4)?;
+    let simtron_key = simtron_key(&source)?;
+    let nucleus_names = names(&source, &simtron_key, "nucleus_names")?;
+    let left = nucleus_names.get("left").ok_or("nucleus 'left' is not registered")?.clone();
+    let right = nucleus_names.get("right").ok_or("nucleus 'right' is not registered")?.clone();
+    let left_stdout = tron_names(&source, &left)?.get("stdout").ok_or("left has no stdout")?.clone();
+    let right_stdout = tron_names(&source, &right)?.get("stdout").ok_or("right has no stdout")?.clone();
+
+    let revision = Revision{ cycle: source.head()?.cycle + 1 };
+    let context = Context{
+        sim_id: source.id().clone(),
+        id: TronKey::new(left.clone(), left_stdout),
+        revision: revision.clone(),
+        tron_config: SYS.local.configs.core_tron_config("tron/neutron")?,
+        mechtron_config: Option::None,
+        timestamp: 0,
+        lookups: Arc::new(LookupCache::new(revision)),
+        id_seq: Arc::new(IdSeq::new(99))
+    };
+
+    assert_eq!(context.simulation_nucleus_id()?, simtron_key.nucleus_id);
+    assert_eq!(context.lookup_nucleus("right")?, right);
+    assert_eq!(context.lookup_tron(&right, "stdout")?, TronKey::new(right.clone(), right_stdout));
+    // resolved again from the cache
+    assert_eq!(context.lookup_nucleus("right")?, right);
+    assert!(context.lookup_nucleus("middle").is_err());
+    assert!(context.lookup_tron(&right, "printer").is_err());
+
+    Ok(())
+}
+
+// a message that can never be delivered is rejected back to its sender, the revision it
+// arrives in still commits
+#[test]
+fn a_message_that_cannot_be_delivered_does_not_stop_the_cycle() -> Result<(),Box<dyn Error>>
+{
+    let source = run("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml", 4)?;
+    let simtron_key = simtron_key(&source)?;
+
+    // only a neutron can create a tron
+    let not_a_neutron = TronKey::new(simtron_key.nucleus_id.clone(), Id::new(simtron_key.nucleus_id.seq_id, 999));
+    assert!(rejected(&source, &simtron_key, MessageKind::Create, not_a_neutron)?);
+
+    Ok(())
+}
+
+// send a message without payloads from 'from' to 'to', revise the next cycle and tell whether it was rejected
+fn rejected( source: &Source, from: &TronKey, kind: MessageKind, to: TronKey ) -> Result<bool,Box<dyn Error>>
+{
+    let head = source.head()?;
+    let message = Message::longform(&IdSeq::new(99),
+                                    kind,
+                                    From{ tron: from.clone(), cycle: head.cycle, timestamp: 0 },
+                                    To::basic(to, "create".to_string()),
+                                    vec!(),
+                                    Option::None,
+                                    Option::None);
+    source.messaging.read()?.cyclic_intake().intake(message.clone())?;
+    source.take_incidents()?;
+
+    assert_eq!(source.next()?.cycle, head.cycle + 1);
+    assert_eq!(source.head()?.cycle, head.cycle + 1);
+    Ok(source.take_incidents()?.iter().any(|incident| match incident {
+        Incident::MessageRejected{ message: id, .. } => *id == message.id,
+        _ => false
+    }))
+}
+
+// a Create must carry the create meta and the constructor of the tron to create
+#[test]
+fn a_create_without_its_payloads_is_rejected() -> Result<(),Box<dyn Error>>
+{
+    let source = run("mechtron.io:examples:0.0.1:two-nuclei-simulation.yaml", 4)?;
+    let simtron_key = simtron_key(&source)?;
+
+    assert!(rejected(&source, &simtron_key, MessageKind::Create, Neutron::key(&simtron_key.nucleus_id))?);
+
+    Ok(())
+}
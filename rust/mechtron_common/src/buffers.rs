@@ -29,6 +29,14 @@ pub fn get<'get, X: 'get,M: NP_Memory + Clone + NP_Mem_New>(buffer:&'get NP_Buff
 }
 
 
+// like get() but a value that has not been set is None rather than an error
+pub fn get_option<'get, X: 'get,M: NP_Memory + Clone + NP_Mem_New>(buffer:&'get NP_Buffer<M>, path: &[&str]) -> Result<Option<X>, Box<dyn Error>> where X: NP_Value<'get> + NP_Scalar<'get> {
+    match buffer.get::<X>(path)
+    {
+        Ok(option)=>Ok(option),
+        Err(e)=>Err(format!("could not get {}",cat(path)).into())
+    }
+}
 
 pub fn set<'get, X: 'get,M: NP_Memory + Clone + NP_Mem_New>(buffer:&'get mut NP_Buffer<M>, path: &[&str], value: X) -> Result<bool, Box<dyn Error>> where X: NP_Value<'get> + NP_Scalar<'get> {
     match buffer.set::<X>(path, value)
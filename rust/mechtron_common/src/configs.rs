@@ -9,9 +9,17 @@ use no_proto::NP_Factory;
 use serde::{Deserialize, Serialize};
 
 use crate::artifact::{Artifact, ArtifactBundle, ArtifactCache, ArtifactCacher, ArtifactRepository, ArtifactYaml};
+use crate::buffers::BufferFactories;
+use no_proto::buffer::NP_Buffer;
+use no_proto::memory::NP_Memory_Owned;
 
 
 pub static CORE_BUNDLE: &'static str = "mechtron.io:core:0.0.1";
+pub static DEFAULT_REQUEST_TIMEOUT_CYCLES: i64 = 10;
+pub static DEFAULT_DELIVERY_HORIZON_CYCLES: i64 = 1024;
+pub static DEFAULT_MECHTRON_INSTRUCTIONS: u64 = 100_000_000;
+// wasm memory is counted in 64KiB pages, this is 64MiB
+pub static DEFAULT_MECHTRON_MEMORY_PAGES: u32 = 1024;
 static CORE_BUNDLE_FMT: &'static str = "mechtron.io:core:0.0.1:{}";
 
 pub struct Configs
@@ -43,6 +51,8 @@ impl Configs{
         configs.core_artifacts.insert("schema/empty".to_string(), Artifact::from(format!( "mechtron.io:core:{}:{}", version,"schema/empty.json").as_str())?);
         configs.core_artifacts.insert("schema/content/meta".to_string(), Artifact::from(format!( "mechtron.io:core:{}:{}", version,"schema/tron/content-meta.json").as_str())?);
         configs.core_artifacts.insert("schema/create/meta".to_string(), Artifact::from(format!( "mechtron.io:core:{}:{}", version,"schema/tron/create-meta.json").as_str())?);
+        configs.core_artifacts.insert("schema/sim/register-nucleus".to_string(), Artifact::from(format!( "mechtron.io:core:{}:{}", version,"schema/sim/register-nucleus.json").as_str())?);
+        configs.core_artifacts.insert("schema/sim/destroy-nucleus".to_string(), Artifact::from(format!( "mechtron.io:core:{}:{}", version,"schema/sim/destroy-nucleus.json").as_str())?);
 
         return configs;
     }
@@ -64,10 +74,76 @@ impl Configs{
     {
         Ok(self.buffer_factory_keeper.get(&self.core_artifact(id)?)?.clone())
     }
+
+    // a tron is either described directly by a tron config or by a mechtron config which
+    // binds a tron config to the wasm that implements it
+    pub fn tron_config_of(&self, artifact: &Artifact ) -> Result<(Arc<TronConfig>,Option<Arc<MechtronConfig>>),Box<dyn Error>>
+    {
+        match self.is_mechtron_config( artifact )?
+        {
+            true => {
+                let mechtron_config = self.mechtron_config_keeper.get( artifact )?;
+                Ok((self.tron_config_keeper.get( &mechtron_config.tron.artifact )?, Option::Some(mechtron_config)))
+            },
+            false => Ok((self.tron_config_keeper.get( artifact )?, Option::None))
+        }
+    }
+
+    // there is nothing in the artifact's name to say which kind of config it is, a mechtron
+    // config is the one that names its wasm.  any other problem with the artifact is an error
+    // rather than a reason to read it as a tron config
+    pub fn is_mechtron_config(&self, artifact: &Artifact ) -> Result<bool,Box<dyn Error>>
+    {
+        self.artifact_cache.cache( artifact )?;
+        let yaml: serde_yaml::Value = serde_yaml::from_str( self.artifact_cache.get( artifact )?.as_str() )?;
+        match yaml.as_mapping()
+        {
+            None => Err(format!("artifact {} is not a tron or mechtron config",artifact.to()).into()),
+            Some(mapping) => Ok(mapping.contains_key(&serde_yaml::Value::String("wasm".to_string())))
+        }
+    }
+
+    // every simulation needs the core trons and schemas to create its nuclei
+    pub fn cache_core(&mut self) -> Result<(),Box<dyn Error>>
+    {
+        for id in &["schema/empty","schema/content/meta","schema/create/meta","schema/sim/register-nucleus","schema/sim/destroy-nucleus"]
+        {
+            let artifact = self.core_artifact(id)?;
+            self.buffer_factory_keeper.cache(&artifact)?;
+        }
+        for id in &["tron/sim","tron/neutron"]
+        {
+            let artifact = self.core_artifact(id)?;
+            self.tron_config_keeper.cache(&artifact)?;
+            let tron_config = self.tron_config_keeper.get(&artifact)?;
+            tron_config.cache(self)?;
+        }
+        Ok(())
+    }
 }
 
 
 
+impl BufferFactories for Configs
+{
+    fn create_buffer(&self, artifact: &Artifact) -> Result<NP_Buffer<NP_Memory_Owned>, Box<dyn Error>> {
+        Ok(self.buffer_factory_keeper.get(artifact)?.new_buffer(Option::None))
+    }
+
+    fn create_buffer_from_array(&self, artifact: &Artifact, array: Vec<u8>) -> Result<NP_Buffer<NP_Memory_Owned>, Box<dyn Error>> {
+        Ok(self.buffer_factory_keeper.get(artifact)?.open_buffer(array))
+    }
+
+    fn create_buffer_from_buffer(&self, artifact: &Artifact, buffer: NP_Buffer<NP_Memory_Owned>) -> Result<NP_Buffer<NP_Memory_Owned>, Box<dyn Error>> {
+        Ok(self.buffer_factory_keeper.get(artifact)?.open_buffer(buffer.finish().bytes()))
+    }
+
+    // factories are shared out of the keeper as Arcs so none of them live for 'static
+    fn get_buffer_factory(&self, artifact: &Artifact) -> Option<&'static NP_Factory<'static>> {
+        Option::None
+    }
+}
+
 pub struct Keeper<V>
 {
     config_cache: RwLock<HashMap<Artifact,Arc<V>>>,
@@ -173,7 +249,21 @@ impl Parser<TronConfig> for TronConfigParser
 pub struct MechtronConfig {
     pub source: Artifact,
     pub wasm: Artifact,
-    pub tron: TronConfigRef
+    pub tron: TronConfigRef,
+    pub limits: MechtronLimits,
+    // the phases the update of the wasm guest is invoked in, every phase when not declared
+    pub phases: Option<Vec<String>>
+}
+
+// how far a single call into the wasm guest may go before it is failed.  there is no wall
+// clock deadline, the instruction budget already bounds how long a call can run and unlike
+// a deadline it stops a guest at the same point on every machine so replays match the sim
+#[derive(Clone)]
+pub struct MechtronLimits
+{
+    // the instruction budget of each create, update or port call
+    pub instructions: u64,
+    pub memory_pages: u32
 }
 
 #[derive(Clone)]
@@ -195,7 +285,22 @@ pub struct TronConfig{
 #[derive(Clone)]
 pub struct MessagesConfig
 {
-    pub create: Option<CreateMessageConfig>
+    pub create: Option<CreateMessageConfig>,
+    pub inbound: Vec<InboundMessageConfig>,
+    pub outbound: Vec<OutboundMessageConfig>
+}
+
+impl MessagesConfig
+{
+    pub fn inbound_port(&self, name: &str ) -> Option<&InboundMessageConfig>
+    {
+        self.inbound.iter().find(|port| port.name.as_str() == name )
+    }
+
+    pub fn outbound_port(&self, name: &str ) -> Option<&OutboundMessageConfig>
+    {
+        self.outbound.iter().find(|port| port.name.as_str() == name )
+    }
 }
 
 #[derive(Clone)]
@@ -236,6 +341,21 @@ impl ArtifactCacher for TronConfig{
             configs.buffer_factory_keeper.cache( &self.messages.as_ref().unwrap().create.as_ref().unwrap().artifact );
         }
 
+        if self.messages.is_some()
+        {
+            for port in &self.messages.as_ref().unwrap().inbound
+            {
+                for artifact in &port.artifact
+                {
+                    configs.buffer_factory_keeper.cache( artifact );
+                }
+            }
+            for port in &self.messages.as_ref().unwrap().outbound
+            {
+                configs.buffer_factory_keeper.cache( &port.artifact );
+            }
+        }
+
        Ok(())
     }
 }
@@ -252,8 +372,10 @@ impl ArtifactCacher for MechtronConfig {
 pub struct MechtronConfigYaml
 {
     name: String,
-    wasm: ArtifactYaml,
-    tron: ArtifactYaml
+    wasm: WasmRefYaml,
+    tron: TronConfigRefYaml,
+    limits: Option<MechtronLimitsYaml>,
+    phases: Option<Vec<String>>
 }
 
 impl MechtronConfigYaml {
@@ -268,12 +390,50 @@ impl MechtronConfigYaml {
         let default_bundle = &artifact.bundle.clone();
         return Ok( MechtronConfig {
             source: artifact.clone(),
-            wasm: self.wasm.to_artifact(default_bundle)?,
-            tron: TronConfigRef{ artifact: self.tron.to_artifact(default_bundle)? },
+            wasm: self.wasm.artifact.to_artifact(default_bundle)?,
+            tron: TronConfigRef{ artifact: self.tron.artifact.to_artifact(default_bundle)? },
+            limits: match &self.limits {
+                None => MechtronLimits{ instructions: DEFAULT_MECHTRON_INSTRUCTIONS, memory_pages: DEFAULT_MECHTRON_MEMORY_PAGES },
+                Some(limits) => limits.to_config()?
+            },
+            phases: self.phases.clone()
         } )
     }
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MechtronLimitsYaml
+{
+    instructions: Option<u64>,
+    memory_pages: Option<u32>
+}
+
+impl MechtronLimitsYaml
+{
+    pub fn to_config(&self) -> Result<MechtronLimits,Box<dyn Error>>
+    {
+        let limits = MechtronLimits{
+            instructions: self.instructions.unwrap_or(DEFAULT_MECHTRON_INSTRUCTIONS),
+            memory_pages: self.memory_pages.unwrap_or(DEFAULT_MECHTRON_MEMORY_PAGES)
+        };
+        if limits.instructions < 1
+        {
+            return Err("limits 'instructions' must be at least 1".into());
+        }
+        if limits.memory_pages < 1
+        {
+            return Err("limits 'memory_pages' must be at least 1".into());
+        }
+        Ok(limits)
+    }
+}
+
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct WasmRefYaml
+{
+  artifact: ArtifactYaml
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct TronConfigRefYaml
@@ -332,6 +492,18 @@ pub struct PortConfigYaml
     artifact: ArtifactYaml
 }
 
+impl PortConfigYaml
+{
+    pub fn to_config(&self, default_bundle: &ArtifactBundle) -> Result<InboundMessageConfig,Box<dyn Error>>
+    {
+        Ok(InboundMessageConfig{
+            name: self.name.clone(),
+            phase: self.phase.clone(),
+            artifact: vec![self.artifact.to_artifact(default_bundle)?]
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct OutMessageConfigYaml
 {
@@ -339,6 +511,50 @@ pub struct OutMessageConfigYaml
     artifact: ArtifactYaml
 }
 
+impl OutMessageConfigYaml
+{
+    pub fn to_config(&self, default_bundle: &ArtifactBundle) -> Result<OutboundMessageConfig,Box<dyn Error>>
+    {
+        Ok(OutboundMessageConfig{
+            name: self.name.clone(),
+            artifact: self.artifact.to_artifact(default_bundle)?
+        })
+    }
+}
+
+impl MessagesConfigYaml
+{
+    pub fn to_config(&self, default_bundle: &ArtifactBundle) -> Result<MessagesConfig,Box<dyn Error>>
+    {
+        let mut inbound = vec!();
+        if self.inbound.is_some()
+        {
+            for port in &self.inbound.as_ref().unwrap().ports
+            {
+                inbound.push(port.to_config(default_bundle)?);
+            }
+        }
+
+        let mut outbound = vec!();
+        if self.outbound.is_some()
+        {
+            for port in self.outbound.as_ref().unwrap()
+            {
+                outbound.push(port.to_config(default_bundle)?);
+            }
+        }
+
+        Ok(MessagesConfig{
+            create: match &self.create {
+                None=>Option::None,
+                Some(create)=>Option::Some(CreateMessageConfig{artifact:create.artifact.to_artifact(default_bundle)?})
+            },
+            inbound: inbound,
+            outbound: outbound
+        })
+    }
+}
+
 impl TronConfigYaml {
 
     pub fn from_yaml(string:&str) -> Result<Self,Box<dyn Error>>
@@ -359,12 +575,9 @@ impl TronConfigYaml {
             name: self.name.clone(),
 
             messages: match &self.messages{
-            None=>Option::None,
-            Some(messages)=>Option::Some( MessagesConfig{
-            create: match &messages.create {
                 None=>Option::None,
-                Some(create)=>Option::Some(CreateMessageConfig{artifact:create.artifact.to_artifact(default_bundle)?})
-            }})},
+                Some(messages)=>Option::Some( messages.to_config(default_bundle)? )
+            },
             content: match &self.content{
                 Some(content)=>Option::Some( ContentConfig{ artifact: content.artifact.to_artifact(default_bundle)?} ),
                 None=>Option::None,
@@ -379,19 +592,77 @@ pub struct SimConfig{
     pub source: Artifact,
     pub name: String,
     pub description: Option<String>,
+    pub phases: Vec<String>,
+    pub request_timeout_cycles: i64,
+    // messages may not be addressed further than this many cycles into the future
+    pub delivery_horizon_cycles: i64,
+    pub retention: ContentRetention,
+    // when true timestamps are derived from the cycle instead of the wall clock so runs can be replayed
+    pub deterministic: bool,
     pub trons: Vec<SimTronConfig>
 }
 
+// how much content history is kept for each tron.  the most recent revision is always kept
+#[derive(Clone,Debug,PartialEq)]
+pub enum ContentRetention
+{
+    All,
+    // keep the last n cycles
+    Last(i64),
+    // keep every kth cycle
+    Every(i64)
+}
+
+impl ContentRetention
+{
+    // true if the content for the given cycle should be kept when 'last' is the most recent cycle
+    pub fn retains( &self, cycle: i64, last: i64 )->bool
+    {
+        if cycle == last
+        {
+            return true;
+        }
+        match self
+        {
+            ContentRetention::All => true,
+            ContentRetention::Last(cycles) => cycle > last - cycles,
+            ContentRetention::Every(k) => cycle % k == 0
+        }
+    }
+}
+
 pub struct SimTronConfig
 {
     pub name: Option<String>,
+    // the lookup name of the nucleus the tron is created in, trons that do not name a
+    // nucleus are all created in the same default nucleus
+    pub nucleus: Option<String>,
     pub artifact: Artifact,
     pub create: Option<SimCreateTronConfig>
 }
 
+impl SimTronConfig
+{
+    // the artifact may be a tron config or a mechtron config that refers to its tron config
+    pub fn tron_config( &self, configs: &Configs ) -> Result<Arc<TronConfig>,Box<dyn Error>>
+    {
+        Ok(configs.tron_config_of( &self.artifact )?.0)
+    }
+}
+
 impl ArtifactCacher for SimTronConfig{
     fn cache(&self, configs: &mut Configs) -> Result<(), Box<dyn Error+'_>> {
-        configs.tron_config_keeper.cache( &self.artifact )?;
+        match configs.is_mechtron_config( &self.artifact )?
+        {
+            true => {
+                configs.mechtron_config_keeper.cache( &self.artifact )?;
+                let mechtron_config = configs.mechtron_config_keeper.get( &self.artifact )?;
+                configs.tron_config_keeper.cache( &mechtron_config.tron.artifact )?;
+            },
+            false => configs.tron_config_keeper.cache( &self.artifact )?
+        }
+        // the create schema is needed to convert the create data into a constructor buffer
+        self.tron_config( configs )?.cache( configs )?;
         if self.create.is_some()
         {
             configs.artifact_cache.cache( &self.create.as_ref().unwrap().data.artifact )?;
@@ -402,11 +673,11 @@ impl ArtifactCacher for SimTronConfig{
 
 pub struct SimCreateTronConfig
 {
-    data: DataRef
+    pub data: DataRef
 }
 
 pub struct DataRef{
-    artifact: Artifact
+    pub artifact: Artifact
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -415,13 +686,52 @@ pub struct SimConfigYaml
     name: String,
     main: ArtifactYaml,
     description: Option<String>,
+    phases: Option<Vec<String>>,
+    request_timeout_cycles: Option<i64>,
+    delivery_horizon_cycles: Option<i64>,
+    retention: Option<RetentionConfigYaml>,
+    deterministic: Option<bool>,
     trons: Vec<SimTronConfigYaml>,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetentionConfigYaml
+{
+    last: Option<i64>,
+    every: Option<i64>
+}
+
+impl RetentionConfigYaml
+{
+    pub fn to_config(&self) -> Result<ContentRetention,Box<dyn Error>>
+    {
+        match (&self.last, &self.every)
+        {
+            (Some(_),Some(_)) => Err("retention can specify 'last' or 'every' but not both".into()),
+            (Some(last),None) => {
+                if *last < 1
+                {
+                    return Err("retention 'last' must be at least 1".into());
+                }
+                Ok(ContentRetention::Last(last.clone()))
+            },
+            (None,Some(every)) => {
+                if *every < 1
+                {
+                    return Err("retention 'every' must be at least 1".into());
+                }
+                Ok(ContentRetention::Every(every.clone()))
+            },
+            (None,None) => Ok(ContentRetention::All)
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct SimTronConfigYaml
 {
     name: Option<String>,
+    nucleus: Option<String>,
     artifact: ArtifactYaml,
     create: Option<CreateSimTronConfigYaml>
 }
@@ -448,12 +758,56 @@ impl SimConfigYaml
     pub fn to_config(&self, artifact: &Artifact ) -> Result<SimConfig,Box<dyn Error>>
     {
         let default_artifact = &artifact.bundle.clone();
+
+        let phases = match &self.phases {
+            None => vec!(),
+            Some(phases) => phases.clone()
+        };
+        if phases.len() > u8::MAX as usize
+        {
+            return Err(format!("sim config {} declares {} phases but no more than {} are allowed", artifact.to(), phases.len(), u8::MAX).into());
+        }
+        for (index,phase) in phases.iter().enumerate()
+        {
+            if phases[..index].contains(phase)
+            {
+                return Err(format!("sim config {} declares phase '{}' more than once", artifact.to(), phase).into());
+            }
+        }
+
+        let request_timeout_cycles = match &self.request_timeout_cycles {
+            None => DEFAULT_REQUEST_TIMEOUT_CYCLES,
+            Some(cycles) => cycles.clone()
+        };
+        if request_timeout_cycles < 1
+        {
+            return Err(format!("sim config {} request_timeout_cycles must be at least 1", artifact.to()).into());
+        }
+
+        let delivery_horizon_cycles = match &self.delivery_horizon_cycles {
+            None => DEFAULT_DELIVERY_HORIZON_CYCLES,
+            Some(cycles) => cycles.clone()
+        };
+        if delivery_horizon_cycles < 1
+        {
+            return Err(format!("sim config {} delivery_horizon_cycles must be at least 1", artifact.to()).into());
+        }
+
         Ok( SimConfig{
             source: artifact.clone(),
             name: self.name.clone(),
             description: self.description.clone(),
+            phases: phases,
+            request_timeout_cycles: request_timeout_cycles,
+            delivery_horizon_cycles: delivery_horizon_cycles,
+            retention: match &self.retention {
+                None => ContentRetention::All,
+                Some(retention) => retention.to_config()?
+            },
+            deterministic: self.deterministic.unwrap_or(false),
             trons: self.trons.iter().map( |t| { SimTronConfig{
                 name: t.name.clone(),
+                nucleus: t.nucleus.clone(),
                 artifact: t.artifact.to_artifact(&default_artifact)?,
                 create: match &t.create {
                     None => Option::None,
@@ -469,6 +823,8 @@ impl SimConfigYaml
 impl ArtifactCacher for SimConfig {
     fn cache(&self, configs: &mut Configs) -> Result<(), Box<dyn Error>> {
 
+        configs.cache_core()?;
+
         for tron in self.trons
         {
             tron.cache( configs )?
@@ -478,3 +834,103 @@ impl ArtifactCacher for SimConfig {
     }
 }
 
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+
+    use crate::artifact::Artifact;
+    use crate::configs::{ContentRetention, MechtronConfigYaml, SimConfig, SimConfigYaml, TronConfigYaml};
+
+    fn sim_config( phases: &str )->Result<SimConfig,Box<dyn Error>>
+    {
+        let yaml = format!("name: Test Simulation\nmain:\n  path: tron/test.yaml\n{}trons: []\n", phases);
+        SimConfigYaml::from(yaml.as_str())?.to_config(&Artifact::from("mechtron.io:examples:0.0.1:test-simulation.yaml")?)
+    }
+
+    #[test]
+    fn phases_keep_their_declared_order() -> Result<(),Box<dyn Error>>
+    {
+        let config = sim_config("phases:\n- physics\n- render\n- cleanup\n")?;
+        assert_eq!(config.phases, vec!("physics".to_string(), "render".to_string(), "cleanup".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn phases_are_optional() -> Result<(),Box<dyn Error>>
+    {
+        assert!(sim_config("")?.phases.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn a_phase_declared_twice_is_an_error()
+    {
+        assert!(sim_config("phases:\n- physics\n- render\n- physics\n").is_err());
+    }
+
+    #[test]
+    fn inbound_ports_are_read_from_the_tron_config() -> Result<(),Box<dyn Error>>
+    {
+        let yaml = "kind: stdout\nname: StdOut\nmessages:\n  inbound:\n    ports:\n    - name: println\n      artifact:\n        path: schema/stdout/println.json\n";
+        let config = TronConfigYaml::from_yaml(yaml)?.to_config(&Artifact::from("mechtron.io:std:1.0.0:tron/stdout.yaml")?)?;
+        let messages = config.messages.as_ref().unwrap();
+
+        let port = messages.inbound_port("println").unwrap();
+        assert_eq!(port.artifact, vec!(Artifact::from("mechtron.io:std:1.0.0:schema/stdout/println.json")?));
+        assert!(messages.inbound_port("print").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn mechtron_update_phases_are_read_from_the_mechtron_config() -> Result<(),Box<dyn Error>>
+    {
+        let artifact = Artifact::from("uberscott.com:examples:1.0.0:hello/mechtron/hello.yaml")?;
+        let yaml = "name: Hello World\nwasm:\n  artifact:\n    path: hello/wasm/hello.wasm\ntron:\n  artifact:\n    path: hello/tron/printer.yaml\n";
+        assert!(MechtronConfigYaml::from_yaml(yaml)?.to_config(&artifact)?.phases.is_none());
+
+        let yaml = format!("{}phases:\n- physics\n", yaml);
+        assert_eq!(MechtronConfigYaml::from_yaml(yaml.as_str())?.to_config(&artifact)?.phases, Option::Some(vec!("physics".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn retains_the_last_cycle_under_every_policy()
+    {
+        assert!(ContentRetention::All.retains(7, 7));
+        assert!(ContentRetention::Last(1).retains(7, 7));
+        assert!(ContentRetention::Every(5).retains(7, 7));
+    }
+
+    #[test]
+    fn retains_all()
+    {
+        for cycle in 0..10
+        {
+            assert!(ContentRetention::All.retains(cycle, 10));
+        }
+    }
+
+    #[test]
+    fn retains_last()
+    {
+        let retention = ContentRetention::Last(3);
+        assert!(retention.retains(10, 10));
+        assert!(retention.retains(9, 10));
+        assert!(retention.retains(8, 10));
+        assert!(!retention.retains(7, 10));
+        assert!(!retention.retains(0, 10));
+    }
+
+    #[test]
+    fn retains_every()
+    {
+        let retention = ContentRetention::Every(4);
+        assert!(retention.retains(0, 10));
+        assert!(retention.retains(4, 10));
+        assert!(retention.retains(8, 10));
+        assert!(!retention.retains(5, 10));
+        assert!(!retention.retains(9, 10));
+    }
+}
@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::fmt;
+
+use no_proto::error::NP_Error;
+use no_proto::NP_Factory;
+use no_proto::pointer::option::NP_Enum;
+
+// an error a wasm guest hands to the host.  the guest writes it to a host buffer with
+// GUEST_ERROR_SCHEMA before it returns from (or traps in) the call that failed
+
+static GUEST_ERROR_SCHEMA: &'static str = r#"{
+    "type": "table",
+    "columns": [
+        ["kind",      {"type": "enum", "choices": ["error", "panic"], "default": "error"}],
+        ["message",   {"type": "string"}],
+        ["backtrace", {"type": "string"}]
+        ]
+}"#;
+
+lazy_static! {
+static ref GUEST_ERROR_FACTORY : NP_Factory<'static> = NP_Factory::new(GUEST_ERROR_SCHEMA).unwrap();
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub enum GuestErrorKind
+{
+    // a handler of the guest returned an error
+    Error,
+    Panic
+}
+
+#[derive(Clone,Debug)]
+pub struct GuestError
+{
+    pub kind: GuestErrorKind,
+    pub message: String,
+    pub backtrace: Option<String>
+}
+
+impl GuestError
+{
+    pub fn new( kind: GuestErrorKind, message: String, backtrace: Option<String> ) -> Self
+    {
+        GuestError {
+            kind: kind,
+            message: message,
+            backtrace: backtrace
+        }
+    }
+
+    pub fn to_bytes( &self ) -> Result<Vec<u8>,Box<dyn Error>>
+    {
+        match self.to_bytes_np_error()
+        {
+            Ok(bytes) => Ok(bytes),
+            Err(_) => Err("could not write guest error to buffer".into())
+        }
+    }
+
+    fn to_bytes_np_error( &self ) -> Result<Vec<u8>,NP_Error>
+    {
+        let mut buffer = GUEST_ERROR_FACTORY.new_buffer(Option::None);
+        buffer.set(&[&"kind"], match self.kind {
+            GuestErrorKind::Error => NP_Enum::new("error"),
+            GuestErrorKind::Panic => NP_Enum::new("panic")
+        })?;
+        buffer.set(&[&"message"], self.message.as_str())?;
+        if let Option::Some(backtrace) = &self.backtrace
+        {
+            buffer.set(&[&"backtrace"], backtrace.as_str())?;
+        }
+        Ok(buffer.finish().bytes())
+    }
+
+    pub fn from_bytes( bytes: Vec<u8> ) -> Result<Self,Box<dyn Error>>
+    {
+        let buffer = GUEST_ERROR_FACTORY.open_buffer(bytes);
+        let kind = match buffer.get::<NP_Enum>(&[&"kind"])
+        {
+            Ok(Some(NP_Enum::Some(kind))) if kind == "panic" => GuestErrorKind::Panic,
+            Ok(_) => GuestErrorKind::Error,
+            Err(_) => return Err("could not read kind of guest error".into())
+        };
+        let message = match buffer.get::<String>(&[&"message"])
+        {
+            Ok(message) => message.unwrap_or(String::new()),
+            Err(_) => return Err("could not read message of guest error".into())
+        };
+        let backtrace = match buffer.get::<String>(&[&"backtrace"])
+        {
+            Ok(backtrace) => backtrace,
+            Err(_) => return Err("could not read backtrace of guest error".into())
+        };
+        Ok(GuestError::new(kind, message, backtrace))
+    }
+}
+
+impl fmt::Display for GuestError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self.kind
+        {
+            GuestErrorKind::Error => write!(f, "{}", self.message)?,
+            GuestErrorKind::Panic => write!(f, "panicked: {}", self.message)?
+        };
+        if let Option::Some(backtrace) = &self.backtrace
+        {
+            write!(f, "\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for GuestError {}
+
+#[cfg(test)]
+mod tests
+{
+    use std::error::Error;
+
+    use crate::error::{GuestError, GuestErrorKind};
+
+    #[test]
+    fn error_round_trip() -> Result<(),Box<dyn Error>>
+    {
+        let error = GuestError::new(GuestErrorKind::Error, "no such port".to_string(), Option::None);
+        let read = GuestError::from_bytes(error.to_bytes()?)?;
+        assert_eq!(GuestErrorKind::Error, read.kind);
+        assert_eq!("no such port", read.message);
+        assert_eq!(Option::None, read.backtrace);
+        Ok(())
+    }
+
+    #[test]
+    fn panic_round_trip() -> Result<(),Box<dyn Error>>
+    {
+        let error = GuestError::new(GuestErrorKind::Panic, "index out of bounds".to_string(), Option::Some("at src/lib.rs:12:5".to_string()));
+        let read = GuestError::from_bytes(error.to_bytes()?)?;
+        assert_eq!(GuestErrorKind::Panic, read.kind);
+        assert_eq!("index out of bounds", read.message);
+        assert_eq!(Option::Some("at src/lib.rs:12:5".to_string()), read.backtrace);
+        Ok(())
+    }
+}
@@ -38,7 +38,7 @@ impl IdSeq {
         self.seq_id
     }
 
-    pub fn next(&mut self)->Id
+    pub fn next(&self)->Id
     {
         Id{
             seq_id:self.seq_id,
@@ -46,6 +46,19 @@ impl IdSeq {
         }
     }
 
+    // the id that will be issued by the next call to next()
+    pub fn peek(&self)->i64
+    {
+        self.seq.load(Ordering::Relaxed)
+    }
+
+    // continue issuing ids from a previous run. the sequence never moves backwards so ids
+    // issued before the resume cannot be issued again
+    pub fn resume(&self, next: i64 )
+    {
+        self.seq.fetch_max(next, Ordering::Relaxed );
+    }
+
 
 }
 
@@ -93,6 +106,7 @@ pub struct Revision
     pub cycle: i64
 }
 
+#[derive(PartialEq,Eq,PartialOrd,Ord,Hash,Debug,Clone)]
 pub struct DeliveryMomentKey
 {
     pub cycle: i64,
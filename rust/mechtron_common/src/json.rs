@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::sync::Arc;
+
+use no_proto::buffer::NP_Buffer;
+use no_proto::json_flex::{json_decode, JSMAP, NP_JSON};
+use no_proto::memory::NP_Memory_Owned;
+
+use crate::artifact::Artifact;
+use crate::configs::Configs;
+use crate::content::{Content, ReadOnlyContent};
+use crate::id::{Id, TronKey};
+use crate::message::{Cycle, From, InterDeliveryType, Message, MessageKind, Payload, To};
+
+// conversion of buffers to and from json.  buffers are walked using the schema of their
+// artifact so the json has the same shape as the schema
+
+pub fn parse( string: &str )->Result<NP_JSON,Box<dyn Error>>
+{
+    match json_decode(string.to_string())
+    {
+        Ok(json) => Ok(*json),
+        Err(e) => Err(format!("could not parse json: {:?}",e).into())
+    }
+}
+
+pub fn buffer_to_json( buffer: &NP_Buffer<NP_Memory_Owned> )->Result<NP_JSON,Box<dyn Error>>
+{
+    match buffer.json_encode(&[])
+    {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("could not encode buffer as json: {:?}",e).into())
+    }
+}
+
+pub fn buffer_from_json( configs: &Configs, artifact: &Artifact, json: &NP_JSON )->Result<NP_Buffer<NP_Memory_Owned>,Box<dyn Error>>
+{
+    let mut buffer = configs.buffer_factory_keeper.get(artifact)?.new_buffer(Option::None);
+    let mut value = JSMAP::new();
+    value.insert("value".to_string(), json.clone());
+    match buffer.set_with_json(&[], NP_JSON::Dictionary(value).stringify())
+    {
+        Ok(_) => Ok(buffer),
+        Err(e) => Err(format!("json does not match the schema of artifact {}: {:?}",artifact.to(),e).into())
+    }
+}
+
+// for json that is kept in an artifact such as tron create data.  the text is wrapped rather
+// than parsed first so that a bare string or number is accepted for scalar schemas
+pub fn buffer_from_json_str( configs: &Configs, artifact: &Artifact, json: &str )->Result<NP_Buffer<NP_Memory_Owned>,Box<dyn Error>>
+{
+    let mut buffer = configs.buffer_factory_keeper.get(artifact)?.new_buffer(Option::None);
+    match buffer.set_with_json(&[], format!("{{\"value\":{}}}", json.trim()))
+    {
+        Ok(_) => Ok(buffer),
+        Err(e) => Err(format!("json does not match the schema of artifact {}: {:?}",artifact.to(),e).into())
+    }
+}
+
+impl Content
+{
+    pub fn to_json( &self )->Result<NP_JSON,Box<dyn Error>>
+    {
+        let mut rtn = JSMAP::new();
+        rtn.insert("artifact".to_string(), NP_JSON::String(self.artifact.to()));
+        rtn.insert("meta".to_string(), buffer_to_json(&self.meta)?);
+        rtn.insert("data".to_string(), buffer_to_json(&self.data)?);
+        Ok(NP_JSON::Dictionary(rtn))
+    }
+
+    pub fn from_json( configs: &Configs, json: &NP_JSON )->Result<Self,Box<dyn Error>>
+    {
+        let artifact = Artifact::from(string(json, "artifact")?.as_str())?;
+        Ok(Content::from(
+            artifact.clone(),
+            buffer_from_json(configs, &configs.core_artifact("schema/content/meta")?, &json["meta"])?,
+            buffer_from_json(configs, &artifact, &json["data"])?
+        ))
+    }
+}
+
+impl ReadOnlyContent
+{
+    pub fn to_json( &self )->Result<NP_JSON,Box<dyn Error>>
+    {
+        self.copy()?.to_json()
+    }
+
+    pub fn from_json( configs: &Configs, json: &NP_JSON )->Result<Self,Box<dyn Error>>
+    {
+        Content::from_json(configs, json)?.read_only()
+    }
+}
+
+impl Payload
+{
+    pub fn to_json( &self )->Result<NP_JSON,Box<dyn Error>>
+    {
+        let mut rtn = JSMAP::new();
+        rtn.insert("artifact".to_string(), NP_JSON::String(self.artifact.to()));
+        rtn.insert("buffer".to_string(), buffer_to_json(&self.buffer)?);
+        Ok(NP_JSON::Dictionary(rtn))
+    }
+
+    pub fn from_json( configs: &Configs, json: &NP_JSON )->Result<Self,Box<dyn Error>>
+    {
+        let artifact = Artifact::from(string(json, "artifact")?.as_str())?;
+        Ok(Payload{
+            buffer: Arc::new(buffer_from_json(configs, &artifact, &json["buffer"])?),
+            artifact: artifact
+        })
+    }
+}
+
+impl Message
+{
+    pub fn to_json( &self )->Result<NP_JSON,Box<dyn Error>>
+    {
+        let mut from = JSMAP::new();
+        from.insert("tron".to_string(), tron_key_to_json(&self.from.tron));
+        from.insert("cycle".to_string(), NP_JSON::Integer(self.from.cycle));
+        from.insert("timestamp".to_string(), NP_JSON::Integer(self.from.timestamp));
+
+        let mut to = JSMAP::new();
+        to.insert("tron".to_string(), tron_key_to_json(&self.to.tron));
+        to.insert("port".to_string(), NP_JSON::String(self.to.port.clone()));
+        to.insert("cycle".to_string(), match &self.to.cycle {
+            Cycle::Future(cycle) => NP_JSON::Integer(cycle.clone()),
+            Cycle::Present => NP_JSON::String("present".to_string()),
+            Cycle::Next => NP_JSON::String("next".to_string())
+        });
+        to.insert("phase".to_string(), NP_JSON::Integer(self.to.phase as i64));
+        to.insert("inter_delivery_type".to_string(), NP_JSON::String(match &self.to.inter_delivery_type {
+            InterDeliveryType::Cyclic => "cyclic".to_string(),
+            InterDeliveryType::Phasic => "phasic".to_string()
+        }));
+
+        let mut payloads = vec!();
+        for payload in &self.payloads
+        {
+            payloads.push(payload.to_json()?);
+        }
+
+        let mut rtn = JSMAP::new();
+        rtn.insert("id".to_string(), id_to_json(&self.id));
+        rtn.insert("kind".to_string(), NP_JSON::String(message_kind_to_name(&self.kind).to_string()));
+        rtn.insert("from".to_string(), NP_JSON::Dictionary(from));
+        rtn.insert("to".to_string(), NP_JSON::Dictionary(to));
+        rtn.insert("payloads".to_string(), NP_JSON::Array(payloads));
+        if let Option::Some(meta) = &self.meta
+        {
+            let mut map = JSMAP::new();
+            for (key,value) in meta
+            {
+                map.insert(key.clone(), NP_JSON::String(value.clone()));
+            }
+            rtn.insert("meta".to_string(), NP_JSON::Dictionary(map));
+        }
+        if let Option::Some(transaction) = &self.transaction
+        {
+            rtn.insert("transaction".to_string(), id_to_json(transaction));
+        }
+        if let Option::Some(response_port) = &self.response_port
+        {
+            rtn.insert("response_port".to_string(), NP_JSON::String(response_port.clone()));
+        }
+        Ok(NP_JSON::Dictionary(rtn))
+    }
+
+    pub fn from_json( configs: &Configs, json: &NP_JSON )->Result<Self,Box<dyn Error>>
+    {
+        let from = &json["from"];
+        let to = &json["to"];
+
+        let mut payloads = vec!();
+        if let Option::Some(array) = json["payloads"].into_vec()
+        {
+            for payload in array
+            {
+                payloads.push(Payload::from_json(configs, payload)?);
+            }
+        }
+
+        let meta = match json["meta"].into_hashmap() {
+            None => Option::None,
+            Some(map) => {
+                let mut meta = HashMap::new();
+                for (key,value) in &map.values
+                {
+                    match value.into_string()
+                    {
+                        None => return Err(format!("message meta '{}' must be a string",key).into()),
+                        Some(value) => meta.insert(key.clone(), value.clone())
+                    };
+                }
+                Option::Some(meta)
+            }
+        };
+
+        Ok(Message{
+            id: id_from_json(&json["id"])?,
+            kind: name_to_message_kind(string(json, "kind")?.as_str())?,
+            from: From{
+                tron: tron_key_from_json(&from["tron"])?,
+                cycle: integer(from, "cycle")?,
+                timestamp: integer(from, "timestamp")?
+            },
+            to: To{
+                tron: tron_key_from_json(&to["tron"])?,
+                port: string(to, "port")?,
+                cycle: match &to["cycle"] {
+                    NP_JSON::Integer(cycle) => Cycle::Future(cycle.clone()),
+                    NP_JSON::String(cycle) if cycle == "present" => Cycle::Present,
+                    NP_JSON::String(cycle) if cycle == "next" => Cycle::Next,
+                    _ => return Err("message 'to.cycle' must be a cycle number, 'present' or 'next'".into())
+                },
+                phase: match u8::try_from(integer(to, "phase")?) {
+                    Ok(phase) => phase,
+                    Err(_) => return Err("message 'to.phase' must be between 0 and 255".into())
+                },
+                inter_delivery_type: match string(to, "inter_delivery_type")?.as_str() {
+                    "cyclic" => InterDeliveryType::Cyclic,
+                    "phasic" => InterDeliveryType::Phasic,
+                    other => return Err(format!("unknown inter_delivery_type '{}'",other).into())
+                }
+            },
+            payloads: payloads,
+            meta: meta,
+            transaction: match &json["transaction"] {
+                NP_JSON::Null => Option::None,
+                transaction => Option::Some(id_from_json(transaction)?)
+            },
+            response_port: json["response_port"].into_string().cloned()
+        })
+    }
+}
+
+fn message_kind_to_name( kind: &MessageKind )->&'static str
+{
+    match kind {
+        MessageKind::Create=>"create",
+        MessageKind::Update=>"update",
+        MessageKind::Content=>"content",
+        MessageKind::Request=>"request",
+        MessageKind::Response=>"response",
+        MessageKind::Reject=>"reject",
+        MessageKind::Destroy=>"destroy"
+    }
+}
+
+fn name_to_message_kind( name: &str )->Result<MessageKind,Box<dyn Error>>
+{
+    match name {
+        "create" => Ok(MessageKind::Create),
+        "update" => Ok(MessageKind::Update),
+        "content" => Ok(MessageKind::Content),
+        "request" => Ok(MessageKind::Request),
+        "response" => Ok(MessageKind::Response),
+        "reject" => Ok(MessageKind::Reject),
+        "destroy" => Ok(MessageKind::Destroy),
+        _ => Err(format!("unknown message kind '{}'",name).into())
+    }
+}
+
+fn id_to_json( id: &Id )->NP_JSON
+{
+    let mut rtn = JSMAP::new();
+    rtn.insert("seq_id".to_string(), NP_JSON::Integer(id.seq_id));
+    rtn.insert("id".to_string(), NP_JSON::Integer(id.id));
+    NP_JSON::Dictionary(rtn)
+}
+
+fn id_from_json( json: &NP_JSON )->Result<Id,Box<dyn Error>>
+{
+    Ok(Id::new(integer(json, "seq_id")?, integer(json, "id")?))
+}
+
+fn tron_key_to_json( key: &TronKey )->NP_JSON
+{
+    let mut rtn = JSMAP::new();
+    rtn.insert("nucleus_id".to_string(), id_to_json(&key.nucleus_id));
+    rtn.insert("tron_id".to_string(), id_to_json(&key.tron_id));
+    NP_JSON::Dictionary(rtn)
+}
+
+fn tron_key_from_json( json: &NP_JSON )->Result<TronKey,Box<dyn Error>>
+{
+    Ok(TronKey::new(id_from_json(&json["nucleus_id"])?, id_from_json(&json["tron_id"])?))
+}
+
+fn string( json: &NP_JSON, key: &str )->Result<String,Box<dyn Error>>
+{
+    match json[key].into_string()
+    {
+        None => Err(format!("expected a string for '{}'",key).into()),
+        Some(value) => Ok(value.clone())
+    }
+}
+
+fn integer( json: &NP_JSON, key: &str )->Result<i64,Box<dyn Error>>
+{
+    match json[key].into_i64()
+    {
+        None => Err(format!("expected an integer for '{}'",key).into()),
+        Some(value) => Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::sync::Arc;
+
+    use no_proto::json_flex::NP_JSON;
+
+    use crate::artifact::{Artifact, ArtifactCache};
+    use crate::configs::Configs;
+    use crate::id::{Id, IdSeq, TronKey};
+    use crate::json::parse;
+    use crate::message::{Cycle, From, InterDeliveryType, Message, MessageKind, To};
+
+    // messages without payloads never look up an artifact
+    struct NoArtifacts;
+
+    impl ArtifactCache for NoArtifacts
+    {
+        fn cache(&self, artifact: &Artifact) -> Result<(), Box<dyn Error + '_>>
+        {
+            Err(format!("no artifact {}", artifact.to()).into())
+        }
+
+        fn load(&self, artifact: &Artifact) -> Result<Vec<u8>, Box<dyn Error + '_>>
+        {
+            Err(format!("no artifact {}", artifact.to()).into())
+        }
+
+        fn get(&self, artifact: &Artifact) -> Result<Arc<String>, Box<dyn Error + '_>>
+        {
+            Err(format!("no artifact {}", artifact.to()).into())
+        }
+    }
+
+    fn message()->Message
+    {
+        let seq = IdSeq::new(0);
+        let from = From{ tron: TronKey::new(Id::new(0,1), Id::new(0,2)), cycle: 3, timestamp: 4 };
+        let to = To{
+            tron: TronKey::new(Id::new(0,1), Id::new(0,5)),
+            port: "port".to_string(),
+            cycle: Cycle::Future(9),
+            phase: 2,
+            inter_delivery_type: InterDeliveryType::Phasic
+        };
+        let mut meta = HashMap::new();
+        meta.insert("key".to_string(), "value".to_string());
+        let mut message = Message::longform(&seq, MessageKind::Request, from, to, vec!(), Option::Some(meta), Option::Some(Id::new(0,6)));
+        message.response_port = Option::Some("responses".to_string());
+        message
+    }
+
+    #[test]
+    fn message_round_trip() -> Result<(),Box<dyn Error>>
+    {
+        let configs = Configs::new(Arc::new(NoArtifacts));
+        let message = message();
+        let json = parse(message.to_json()?.stringify().as_str())?;
+        let read = Message::from_json(&configs, &json)?;
+
+        assert_eq!(read.id, message.id);
+        assert_eq!(read.from.tron, message.from.tron);
+        assert_eq!(read.from.cycle, 3);
+        assert_eq!(read.from.timestamp, 4);
+        assert_eq!(read.to.tron, message.to.tron);
+        assert_eq!(read.to.port, "port");
+        assert!(matches!(read.to.cycle, Cycle::Future(9)));
+        assert_eq!(read.to.phase, 2);
+        assert!(matches!(read.to.inter_delivery_type, InterDeliveryType::Phasic));
+        assert!(read.payloads.is_empty());
+        assert_eq!(read.meta, message.meta);
+        assert_eq!(read.transaction, message.transaction);
+        assert_eq!(read.response_port, message.response_port);
+        Ok(())
+    }
+
+    #[test]
+    fn phase_out_of_range_is_an_error() -> Result<(),Box<dyn Error>>
+    {
+        let configs = Configs::new(Arc::new(NoArtifacts));
+        let mut json = message().to_json()?;
+        if let NP_JSON::Dictionary(map) = &mut json
+        {
+            if let Option::Some(NP_JSON::Dictionary(to)) = map.get_mut("to")
+            {
+                to.insert("phase".to_string(), NP_JSON::Integer(256));
+            }
+        }
+        assert!(Message::from_json(&configs, &json).is_err());
+        Ok(())
+    }
+}
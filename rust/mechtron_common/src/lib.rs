@@ -7,6 +7,8 @@ pub mod message;
 pub mod configs;
 pub mod id;
 pub mod content;
+pub mod json;
+pub mod error;
 
 
 
@@ -6,6 +6,7 @@ use no_proto::NP_Factory;
 use no_proto::error::NP_Error;
 use crate::buffers::BufferFactories;
 use no_proto::pointer::{NP_Scalar, NP_Value};
+use no_proto::pointer::option::NP_Enum;
 use bytes::Bytes;
 use std::error::Error;
 use uuid::Uuid;
@@ -20,16 +21,27 @@ static MESSAGE_SCHEMA: &'static str = r#"{
     "of":
     {"type": "table",
     "columns": [
-        ["id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}],
+        ["id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],
         ["kind",   {"type": "u8"}],
-        ["from",    {"type": "table", "columns":[["nucleus_id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}],["tron_id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}],["cycle",{"type":"i64"}],["timestamp",{"type":"i64"}]]}],
-        ["to",      {"type": "table", "columns":[["nucleus_id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}],["tron_id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}],["cycle",{"type":"i64"}],["phase",{"type":"u8"}]]},["inter_delivery_type", {"type": "enum", "choices": ["cyclic", "phasic"], "default": "cyclic"}],["port",   {"type": "string"}]],
-
-        ["payloads",   {"type": "list", "of":{ "type":"table", "columns": [ ["buffer", {"type","bytes"}], ["artifact", {"type","string"}] ]  }}],
+        ["from",    {"type": "table", "columns":[
+                ["tron", {"type": "table", "columns":[["nucleus_id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],["tron_id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}]]}],
+                ["cycle",{"type":"i64"}],
+                ["timestamp",{"type":"i64"}]]}],
+        ["to",      {"type": "table", "columns":[
+                ["tron", {"type": "table", "columns":[["nucleus_id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],["tron_id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}]]}],
+                ["cycle_kind", {"type": "enum", "choices": ["future", "present", "next"], "default": "next"}],
+                ["cycle",{"type":"i64"}],
+                ["phase",{"type":"u8"}],
+                ["inter_delivery_type", {"type": "enum", "choices": ["cyclic", "phasic"], "default": "cyclic"}],
+                ["port",   {"type": "string"}]]}],
+
+        ["payloads",   {"type": "list", "of":{ "type":"table", "columns": [ ["buffer", {"type":"bytes"}], ["artifact", {"type":"string"}] ]  }}],
 
         ["meta",   {"type": "map","value": { "type": "string" } }],
-        ["transaction",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}]
+        ["transaction",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],
+        ["response_port",   {"type": "string"}]
         ]
+    }
 }"#;
 
 
@@ -38,23 +50,25 @@ static MESSAGE_BUILDERS_SCHEMA: &'static str = r#"{
     "of":
     {"type": "table",
     "columns": [
-        ["kind",   {"type": "i32"}],
-
-        ["to_tron",  {"type": "table", "columns":[["nucleus_id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}],["tron_id",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}]]}],
+        ["kind",   {"type": "u8"}],
 
+        ["to_nucleus_id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],
+        ["to_tron_id",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],
         ["to_nucleus_lookup_name",      {"type": "string"}],
         ["to_tron_lookup_name",      {"type": "string"}],
-        ["to_cycle_kind",      {"type": "i64"}],
+        ["to_cycle_kind",      {"type": "enum", "choices": ["future", "present", "next"], "default": "next"}],
         ["to_cycle",      {"type": "i64"}],
         ["to_phase_name",      {"type": "string"}],
         ["to_phase",      {"type": "u8"}],
         ["to_inter_delivery_type", {"type": "enum", "choices": ["cyclic", "phasic"], "default": "cyclic"}],
         ["to_port",   {"type": "string"}],
 
-        ["payloads",   {"type": "list", "of":{ "type":"table", "columns": [ ["buffer", {"type","bytes"}], ["artifact", {"type","string"}] ]  }}],
+        ["payloads",   {"type": "list", "of":{ "type":"table", "columns": [ ["buffer", {"type":"bytes"}], ["artifact", {"type":"string"}] ]  }}],
         ["meta",   {"type": "map","value": { "type": "string" } }],
-        ["transaction",   {"type": "table", "columns":[["seq_id":{"type":"i64"}],["id":{"type":"i64"}]]}]
+        ["transaction",   {"type": "table", "columns":[["seq_id",{"type":"i64"}],["id",{"type":"i64"}]]}],
+        ["response_port",   {"type": "string"}]
         ]
+    }
 }"#;
 
 lazy_static! {
@@ -133,7 +147,8 @@ pub enum MessageKind{
     Content,
     Request,
     Response,
-    Reject
+    Reject,
+    Destroy
 }
 
 fn message_kind_to_index(kind: &MessageKind ) -> u8
@@ -144,7 +159,8 @@ fn message_kind_to_index(kind: &MessageKind ) -> u8
         MessageKind::Content =>2,
         MessageKind::Request =>3,
         MessageKind::Response =>4,
-        MessageKind::Reject=>5
+        MessageKind::Reject=>5,
+        MessageKind::Destroy=>6
     }
 }
 
@@ -157,6 +173,7 @@ fn index_to_message_kind( index: u8 ) -> Result<MessageKind,Box<dyn Error>>
         3 => Ok(MessageKind::Request),
         4 => Ok(MessageKind::Response),
         5 => Ok(MessageKind::Reject),
+        6 => Ok(MessageKind::Destroy),
         _ => Err(format!("invalid index {}",index).into())
     }
 }
@@ -187,7 +204,12 @@ pub struct MessageBuilder {
     pub to_inter_delivery_type: Option<InterDeliveryType>,
     pub payloads: Option<Vec<Payload>>,
     pub meta: Option<HashMap<String,String>>,
-    pub transaction: Option<Id>
+    pub transaction: Option<Id>,
+    pub response_port: Option<String>,
+    // set by the host on the messages it sends on a tron's behalf so they are not held to
+    // the tron's declared outbound ports.  never written to or read from a buffer, so a
+    // builder that comes from a wasm guest is never internal
+    pub internal: bool
 }
 
 impl  MessageBuilder {
@@ -208,7 +230,27 @@ impl  MessageBuilder {
             payloads: None,
             meta: None,
             transaction: None,
+            response_port: None,
+            internal: false
+        }
+    }
+
+    // a Response to the given Request.  the runtime routes the response to the
+    // requester's response port using the transaction
+    pub fn response( request: &Message ) -> Result<Self,Box<dyn Error>>
+    {
+        if request.transaction.is_none()
+        {
+            return Err("cannot respond to a message that has no transaction".into());
         }
+
+        let mut builder = MessageBuilder::new();
+        builder.kind = Option::Some(MessageKind::Response);
+        builder.to_nucleus_id = Option::Some(request.from.tron.nucleus_id.clone());
+        builder.to_tron_id = Option::Some(request.from.tron.tron_id.clone());
+        builder.to_port = request.response_port.clone();
+        builder.transaction = request.transaction.clone();
+        Ok(builder)
     }
 
     pub fn validate(&self) ->Result<(),Box<dyn Error>>
@@ -218,32 +260,41 @@ impl  MessageBuilder {
             return Err("message builder kind must be set".into());
         }
 
+        match self.kind.as_ref().unwrap()
+        {
+            MessageKind::Request => {
+                if self.response_port.is_none()
+                {
+                    return Err("message builder response_port must be set for a Request".into());
+                }
+            }
+            MessageKind::Response => {
+                if self.transaction.is_none()
+                {
+                    return Err("message builder transaction must be set for a Response".into());
+                }
+            }
+            _ => {}
+        }
+
         if self.to_phase_name.is_some() && self.to_phase.is_some()
         {
             return Err("to_phase_name and to_phase cannot both be set".into());
         }
 
-        if self.to_nucleus_lookup_name.is_some() != self.to_nucleus_id.is_some()
+        if self.to_nucleus_lookup_name.is_some() == self.to_nucleus_id.is_some()
         {
             return Err("message builder to_nucleus_lookup_name OR to_nucleus_id must be set (but not both)".into());
         }
 
-        if self.to_tron_lookup_name.is_some() != self.to_tron_id.is_some()
+        if self.to_tron_lookup_name.is_some() == self.to_tron_id.is_some()
         {
             return Err("message builder to_tron_lookup_name OR to_tron_id must be set (but not both)".into());
         }
 
-        if self.to_cycle_kind.is_some() != self.to_cycle.is_some()
-        {
-            return Err("message builder to_cycle_kind OR to_cycle must be set (but not both)".into());
-        }
-        if self.payload.is_none()
-        {
-            return Err("message builder payload must be set".into());
-        }
-        if self.payload_artifact.is_none()
+        if self.to_port.is_none()
         {
-            return Err("message builder payload_artifact must be set".into());
+            return Err("message builder to_port must be set".into());
         }
 
         Ok(())
@@ -253,12 +304,12 @@ impl  MessageBuilder {
     {
         self.validate()?;
 
-        if self.to_nucleus_id.is_some()
+        if self.to_nucleus_id.is_none()
         {
             return Err("message builder to_nucleus_id must be set before build".into());
         }
 
-        if self.to_tron_id.is_some()
+        if self.to_tron_id.is_none()
         {
             return Err("message builder to_tron_id must be set before build".into());
         }
@@ -266,27 +317,39 @@ impl  MessageBuilder {
         Ok(())
     }
 
-    pub fn build(&self, seq: &mut IdSeq) -> Result<Message,Box<dyn Error>>
+    pub fn build(&self, seq: &IdSeq) -> Result<Message,Box<dyn Error>>
     {
         self.validate_build()?;
+        if self.from.is_none()
+        {
+            return Err("message builder from must be set before build".into());
+        }
+        // unless told otherwise a message is delivered at the start of the next cycle, the same as To::basic
         Ok(Message{
             id: seq.next(),
-            kind: self.kind.unwrap(),
-            from: self.from.unwrap(),
+            kind: self.kind.as_ref().unwrap().clone(),
+            from: self.from.as_ref().unwrap().clone(),
             to: To {
-                tron: TronKey { nucleus_id: self.to_nucleus_id.unwrap().clone(),
-                                tron_id: self.to_tron_id.unwrap().clone() },
-                port: self.to_port.unwrap().clone(),
-                cycle: self.to_cycle_kind.unwrap().clone(),
-                phase: self.to_phase.unwrap(),
+                tron: TronKey { nucleus_id: self.to_nucleus_id.as_ref().unwrap().clone(),
+                                tron_id: self.to_tron_id.as_ref().unwrap().clone() },
+                port: self.to_port.as_ref().unwrap().clone(),
+                cycle: match &self.to_cycle_kind {
+                    Some(cycle)=>cycle.clone(),
+                    None=>Cycle::Next
+                },
+                phase: self.to_phase.unwrap_or(0),
                 inter_delivery_type: match &self.to_inter_delivery_type {
                     Some(r)=>r.clone(),
-                    None=>Option::None
+                    None=>InterDeliveryType::Cyclic
                 }
             },
-            payloads: vec![],
+            payloads: match &self.payloads {
+                Some(payloads)=>payloads.clone(),
+                None=>vec![]
+            },
             meta: self.meta.clone(),
-            transaction: self.transaction.clone()
+            transaction: self.transaction.clone(),
+            response_port: self.response_port.clone()
         })
     }
 
@@ -332,34 +395,67 @@ impl  MessageBuilder {
         }
 
         if self.to_nucleus_id.is_some() {
-            buffer.set(&[&index, &"to_nucleus_id", &"seq_id"], self.to_nucleus_id.unwrap().seq_id)?;
-            buffer.set(&[&index, &"to_nucleus_id", &"id"], self.to_nucleus_id.unwrap().id)?;
+            buffer.set(&[&index, &"to_nucleus_id", &"seq_id"], self.to_nucleus_id.as_ref().unwrap().seq_id)?;
+            buffer.set(&[&index, &"to_nucleus_id", &"id"], self.to_nucleus_id.as_ref().unwrap().id)?;
         }
 
         if self.to_tron_id.is_some() {
-            buffer.set(&[&index, &"to_tron_id", &"seq_id"], self.to_tron_id.unwrap().seq_id)?;
-            buffer.set(&[&index, &"to_tron_id", &"id"], self.to_tron_id.unwrap().id)?;
+            buffer.set(&[&index, &"to_tron_id", &"seq_id"], self.to_tron_id.as_ref().unwrap().seq_id)?;
+            buffer.set(&[&index, &"to_tron_id", &"id"], self.to_tron_id.as_ref().unwrap().id)?;
+        }
+
+        if self.to_cycle_kind.is_some()
+        {
+            match self.to_cycle_kind.as_ref().unwrap()
+            {
+                Cycle::Future(cycle)=>{
+                    buffer.set(&[&index, &"to_cycle_kind"], NP_Enum::new("future"))?;
+                    buffer.set(&[&index, &"to_cycle"], cycle.clone())?;
+                },
+                Cycle::Present=>{
+                    buffer.set(&[&index, &"to_cycle_kind"], NP_Enum::new("present"))?;
+                },
+                Cycle::Next=>{
+                    buffer.set(&[&index, &"to_cycle_kind"], NP_Enum::new("next"))?;
+                }
+            }
         }
 
-        buffer.set(&[&index, &"to_port"], self.to_port.unwrap())?;
+        if self.to_phase_name.is_some() {
+            buffer.set(&[&index, &"to_phase_name"], self.to_phase_name.as_ref().unwrap().as_str())?;
+        }
+
+        if self.to_phase.is_some() {
+            buffer.set(&[&index, &"to_phase"], self.to_phase.unwrap())?;
+        }
 
+        buffer.set(&[&index, &"to_port"], self.to_port.as_ref().unwrap().as_str())?;
 
         if self.to_inter_delivery_type.is_some()
         {
-            buffer.set(&[&index, &"to_inter_delivery_type"], match &self.to_inter_delivery_type.unwrap(){
-                InterDeliveryType::Cyclic=>"cyclic",
-                InterDeliveryType::Phasic=>"phasic",
+            buffer.set(&[&index, &"to_inter_delivery_type"], match self.to_inter_delivery_type.as_ref().unwrap(){
+                InterDeliveryType::Cyclic=>NP_Enum::new("cyclic"),
+                InterDeliveryType::Phasic=>NP_Enum::new("phasic"),
             } )?;
         }
 
-        buffer.set( &[&index,&"payload"], self.payload.as_ref().unwrap().read_bytes() )?;
-        buffer.set( &[&index,&"payload_artifact"], self.payload_artifact.as_ref().unwrap().to() )?;
+        if self.payloads.is_some()
+        {
+            let mut payload_index = 0;
+            for payload in self.payloads.as_ref().unwrap()
+            {
+                let payload_index_str = payload_index.to_string();
+                buffer.set( &[&index,&"payloads",&payload_index_str,&"buffer"], payload.buffer.read_bytes() )?;
+                buffer.set( &[&index,&"payloads",&payload_index_str,&"artifact"], payload.artifact.to().as_str() )?;
+                payload_index = payload_index+1;
+            }
+        }
 
         if self.meta.is_some()
         {
             for k in self.meta.as_ref().unwrap().keys()
             {
-                buffer.set(&[&index, &"meta", k], self.meta.as_ref().unwrap().get(k).as_ref().unwrap().to_string())?;
+                buffer.set(&[&index, &"meta", k], self.meta.as_ref().unwrap().get(k).unwrap().as_str())?;
             }
         }
 
@@ -370,10 +466,134 @@ impl  MessageBuilder {
             buffer.set(&[&index,&"transaction", &"id"], transaction.id)?;
         }
 
+        if self.response_port.is_some()
+        {
+            buffer.set(&[&index,&"response_port"], self.response_port.as_ref().unwrap().as_str() )?;
+        }
+
         Ok(())
     }
+
+    // builders arrive from a wasm guest as the bytes of a MESSAGE_BUILDERS_SCHEMA buffer.
+    // from is never serialized, the host sets it to the tron that ran the guest
+    pub fn message_builders_from_bytes( buffer_factories: &dyn BufferFactories, bytes: Vec<u8> )->Result<Vec<Self>,Box<dyn Error>>
+    {
+        let buffer = MESSAGE_BUILDERS_FACTORY.open_buffer( bytes );
+        MessageBuilder::message_builders_from_buffer( buffer_factories, &buffer )
+    }
+
+    pub fn message_builders_from_buffer<M: NP_Memory + Clone + NP_Mem_New>( buffer_factories: &dyn BufferFactories, buffer: &NP_Buffer<M> )->Result<Vec<Self>,Box<dyn Error>>
+    {
+        let length = match buffer.get_length(&[])
+        {
+            Ok(length)=>length.unwrap_or(0),
+            Err(_)=>return Err("could not read the length of message builders buffer".into())
+        };
+
+        let mut rtn = vec![];
+        for index in 0..length
+        {
+            rtn.push(MessageBuilder::from_buffer( buffer_factories, buffer, index )?);
+        }
+        Ok(rtn)
+    }
+
+    fn from_buffer<M: NP_Memory + Clone + NP_Mem_New>( buffer_factories: &dyn BufferFactories, buffer: &NP_Buffer<M>, index: usize )->Result<Self,Box<dyn Error>>
+    {
+        let index = index.to_string();
+        let mut builder = MessageBuilder::new();
+
+        builder.kind = Option::Some(index_to_message_kind(Message::get::<u8,M>(buffer, &[&index, &"kind"])?)?);
+        builder.to_nucleus_lookup_name = option::<String,M>(buffer, &[&index, &"to_nucleus_lookup_name"])?;
+        builder.to_tron_lookup_name = option::<String,M>(buffer, &[&index, &"to_tron_lookup_name"])?;
+        builder.to_nucleus_id = option_id(buffer, &[&index, &"to_nucleus_id"])?;
+        builder.to_tron_id = option_id(buffer, &[&index, &"to_tron_id"])?;
+
+        builder.to_cycle_kind = match option::<NP_Enum,M>(buffer, &[&index, &"to_cycle_kind"])?
+        {
+            Some(NP_Enum::Some(kind)) if kind == "future" => Option::Some(Cycle::Future(Message::get::<i64,M>(buffer, &[&index, &"to_cycle"])?)),
+            Some(NP_Enum::Some(kind)) if kind == "present" => Option::Some(Cycle::Present),
+            Some(NP_Enum::Some(kind)) if kind == "next" => Option::Some(Cycle::Next),
+            _ => Option::None
+        };
+
+        builder.to_phase_name = option::<String,M>(buffer, &[&index, &"to_phase_name"])?;
+        builder.to_phase = option::<u8,M>(buffer, &[&index, &"to_phase"])?;
+        builder.to_port = option::<String,M>(buffer, &[&index, &"to_port"])?;
+        builder.to_inter_delivery_type = match option::<NP_Enum,M>(buffer, &[&index, &"to_inter_delivery_type"])?
+        {
+            Some(NP_Enum::Some(kind)) if kind == "cyclic" => Option::Some(InterDeliveryType::Cyclic),
+            Some(NP_Enum::Some(kind)) if kind == "phasic" => Option::Some(InterDeliveryType::Phasic),
+            _ => Option::None
+        };
+
+        let payloads_length = match buffer.get_length(&[&index, &"payloads"])
+        {
+            Ok(length)=>length.unwrap_or(0),
+            Err(_)=>return Err(format!("could not read payloads of message builder {}",index).into())
+        };
+        if payloads_length > 0
+        {
+            let mut payloads = vec!();
+            for payload_index in 0..payloads_length
+            {
+                let payload_index = payload_index.to_string();
+                let artifact = Artifact::from(Message::get::<String,M>(buffer, &[&index, &"payloads", &payload_index, &"artifact"])?.as_str())?;
+                let bytes = Message::get::<Vec<u8>,M>(buffer, &[&index, &"payloads", &payload_index, &"buffer"])?;
+                payloads.push(Payload{ buffer: Arc::new(buffer_factories.create_buffer_from_array(&artifact, bytes)?), artifact: artifact });
+            }
+            builder.payloads = Option::Some(payloads);
+        }
+
+        let mut meta: HashMap<String,String> = HashMap::new();
+        if let Ok(Some(items)) = buffer.get_collection(&[&index, &"meta"])
+        {
+            for item in items
+            {
+                if let Ok(Some(value)) = item.get::<String>()
+                {
+                    meta.insert(item.key.to_string(), value);
+                }
+            }
+        }
+        if !meta.is_empty()
+        {
+            builder.meta = Option::Some(meta);
+        }
+
+        builder.transaction = option_id(buffer, &[&index, &"transaction"])?;
+        builder.response_port = option::<String,M>(buffer, &[&index, &"response_port"])?;
+
+        Ok(builder)
+    }
 }
 
+fn option<'get, X: 'get,M: NP_Memory + Clone + NP_Mem_New>(buffer:&'get NP_Buffer<M>, path: &[&str]) -> Result<Option<X>, Box<dyn Error>> where X: NP_Value<'get> + NP_Scalar<'get>
+{
+    match buffer.get::<X>(path)
+    {
+        Ok(option)=>Ok(option),
+        Err(_)=>Err(format!("could not get {}",cat(path)).into())
+    }
+}
+
+fn option_id<M: NP_Memory + Clone + NP_Mem_New>(buffer:&NP_Buffer<M>, path: &[&str]) -> Result<Option<Id>, Box<dyn Error>>
+{
+    let mut seq_id_path = path.to_vec();
+    seq_id_path.push("seq_id");
+    let mut id_path = path.to_vec();
+    id_path.push("id");
+
+    match option::<i64,M>(buffer, &seq_id_path)?
+    {
+        None=>Ok(Option::None),
+        Some(seq_id)=>Ok(Option::Some(Id::new(seq_id, Message::get::<i64,M>(buffer, &id_path)?)))
+    }
+}
+
+// the port a Reject is delivered to on the tron that sent the rejected message
+pub static REJECT_PORT: &'static str = "reject";
+
 #[derive(Clone)]
 pub struct Payload {
     pub buffer: Arc<NP_Buffer<NP_Memory_Owned>>,
@@ -389,12 +609,13 @@ pub struct Message {
     pub payloads: Vec<Payload>,
     pub meta: Option<HashMap<String,String>>,
     pub transaction: Option<Id>,
+    pub response_port: Option<String>
 }
 
 
 impl Message {
 
-    pub fn single_payload(seq: & mut IdSeq,
+    pub fn single_payload(seq: &IdSeq,
                  kind: MessageKind,
                  from: From,
                  to: To,
@@ -403,7 +624,7 @@ impl Message {
         Message::multi_payload( seq, kind, from, to, vec!(payload) )
     }
 
-    pub fn multi_payload(seq: & mut IdSeq,
+    pub fn multi_payload(seq: &IdSeq,
                  kind: MessageKind,
                  from: From,
                  to: To,
@@ -414,7 +635,7 @@ impl Message {
 
 
 
-    pub fn longform(seq: & mut IdSeq,
+    pub fn longform(seq: &IdSeq,
                kind: MessageKind,
                from: From,
                to: To,
@@ -429,10 +650,53 @@ impl Message {
             to: to,
             payloads: payloads,
             meta: meta,
-            transaction: transaction
+            transaction: transaction,
+            response_port: Option::None
+        }
+    }
+
+    // a Reject addressed back to the sender of this message, the reason is carried in meta.
+    // the Reject of a Request goes to its response port, the same as a Response would
+    pub fn reject(&self, seq: &IdSeq, from: From, reason: &str ) -> Self
+    {
+        let port = match (&self.kind, &self.response_port) {
+            (MessageKind::Request, Some(response_port)) => response_port.clone(),
+            _ => REJECT_PORT.to_string()
+        };
+
+        let mut meta = HashMap::new();
+        meta.insert("reason".to_string(), reason.to_string() );
+        meta.insert("rejected_message_seq_id".to_string(), self.id.seq_id.to_string() );
+        meta.insert("rejected_message_id".to_string(), self.id.id.to_string() );
+
+        Message::longform( seq,
+                           MessageKind::Reject,
+                           from,
+                           To::basic( self.from.tron.clone(), port ),
+                           vec!(),
+                           Option::Some(meta),
+                           self.transaction.clone() )
+    }
+
+    // the payload at 'index', a message that does not carry that many payloads is an error
+    pub fn payload(&self, index: usize) -> Result<&Payload, Box<dyn Error>>
+    {
+        match self.payloads.get(index)
+        {
+            None => Err(format!("message {:?} carries {} payloads and has none at index {}", self.id, self.payloads.len(), index).into()),
+            Some(payload) => Ok(payload)
         }
     }
 
+    // the id of the message a Reject was sent back for
+    pub fn rejected_message_id(&self) -> Option<Id>
+    {
+        let meta = self.meta.as_ref()?;
+        let seq_id = meta.get("rejected_message_seq_id")?.parse::<i64>().ok()?;
+        let id = meta.get("rejected_message_id")?.parse::<i64>().ok()?;
+        Option::Some(Id::new(seq_id, id))
+    }
+
     pub fn messages_to_buffer<'message,'buffer> ( messages: &[&'message Message] )->Result<NP_Buffer<NP_Memory_Owned> ,Box<dyn Error>>
     {
         let mut buffer= MESSAGES_FACTORY.new_buffer(Option::None);
@@ -452,45 +716,68 @@ impl Message {
     pub fn append_to_buffer<M: NP_Memory + Clone + NP_Mem_New>(&self, buffer: &mut NP_Buffer<M>, index: usize ) -> Result<(),Box<NP_Error>>
     {
         let index = index.to_string();
+        buffer.set(&[&index, &"id", &"seq_id"], self.id.seq_id)?;
+        buffer.set(&[&index, &"id", &"id"], self.id.id)?;
         buffer.set( &[&index,&"kind"], message_kind_to_index(&self.kind) )?;
-        buffer.set(&[&index, &"from", &"nucleus_id",&"seq_id"],&self.from.tron.nucleus_id.seq_id)?;
-        buffer.set(&[&index, &"from", &"nucleus_id",&"id"],&self.from.tron.nucleus_id.id)?;
-        buffer.set(&[&index, &"from", &"tron_id",&"seq_id"],&self.from.tron.tron_id.seq_id)?;
-        buffer.set(&[&index, &"from", &"tron_id",&"id"],&self.from.tron.tron_id.id)?;
+        buffer.set(&[&index, &"from", &"tron", &"nucleus_id",&"seq_id"],self.from.tron.nucleus_id.seq_id)?;
+        buffer.set(&[&index, &"from", &"tron", &"nucleus_id",&"id"],self.from.tron.nucleus_id.id)?;
+        buffer.set(&[&index, &"from", &"tron", &"tron_id",&"seq_id"],self.from.tron.tron_id.seq_id)?;
+        buffer.set(&[&index, &"from", &"tron", &"tron_id",&"id"],self.from.tron.tron_id.id)?;
         buffer.set(&[&index, &"from", &"cycle"], self.from.cycle.clone())?;
         buffer.set(&[&index, &"from", &"timestamp"], self.from.timestamp.clone())?;
 
-        buffer.set(&[&index, &"to", &"nucleus_id",&"seq_id"],&self.to.tron.nucleus_id.seq_id)?;
-        buffer.set(&[&index, &"to", &"nucleus_id",&"id"],&self.to.tron.nucleus_id.id)?;
-        buffer.set(&[&index, &"to", &"tron_id",&"seq_id"],&self.to.tron.tron_id.seq_id)?;
-        buffer.set(&[&index, &"to", &"tron_id",&"id"],&self.to.tron.tron_id.id)?;
+        buffer.set(&[&index, &"to", &"tron", &"nucleus_id",&"seq_id"],self.to.tron.nucleus_id.seq_id)?;
+        buffer.set(&[&index, &"to", &"tron", &"nucleus_id",&"id"],self.to.tron.nucleus_id.id)?;
+        buffer.set(&[&index, &"to", &"tron", &"tron_id",&"seq_id"],self.to.tron.tron_id.seq_id)?;
+        buffer.set(&[&index, &"to", &"tron", &"tron_id",&"id"],self.to.tron.tron_id.id)?;
         buffer.set(&[&index, &"to", &"phase"], self.to.phase)?;
-        buffer.set(&[&index, &"to", &"port"], self.to.port.clone() )?;
-        buffer.set(&[&index, &"to", &"inter_delivery_type"], match &self.inter_delivery_type {
-            InterDeliveryType::Cyclic=>"cyclic",
-            InterDeliveryType::Phasic=>"phasic"
+        buffer.set(&[&index, &"to", &"port"], self.to.port.as_str() )?;
+        buffer.set(&[&index, &"to", &"inter_delivery_type"], match &self.to.inter_delivery_type {
+            InterDeliveryType::Cyclic=>NP_Enum::new("cyclic"),
+            InterDeliveryType::Phasic=>NP_Enum::new("phasic")
         } )?;
 
         match &self.to.cycle
         {
-            Cycle::Future(c)=>buffer.set(&[&index, &"to", &"cycle"], c.clone())?,
-            Cycle::Next=> false
+            Cycle::Future(c)=>{
+                buffer.set(&[&index, &"to", &"cycle_kind"], NP_Enum::new("future"))?;
+                buffer.set(&[&index, &"to", &"cycle"], c.clone())?;
+            },
+            Cycle::Present=>{
+                buffer.set(&[&index, &"to", &"cycle_kind"], NP_Enum::new("present"))?;
+            },
+            Cycle::Next=>{
+                buffer.set(&[&index, &"to", &"cycle_kind"], NP_Enum::new("next"))?;
+            }
         };
 
+        let mut payload_index = 0;
+        for payload in &self.payloads
+        {
+            let payload_index_str = payload_index.to_string();
+            buffer.set( &[&index,&"payloads",&payload_index_str,&"buffer"], payload.buffer.read_bytes() )?;
+            buffer.set( &[&index,&"payloads",&payload_index_str,&"artifact"], payload.artifact.to().as_str() )?;
+            payload_index = payload_index+1;
+        }
 
-
-        buffer.set( &[&index,&"payload"], self.payload.read_bytes() )?;
-        buffer.set( &[&index,&"payload_artifact"], self.payload_artifact.to() )?;
-
-        for k in self.meta.keys()
+        if self.meta.is_some()
         {
-            buffer.set( &[&index,&"meta",k], self.meta.get(k).unwrap().to_string())?;
+            for (k,v) in self.meta.as_ref().unwrap()
+            {
+                buffer.set( &[&index,&"meta",k], v.as_str())?;
+            }
         }
 
         if self.transaction.is_some()
         {
             let transaction = self.transaction.as_ref().unwrap();
-            buffer.set(&[&index,&"transaction"], transaction.clone() )?;
+            buffer.set(&[&index,&"transaction", &"seq_id"], transaction.seq_id )?;
+            buffer.set(&[&index,&"transaction", &"id"], transaction.id )?;
+        }
+
+        if self.response_port.is_some()
+        {
+            buffer.set(&[&index,&"response_port"], self.response_port.as_ref().unwrap().as_str() )?;
         }
 
         Ok(())
@@ -499,83 +786,85 @@ impl Message {
     pub fn from_buffer<M: NP_Memory + Clone + NP_Mem_New>(buffer_factories: & dyn BufferFactories, buffer: &NP_Buffer<M>, index: usize ) -> Result<Self,Box<dyn Error>>
     {
         let index = index.to_string();
-        let payload_artifact = Message::get::<String,M>(&buffer, &[&index,&"payload_artifact"])?;
-        let payload_artifact = Artifact::from(&payload_artifact)?;
-        let payload = Message::get::<Vec<u8>,M>(&buffer, &[&index,&"payload"])?;
 
         let mut meta: HashMap<String,String> = HashMap::new();
-        if buffer.get_collection( &[&index,&"meta"]).unwrap().is_some()
+        if let Ok(Some(items)) = buffer.get_collection( &[&index,&"meta"])
         {
-            for item in buffer.get_collection(&[&index, &"meta"]).unwrap().unwrap()
+            for item in items
             {
-                meta.insert(item.key.to_string(), item.get::<String>().unwrap().unwrap());
+                if let Ok(Some(value)) = item.get::<String>()
+                {
+                    meta.insert(item.key.to_string(), value);
+                }
             }
         }
 
-        let meta= meta;
+        let payloads_length = match buffer.get_length(&[&index, &"payloads"])
+        {
+            Ok(length)=>length.unwrap_or(0),
+            Err(_)=>return Err(format!("could not read payloads of message {}",index).into())
+        };
+        let mut payloads = vec!();
+        for payload_index in 0..payloads_length
+        {
+            let payload_index = payload_index.to_string();
+            let artifact = Artifact::from(Message::get::<String,M>( buffer, &[ &index, &"payloads", &payload_index, &"artifact"])?.as_str())?;
+            let bytes = Message::get::<Vec<u8>,M>( buffer, &[ &index, &"payloads", &payload_index, &"buffer"])?;
+            payloads.push(Payload { buffer: Arc::new(buffer_factories.create_buffer_from_array(&artifact, bytes)?), artifact: artifact });
+        }
 
         let message = Message {
-            id: Id{
-                seq_id: Message::get::<i64,M>(&buffer, &[&index,&"id",&"seq_id"])?,
-                id: Message::get::<i64,M>(&buffer, &[&index,&"id",&"id"])?
+            id: Id::new( Message::get::<i64,M>(buffer, &[&index,&"id",&"seq_id"])?,
+                         Message::get::<i64,M>(buffer, &[&index,&"id",&"id"])? ),
+            kind: index_to_message_kind(Message::get::<u8,M>(buffer, &[&index, &"kind"])?)?,
+            from: From{
+                tron: TronKey::new( Id::new( Message::get::<i64,M>(buffer, &[&index,&"from",&"tron",&"nucleus_id",&"seq_id"])?,
+                                             Message::get::<i64,M>(buffer, &[&index,&"from",&"tron",&"nucleus_id",&"id"])? ),
+                                    Id::new( Message::get::<i64,M>(buffer, &[&index,&"from",&"tron",&"tron_id",&"seq_id"])?,
+                                             Message::get::<i64,M>(buffer, &[&index,&"from",&"tron",&"tron_id",&"id"])? ) ),
+                timestamp: Message::get::<i64,M>(buffer, &[&index,&"from",&"timestamp"])?,
+                cycle: Message::get::<i64,M>(buffer, &[&index,&"from",&"cycle"])?
             },
-            kind: index_to_message_kind(Message::get::<u8,M>(&buffer, &[&index, &"kind"])?)?,
-            from:
-                    From{ tron: TronKey{ nucleus_id : Id{ seq_id: Message::get::<i64,M>(&buffer, &[&index,&"from",&"tron",&"nucleus_id",&"seq_id"])?,
-                                                          id: Message::get::<i64,M>(&buffer, &[&index,&"from",&"tron",&"nucleus_id",&"id"])? },
-                                             tron_id: Id{ seq_id: Message::get::<i64,M>(&buffer, &[&index,&"from",&"tron",&"tron_id",&"seq_id"])?,
-                                                          id: Message::get::<i64,M>(&buffer, &[&index,&"from",&"tron",&"tron_id",&"id"])? }},
-                    timestamp: Message::get::<i64,M>(&buffer, &[&index,&"from",&"timestamp"])?,
-                    cycle: Message::get::<i64,M>(&buffer, &[&index,&"from",&"cycle"])?
-
-                    } ,
-
-
-                to:To{ tron: TronKey{ nucleus_id : Id{ seq_id: Message::get::<i64,M>(&buffer, &[&index,&"to",&"tron",&"nucleus_id",&"seq_id"])?,
-                    id: Message::get::<i64,M>(&buffer, &[&index,&"to",&"tron",&"nucleus_id",&"id"])? },
-                    tron_id: Id{ seq_id: Message::get::<i64,M>(&buffer, &[&index,&"to",&"tron",&"tron_id",&"seq_id"])?,
-                        id: Message::get::<i64,M>(&buffer, &[&index,&"to",&"tron",&"tron_id",&"id"])? }},
-                    cycle: match buffer.get::<i64>(&[&index,&"to",&"cycle"]).unwrap() {
-                        Some(cycle) => Cycle::Future(cycle),
-                        None => Cycle::Next
-                    },
-                    phase:Message::get::<u8,M>(&buffer, &[&index,&"to",&"phase"])?,
-                    port:Message::get::<String,M>(&buffer, &[&index,&"to",&"port"])?,
-                    inter_delivery_type: match Message::get::<String,M>(&buffer, &[&index,&"to",&"inter_delivery_type"])?{
-                       &"cyclic"=>InterDeliveryType::Cyclic,
-                        &"phasic"=>InterDeliveryType::Phasic,
-                        _ => {}
-                    }
+            to: To{
+                tron: TronKey::new( Id::new( Message::get::<i64,M>(buffer, &[&index,&"to",&"tron",&"nucleus_id",&"seq_id"])?,
+                                             Message::get::<i64,M>(buffer, &[&index,&"to",&"tron",&"nucleus_id",&"id"])? ),
+                                    Id::new( Message::get::<i64,M>(buffer, &[&index,&"to",&"tron",&"tron_id",&"seq_id"])?,
+                                             Message::get::<i64,M>(buffer, &[&index,&"to",&"tron",&"tron_id",&"id"])? ) ),
+                cycle: match option::<NP_Enum,M>(buffer, &[&index,&"to",&"cycle_kind"])? {
+                    Some(NP_Enum::Some(kind)) if kind == "future" => Cycle::Future(Message::get::<i64,M>(buffer, &[&index,&"to",&"cycle"])?),
+                    Some(NP_Enum::Some(kind)) if kind == "present" => Cycle::Present,
+                    _ => Cycle::Next
                 },
-
-            payloads: { let length = buffer.get_length(&[] )?.unwrap();
-                        let mut rtn = vec!();
-                        for payload_index in 0..length {
-                            let artifact = Artifact::from(Message::get:: <String, M>( & buffer, &[ & index, &"payloads", &payload_index, &"artifact"])?.as_str())?;
-                            let bytes = Message::get:: <Vec<u8>, M>( & buffer, &[ & index, &"payloads", &payload_index, &"buffer"])?;
-                            let payload = Payload { buffer: Arc::new(buffer_factories.create_buffer_from(&artifact, bytes)?), artifact: artifact };
-                            rtn.push(payload);
-                        }
-                        rtn},
-
-
-            meta: Option::None,
-            transaction: match buffer.get::<i64>( &[&index,&"transaction", &"seq_id"] )?{
-                None=>Option::None,
-                Some(seq_id)=>Some( Id{ seq_id:seq_id,
-                                              id: buffer.get::<i64>( &[&index,&"transaction", &"seq_id"] )?.unwrap()
-                 })
-            }
+                phase: Message::get::<u8,M>(buffer, &[&index,&"to",&"phase"])?,
+                port: Message::get::<String,M>(buffer, &[&index,&"to",&"port"])?,
+                inter_delivery_type: match option::<NP_Enum,M>(buffer, &[&index,&"to",&"inter_delivery_type"])? {
+                    Some(NP_Enum::Some(kind)) if kind == "phasic" => InterDeliveryType::Phasic,
+                    _ => InterDeliveryType::Cyclic
+                }
+            },
+            payloads: payloads,
+            meta: match meta.is_empty() {
+                true => Option::None,
+                false => Option::Some(meta)
+            },
+            transaction: option_id(buffer, &[&index,&"transaction"])?,
+            response_port: option::<String,M>(buffer, &[&index,&"response_port"])?
         };
         return Ok(message);
     }
 
-    pub fn messages_from_bytes(  seq: &mut IdSeq,buffer_factories: & dyn BufferFactories, bytes: &Bytes) -> Result<Vec<Self>,Box<dyn Error>>
+    pub fn messages_from_bytes(  seq: &IdSeq,buffer_factories: & dyn BufferFactories, bytes: &Bytes) -> Result<Vec<Self>,Box<dyn Error>>
     {
         let buffer = MESSAGES_FACTORY.open_buffer( bytes.to_vec() );
         return Ok( Message::messages_from_buffer( buffer_factories, &buffer)? );
     }
 
+    pub fn messages_from_array( buffer_factories: & dyn BufferFactories, array: Vec<u8> ) -> Result<Vec<Self>,Box<dyn Error>>
+    {
+        let buffer = MESSAGES_FACTORY.open_buffer( array );
+        Message::messages_from_buffer( buffer_factories, &buffer )
+    }
+
     pub fn messages_from_buffer<M: NP_Memory + Clone + NP_Mem_New>( buffer_factories: & dyn BufferFactories, buffer: &NP_Buffer<M> ) -> Result<Vec<Self>,Box<dyn Error>>
     {
         let length = buffer.get_length(&[] )?.unwrap();
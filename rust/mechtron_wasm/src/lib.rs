@@ -2,22 +2,22 @@
 extern crate lazy_static;
 
 use std::sync::atomic::{{AtomicUsize, Ordering}};
-use std::sync::{Mutex, Arc};
+use std::sync::{Mutex, Arc, Once};
+use std::panic;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use no_proto::buffer::NP_Buffer;
-use no_proto::buffer_ro::NP_Buffer_RO;
+use no_proto::memory::NP_Memory_Owned;
 use no_proto::NP_Factory;
 use wasm_bindgen::__rt::std::collections::HashMap;
 use wasm_bindgen::__rt::std::error::Error;
 use wasm_bindgen::prelude::*;
 
-use mechtron_config::artifact_config::Artifact;
-use mechtron_config::buffers::BufferFactories;
-use mechtron_config::mechtron_config::{ActorConfigYaml, MechtronConfig};
-use std::ops::Deref;
-use std::borrow::{Borrow, BorrowMut};
-use std::cell::RefCell;
+use mechtron_common::artifact::Artifact;
+use mechtron_common::buffers::BufferFactories;
+use mechtron_common::configs::{MechtronConfig, MechtronConfigYaml, TronConfig, TronConfigYaml};
+use mechtron_common::error::{GuestError, GuestErrorKind};
+use mechtron_common::message::{Message, MessageBuilder};
 
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -29,13 +29,22 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 extern "C"
 {
     fn host_alloc_buffer( len: i32 ) -> i32;
-    fn host_append_to_buffer( id: i32, value: i32 );
+    fn host_write_to_buffer( id: i32, ptr: i32, len: i32 );
     fn host_dealloc_buffer( id: i32 );
     fn host_log( buffer_id: i32 );
     fn host_content_update( buffer_id: i32 );
     fn host_messages(buffer_id: i32);
-    fn host_write_artifact_as_string(artifact_name_buffer_id: i32) -> i32;
-    fn mechtron_create(ctx: &MechtronContext, create_message: &NP_Buffer_RO, content: &mut NP_Buffer) -> Result<(), Box<std::error::Error>>;
+    fn host_error(buffer_id: i32);
+}
+
+// a mechtron implements these.  content is the tron's content data opened with the
+// content artifact of its tron config, changes made to it become the tron's new content.
+// the returned builders are sent to the host as the messages the tron emits
+extern "C"
+{
+    fn mechtron_tron_create(ctx: &MechtronContext, content: &mut NP_Buffer<NP_Memory_Owned>, create: &Message) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>;
+    fn mechtron_tron_update(ctx: &MechtronContext, content: &mut NP_Buffer<NP_Memory_Owned>, phase: &str) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>;
+    fn mechtron_tron_port(ctx: &MechtronContext, content: &mut NP_Buffer<NP_Memory_Owned>, port: &str, messages: &Vec<Message>) -> Result<Option<Vec<MessageBuilder>>, Box<dyn Error>>;
 }
 
 
@@ -53,18 +62,23 @@ lazy_static! {
         Mutex::new(a)
     };
 
-    static ref message_buffer_factories: Mutex<Box<BufferFactoriesCache<'static>>> = {
+    static ref message_buffer_factories: Mutex<Box<BufferFactoriesCache>> = {
         let bfc = BufferFactoriesCache::new();
         Mutex::new(bfc)
     };
+
+    // stands in for the content of trons that do not declare any
+    static ref no_content_factory: NP_Factory<'static> = NP_Factory::new(r#"{"type":"any"}"#).unwrap();
 }
 
-struct BufferFactoriesCache<'a>
+// factories are bound once when the guest is instantiated and live as long as the guest
+// does, so they are leaked to hand them out as 'static
+struct BufferFactoriesCache
 {
-    cache: HashMap<Artifact,NP_Factory<'a>>
+    cache: HashMap<Artifact,&'static NP_Factory<'static>>
 }
 
-impl <'a> BufferFactoriesCache<'a>
+impl BufferFactoriesCache
 {
     fn new()->Box<Self>
     {
@@ -77,89 +91,120 @@ impl <'a> BufferFactoriesCache<'a>
         let result = NP_Factory::new(consume_string(factory_schema_buffer_id)?);
         match result{
             Ok(factory)=>{
-                self.cache.insert( artifact_file.clone(), factory );
+                self.cache.insert( artifact_file.clone(), Box::leak(Box::new(factory)) );
                 Ok(())
             },
-            Err(_)=> Err("could not parse factory config".into())
+            Err(e)=> Err(format!("could not parse factory config of {}: {:?}", artifact_file.to(), e).into())
         }
     }
 }
 
-impl <'a> BufferFactories for BufferFactoriesCache<'a>
+impl BufferFactories for BufferFactoriesCache
 {
-    fn create_buffer(&self, artifact_file: &Artifact) -> Result<NP_Buffer, Box<dyn Error>> {
-        return match self.get_buffer_factory(artifact_file)
+    fn create_buffer(&self, artifact: &Artifact) -> Result<NP_Buffer<NP_Memory_Owned>, Box<dyn Error>> {
+        return match self.get_buffer_factory(artifact)
         {
-            Some(factory)=>Ok(factory.empty_buffer(Option::None)),
-            None=>Err(format!("could not find factory: {}", artifact_file.to()).into())
+            Some(factory)=>Ok(factory.new_buffer(Option::None)),
+            None=>Err(format!("could not find factory: {}", artifact.to()).into())
         }
     }
 
-    fn create_buffer_from(&self, artifact_file: &Artifact, array: Vec<u8>) -> Result<NP_Buffer, Box<dyn Error>> {
-        return match self.get_buffer_factory(artifact_file)
+    fn create_buffer_from_array(&self, artifact: &Artifact, array: Vec<u8>) -> Result<NP_Buffer<NP_Memory_Owned>, Box<dyn Error>> {
+        return match self.get_buffer_factory(artifact)
         {
             Some(factory)=>Ok(factory.open_buffer(array)),
-            None=>Err(format!("could not find factory: {}", artifact_file.to()).into())
+            None=>Err(format!("could not find factory: {}", artifact.to()).into())
         }
     }
 
-    fn get_buffer_factory(&self, artifact_file: &Artifact) -> Option<&NP_Factory> {
-        return self.cache.get(artifact_file);
+    fn create_buffer_from_buffer(&self, artifact: &Artifact, buffer: NP_Buffer<NP_Memory_Owned>) -> Result<NP_Buffer<NP_Memory_Owned>, Box<dyn Error>> {
+        self.create_buffer_from_array(artifact, buffer.finish().bytes())
+    }
+
+    fn get_buffer_factory(&self, artifact: &Artifact) -> Option<&'static NP_Factory<'static>> {
+        return self.cache.get(artifact).cloned();
     }
 }
 
-pub fn host_write_buffer( buffer: &NP_Buffer ) -> i32
+// the host copies the bytes out of this guest's linear memory in one go
+pub fn host_write_bytes( bytes: &[u8] ) -> i32
 {
     unsafe {
-        let buffer_id = host_alloc_buffer(buffer.calc_bytes().unwrap().current_buffer as _);
-        for b in buffer.read_bytes()
-        {
-            host_append_to_buffer(buffer_id, *b as _);
-        }
+        let buffer_id = host_alloc_buffer(bytes.len() as _);
+        host_write_to_buffer(buffer_id, bytes.as_ptr() as _, bytes.len() as _);
         return buffer_id;
     }
 }
 
-pub fn host_write_string(str: &str) -> i32
+pub fn host_write_buffer( buffer: &NP_Buffer<NP_Memory_Owned> ) -> i32
 {
-    unsafe {
-        let buffer_id = host_alloc_buffer(str.len() as _);
-        for b in str.bytes()
-        {
-            host_append_to_buffer(buffer_id, b as _);
-        }
-        return buffer_id;
-    }
+    host_write_bytes(buffer.read_bytes())
 }
 
-pub fn host_get_artifact_as_str(artifact: &str) -> Result<String, Box<dyn Error>>
+pub fn host_write_string(str: &str) -> i32
 {
-    let artifact_name_buffer_id = host_write_string(artifact);
-    unsafe {
-        let artifact_as_string_buffer_id =
-            host_write_artifact_as_string(artifact_name_buffer_id);
-        let rtn = consume_string(artifact_as_string_buffer_id)?;
-        Ok(rtn)
-    }
+    host_write_bytes(str.as_bytes())
 }
 
 pub fn log(str: &str)
 {
+    let buffer_id = host_write_string(str);
     unsafe
         {
-            let buffer_id = host_alloc_buffer(str.len() as _);
-            for b in str.as_bytes()
-            {
-                host_append_to_buffer(buffer_id, *b as i32);
-            }
             host_log(buffer_id);
             host_dealloc_buffer(buffer_id);
         }
 }
 
+// the host keeps the error and hands it to whoever made the call that failed
+pub fn report_error( kind: GuestErrorKind, message: String, backtrace: Option<String> )
+{
+    let error = GuestError::new(kind, message, backtrace);
+    match error.to_bytes()
+    {
+        Ok(bytes) => unsafe {
+            host_error(host_write_bytes(bytes.as_slice()));
+        },
+        Err(e) => log(format!("could not report error: {}: {}", error, e).as_str())
+    }
+}
+
+// a panic traps the guest before it can return, so it is reported as it happens.
+// there is no backtrace to be had in wasm so the location of the panic stands in for it
+fn report_panic( info: &panic::PanicInfo )
+{
+    let message = if let Option::Some(message) = info.payload().downcast_ref::<&str>()
+    {
+        message.to_string()
+    }
+    else if let Option::Some(message) = info.payload().downcast_ref::<String>()
+    {
+        message.clone()
+    }
+    else
+    {
+        "unknown panic".to_string()
+    };
+
+    let location = info.location().map(|location| format!("at {}:{}:{}", location.file(), location.line(), location.column()));
+    report_error(GuestErrorKind::Panic, message, location);
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+// the host calls alloc_buffer and bind_message_artifact before mechtron_init, so every export
+// installs the hook rather than leaving it to whichever is called first
+fn hook_panics()
+{
+    PANIC_HOOK.call_once(|| panic::set_hook(Box::new(report_panic)));
+}
+
+// the buffer is allocated at its full length so the host can write all of it through buffer_ptr
 #[wasm_bindgen]
 pub fn alloc_buffer( len: i32 ) -> i32 {
+    hook_panics();
     let mut buffer = BytesMut::with_capacity(len as usize );
+    buffer.resize(len as usize, 0);
 
     let mut buffer_id = buffer_index.fetch_add(1,Ordering::Relaxed) as i32;
     buffers.lock().unwrap().insert(buffer_id,buffer);
@@ -167,14 +212,22 @@ pub fn alloc_buffer( len: i32 ) -> i32 {
     return buffer_id;
 }
 
+// where the buffer's bytes live in linear memory.  moving the BytesMut around the map
+// does not move its bytes so the pointer is good until the buffer is consumed or dealloced.
+// -1 tells the host there is no buffer with this id
 #[wasm_bindgen]
-pub fn append_to_buffer( id: i32, value: i32 )
+pub fn buffer_ptr( id: i32 ) -> i32
 {
-    let b= buffers.lock();
-    let mut unwrapped = b.unwrap();
-    let option = unwrapped.get_mut(&id);
-    let buffer = option.unwrap();
-    buffer.put_u8(value as u8);
+    let mut buffers_guard = match buffers.lock()
+    {
+        Ok(guard) => guard,
+        Err(_) => return -1
+    };
+    match buffers_guard.get_mut(&id)
+    {
+        None => -1,
+        Some(buffer) => buffer.as_mut_ptr() as i32
+    }
 }
 
 #[wasm_bindgen]
@@ -185,13 +238,22 @@ pub fn dealloc_buffer( id: i32 )
 
 
 #[wasm_bindgen]
-pub fn bind_message_artifact( artifact_file_buffer_id: i32, artifact_file_content_buffer_id: i32 )
+pub fn bind_message_artifact( artifact_file_buffer_id: i32, artifact_file_content_buffer_id: i32 ) -> i32
 {
+    hook_panics();
+
     let mut factories = message_buffer_factories.lock().unwrap();
-    factories.add_factory(artifact_file_buffer_id,artifact_file_content_buffer_id);
+    match factories.add_factory(artifact_file_buffer_id,artifact_file_content_buffer_id)
+    {
+        Ok(_) => 0,
+        Err(e) => {
+            report_error(GuestErrorKind::Error, format!("bind_message_artifact: {}", e), Option::None);
+            1
+        }
+    }
 }
 
-fn consume_buffer( buffer_id: i32 ) -> Result<Box<Bytes>,Box<std::error::Error>>
+fn consume_buffer( buffer_id: i32 ) -> Result<Box<Bytes>,Box<dyn Error>>
 {
     let option = buffers.lock().unwrap().remove(&buffer_id);
     match option {
@@ -209,167 +271,172 @@ fn consume_string(buffer_id: i32) -> Result<String, Box<dyn Error>>
 }
 
 #[wasm_bindgen]
-pub fn mechtron_init(mechtron_config_buffer_id:i32, mechtron_config_artifact_buffer_id: i32) -> i32
+pub fn mechtron_init(mechtron_config_buffer_id:i32, mechtron_config_artifact_buffer_id: i32, tron_config_buffer_id:i32, tron_config_artifact_buffer_id: i32) -> i32
 {
-    log( &"hello from WebAssembly actor_init" );
-    let result = init_context(mechtron_config_buffer_id, mechtron_config_artifact_buffer_id);
+    hook_panics();
 
-    if result.is_err()
+    let result = init_context(mechtron_config_buffer_id, mechtron_config_artifact_buffer_id, tron_config_buffer_id, tron_config_artifact_buffer_id);
+
+    match result
     {
-       println!("returning ERR");
-       return 1;
+        Ok(_) => 0,
+        Err(e) => {
+            report_error(GuestErrorKind::Error, format!("mechtron_init: {}", e), Option::None);
+            1
+        }
     }
-
-    println!( "actor name: {}", context.lock().unwrap().actor_config.as_ref().unwrap().name );
-
-    return 0;
 }
 
-fn get_artifact_file( artifact_buffer_id:i32)->Result<Artifact,Box<std::error::Error>>
+fn get_artifact_file( artifact_buffer_id:i32)->Result<Artifact,Box<dyn Error>>
 {
     let artifact_file_str= consume_string(artifact_buffer_id)?;
     return Ok(Artifact::from(&artifact_file_str )?);
 }
 
-fn init_context( actor_config_buffer_id:i32, actor_config_artifact_buffer_id:i32) -> Result<(),Box<std::error::Error>>
+fn init_context( mechtron_config_buffer_id:i32, mechtron_config_artifact_buffer_id:i32, tron_config_buffer_id:i32, tron_config_artifact_buffer_id:i32) -> Result<(),Box<dyn Error>>
 {
+    let mechtron_config_yaml = MechtronConfigYaml::from_yaml(&consume_string(mechtron_config_buffer_id)?)?;
+    let mechtron_config = mechtron_config_yaml.to_config( &get_artifact_file(mechtron_config_artifact_buffer_id)?)?;
+
+    let tron_config_yaml = TronConfigYaml::from_yaml(&consume_string(tron_config_buffer_id)?)?;
+    let tron_config = tron_config_yaml.to_config( &get_artifact_file(tron_config_artifact_buffer_id)?)?;
 
-    let actor_config_str = consume_string(actor_config_buffer_id)?;
-    let actor_config_yaml = ActorConfigYaml::from_yaml(&actor_config_str)?;
-    let actor_config = actor_config_yaml.to_actor_config( &get_artifact_file(actor_config_artifact_buffer_id)?)?;
-    log( &format!("Actor Name is: {}",actor_config.name) );
-    context.lock().unwrap().actor_config = Option::Some(actor_config);
+    let mut ctx = context.lock().unwrap();
+    ctx.mechtron_config = Option::Some(mechtron_config);
+    ctx.tron_config = Option::Some(tron_config);
     return Ok(());
 }
 
-
-#[wasm_bindgen]
-pub fn mechtron_actor_create( create_message_buffer_id: i32 ) -> i32
+// the host hands over the tron's content data, which is opened with the tron's content
+// artifact.  trons without content are given an empty buffer that is never sent back
+fn open_content( ctx: &MechtronContext, factories: &BufferFactoriesCache, content_buffer_id: i32 ) -> Result<Option<NP_Buffer<NP_Memory_Owned>>,Box<dyn Error>>
 {
-
-    log("mechtron_actor_create");
-    let factories = message_buffer_factories.lock().unwrap();
-    log("got factories");
-    let ctx = context.lock().unwrap();
-
-    let actor_config = &ctx.actor_config.as_ref().unwrap();
-    log("got actor_config");
-
-    let create_factory = factories.get_buffer_factory( &actor_config.create_message ).unwrap();
-    let content_factory = factories.get_buffer_factory( &actor_config.content ).unwrap();
-
-    log("got create factor and content_factory");
-    let raw_create_message = consume_buffer(create_message_buffer_id).unwrap();
-    log(format!("raw create message size: {}",raw_create_message.len()).as_str() );
-    log("raw_create_messagE");
-
-    let create_message = create_factory.open_buffer_ro(raw_create_message.as_ref());
-    let mut content = content_factory.empty_buffer(Option::None );
-    log("content");
-
-    unsafe {
-        match mechtron_create(&ctx, &create_message, &mut content ) {
-            Ok(_) => {
-                log( "wrign host buffeR" );
-                let content_buffer_id = host_write_buffer(&content);
-                log( "mechtron_actor_create: host_content_update()");
-                host_content_update(content_buffer_id);
-                log( "mechtron_actor_create: host_content_update() DONE");
-                return content_buffer_id;
-            },
-            Err(e) => {
-                log("error during create...");
-                return 1
-            }
-        }
+    let bytes = consume_buffer(content_buffer_id)?;
+    match &ctx.tron_config.as_ref().unwrap().content
+    {
+        None => Ok(Option::None),
+        Some(content) => Ok(Option::Some(factories.create_buffer_from_array(&content.artifact, bytes.to_vec())?))
     }
 }
 
-
-pub struct MechtronContext
+fn open_messages( factories: &BufferFactoriesCache, messages_buffer_id: i32 ) -> Result<Vec<Message>,Box<dyn Error>>
 {
-    actor_config: Option<MechtronConfig>
+    let bytes = consume_buffer(messages_buffer_id)?;
+    Message::messages_from_array(factories, bytes.to_vec())
 }
 
-impl MechtronContext
+// hand the new content and the emitted builders back to the host
+fn respond( content: Option<NP_Buffer<NP_Memory_Owned>>, builders: Option<Vec<MessageBuilder>> ) -> Result<(),Box<dyn Error>>
 {
-    fn new() -> Self
+    if let Option::Some(content) = content
     {
-        return MechtronContext { actor_config: Option::None };
+        unsafe {
+            host_content_update(host_write_buffer(&content));
+        }
     }
-}
 
+    if let Option::Some(builders) = builders
+    {
+        let buffer = MessageBuilder::message_builders_to_buffer(builders)?;
+        unsafe {
+            host_messages(host_write_buffer(&buffer));
+        }
+    }
+    Ok(())
+}
 
-pub struct MechtronArtifactCache
+fn invoke<F>( call: &str, content_buffer_id: i32, f: F ) -> i32 where F: FnOnce(&MechtronContext,&BufferFactoriesCache,&mut NP_Buffer<NP_Memory_Owned>) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
 {
-    cache: Mutex<RefCell<HashMap<Artifact, Arc<String>>>>,
-}
+    hook_panics();
 
+    let factories = message_buffer_factories.lock().unwrap();
+    let ctx = context.lock().unwrap();
 
-impl<'a> MechtronArtifactCache
-{
-    pub fn new(repo_path: String) -> Self
+    let result = match open_content(&ctx, &factories, content_buffer_id)
+    {
+        Ok(Some(mut content)) => match f(&ctx, &factories, &mut content) {
+            Ok(builders) => respond(Option::Some(content), builders),
+            Err(e) => Err(e)
+        },
+        Ok(None) => {
+            let mut empty = no_content_factory.new_buffer(Option::None);
+            match f(&ctx, &factories, &mut empty) {
+                Ok(builders) => respond(Option::None, builders),
+                Err(e) => Err(e)
+            }
+        },
+        Err(e) => Err(e)
+    };
+
+    match result
     {
-        return MechtronArtifactCache {
-            cache: Mutex::new(RefCell::new(HashMap::new())),
-        };
+        Ok(_) => 0,
+        Err(e) => {
+            report_error(GuestErrorKind::Error, format!("{}: {}", call, e), Option::None);
+            1
+        }
     }
 }
 
-impl<'a> ArtifactRepository for MechtronArtifactCache
+#[wasm_bindgen]
+pub fn mechtron_create( content_buffer_id: i32, messages_buffer_id: i32 ) -> i32
 {
-    fn fetch(&self, bundle: &ArtifactBundle) -> Result<(), Box<dyn Error + '_>>
-    {
-        let lock = self.fetches.read()?;
-        if lock.contains(bundle)
+    invoke( "mechtron_create", content_buffer_id, |ctx, factories, content| {
+        let messages = open_messages(factories, messages_buffer_id)?;
+        match messages.first()
         {
-            return Ok(())
+            None => Err("mechtron_create was not given a create message".into()),
+            Some(create) => unsafe { mechtron_tron_create(ctx, content, create) }
         }
+    })
+}
 
-        let mut lock = self.fetches.write()?;
-        lock.insert(bundle.clone());
+#[wasm_bindgen]
+pub fn mechtron_update( phase_buffer_id: i32, content_buffer_id: i32 ) -> i32
+{
+    let phase = match consume_string(phase_buffer_id)
+    {
+        Ok(phase) => phase,
+        Err(e) => {
+            report_error(GuestErrorKind::Error, format!("mechtron_update: could not read phase: {}", e), Option::None);
+            return 1;
+        }
+    };
 
-        // at this time we don't do anything
-        // later we will pull a zip file from a public repository and
-        // extract the files to 'repo_path'
-        return Ok(());
-    }
+    invoke( "mechtron_update", content_buffer_id, |ctx, factories, content| {
+        unsafe { mechtron_tron_update(ctx, content, phase.as_str()) }
+    })
 }
 
-impl<'a> ArtifactCache for MechtronArtifactCache
+#[wasm_bindgen]
+pub fn mechtron_port( port_buffer_id: i32, content_buffer_id: i32, messages_buffer_id: i32 ) -> i32
 {
-    fn cache(&self, artifact: &Artifact) -> Result<(), Box<dyn Error + '_>>
+    let port = match consume_string(port_buffer_id)
     {
-        let cache = self.cache.lock();
-        let cell = &*cache.unwrap();
-        let cache = cell.borrow();
-        if cache.contains_key(artifact)
-        {
-            return Ok(());
+        Ok(port) => port,
+        Err(e) => {
+            report_error(GuestErrorKind::Error, format!("mechtron_port: could not read port: {}", e), Option::None);
+            return 1;
         }
-        let mut cache = cell.borrow_mut();
-        let string = String::from_utf8(self.load(artifact)?)?;
-        cache.insert(artifact.clone(), Arc::new(string));
-        return Ok(());
-    }
+    };
+
+    invoke( "mechtron_port", content_buffer_id, |ctx, factories, content| {
+        let messages = open_messages(factories, messages_buffer_id)?;
+        unsafe { mechtron_tron_port(ctx, content, port.as_str(), &messages) }
+    })
+}
 
-    fn load(&self, artifact: &Artifact) -> Result<Vec<u8>, Box<dyn Error + '_>>
-    {
-        host
-        return Ok(data);
-    }
 
-    fn get(&self, artifact: &Artifact) -> Result<Arc<String>, Box<dyn Error + '_>>
+pub struct MechtronContext
+{
+    pub mechtron_config: Option<MechtronConfig>,
+    pub tron_config: Option<TronConfig>
+}
+
+impl MechtronContext
+{
+    fn new() -> Self
     {
-        let cache = self.cache.lock();
-        let cache = cache.unwrap();
-        let cache = cache.deref();
-        let cache = cache.borrow();
-        let option = cache.get(artifact);
-
-        match option {
-            None => Err(format!("artifact is not cached: {}", artifact.to()).into()),
-            Some(rtn) => Ok(rtn.clone())
-        }
+        return MechtronContext { mechtron_config: Option::None, tron_config: Option::None };
     }
-}
\ No newline at end of file
+}
@@ -1,25 +1,40 @@
 mod utils;
 
+use std::error::Error;
+
 use mechtron_wasm::*;
+use mechtron_common::message::{Message, MessageBuilder};
 use no_proto::buffer::NP_Buffer;
-use no_proto::buffer_ro::NP_Buffer_RO;
+use no_proto::memory::NP_Memory_Owned;
 
 #[no_mangle]
-pub extern "C" fn mechtron_create(ctx: &MechtronContext, create_message: &NP_Buffer_RO, content: &mut NP_Buffer ) -> Result<(),Box<std::error::Error>>
+pub extern "C" fn mechtron_tron_create(ctx: &MechtronContext, content: &mut NP_Buffer<NP_Memory_Owned>, create: &Message ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
 {
-   log("actor_create() called! XX");
-   let name = create_message.get::<String>(&[&"name"]);
+   log("mechtron_tron_create() called!");
+   let constructor = &create.payloads[1].buffer;
+   let name = constructor.get::<String>(&[&"name"]);
    log( format!("name result {:?}",name).as_str());
    let name = name.unwrap().unwrap();
    content.set(&[&"name"], name.clone());
-   let age = create_message.get::<i32>(&[&"age"]).unwrap().unwrap();
+   let age = constructor.get::<i32>(&[&"age"]).unwrap().unwrap();
 
    log( format!("name is: {}",name.as_str()).as_str() );
 
    content.set( &[&"age"], age+1 );
 
-   return Ok(());
+   return Ok(Option::None);
 }
 
+#[no_mangle]
+pub extern "C" fn mechtron_tron_update(ctx: &MechtronContext, content: &mut NP_Buffer<NP_Memory_Owned>, phase: &str ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+{
+   log( format!("mechtron_tron_update() called for phase {}",phase).as_str() );
+   return Ok(Option::None);
+}
 
-
+#[no_mangle]
+pub extern "C" fn mechtron_tron_port(ctx: &MechtronContext, content: &mut NP_Buffer<NP_Memory_Owned>, port: &str, messages: &Vec<Message> ) -> Result<Option<Vec<MessageBuilder>>,Box<dyn Error>>
+{
+   log( format!("mechtron_tron_port() received {} messages on port {}",messages.len(),port).as_str() );
+   return Ok(Option::None);
+}